@@ -0,0 +1,32 @@
+use yugabyte::engine::alert::post_alert;
+use yugabyte::errors::Error;
+use yugabyte::model::alert::Alert;
+
+use crate::broadcast::{EventBroadcaster, ALERTS_TOPIC};
+use crate::config::{AlertConfig, AlertSink};
+
+// Send the alert to the log and the live stream when they are configured sinks, a failing sink does not
+// keep it from the other. The webhook is posted apart, see `post_alert_webhook`.
+pub fn send_alert(alert: &Alert, alert_config: &AlertConfig, broadcaster: &EventBroadcaster) {
+    for sink in &alert_config.sinks {
+        match sink {
+            AlertSink::Log => {
+                tracing::warn!(kind = %alert.kind, job_id = %alert.job_id, "{}", alert.message)
+            }
+            AlertSink::Webhook => {}
+            AlertSink::Sse => match serde_json::to_string(alert) {
+                Ok(data) => broadcaster.broadcast(ALERTS_TOPIC, &alert.kind, &data),
+                Err(e) => tracing::error!(job_id = %alert.job_id, "the alert cannot be serialized: {}", e),
+            },
+        }
+    }
+}
+
+// Post the alert to the webhook of the operators when it is a configured sink, the error is returned
+// so that the relay posts the alert again.
+pub fn post_alert_webhook(alert: &Alert, alert_config: &AlertConfig) -> Result<(), Error> {
+    match &alert_config.webhook_url {
+        Some(webhook_url) if alert_config.sinks.contains(&AlertSink::Webhook) => post_alert(webhook_url, alert),
+        _ => Ok(()),
+    }
+}
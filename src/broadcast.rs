@@ -0,0 +1,38 @@
+use std::sync::Mutex;
+
+use actix_web::web::Bytes;
+use futures_channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+
+// The stream of the alerts, GET /api/v1/alerts.
+pub const ALERTS_TOPIC: &str = "alerts";
+// The stream of the domain events of the jobs, GET /api/v1/events.
+pub const EVENTS_TOPIC: &str = "events";
+// The stream of the row changes of the jobs, GET /api/v1/jobs/changes.
+pub const CHANGES_TOPIC: &str = "changes";
+
+// The clients listening to a topic as server-sent events, a client that went away is dropped
+// the next time something is sent to its topic.
+#[derive(Default)]
+pub struct EventBroadcaster {
+    clients: Mutex<Vec<(&'static str, UnboundedSender<Bytes>)>>,
+}
+
+impl EventBroadcaster {
+    pub fn subscribe(&self, topic: &'static str) -> UnboundedReceiver<Bytes> {
+        let (sender, receiver) = unbounded();
+        let _ = sender.unbounded_send(Bytes::from_static(b": connected\n\n"));
+        if let Ok(mut clients) = self.clients.lock() {
+            clients.push((topic, sender));
+        }
+        receiver
+    }
+
+    pub fn broadcast(&self, topic: &str, event: &str, data: &str) {
+        let message = Bytes::from(format!("event: {}\ndata: {}\n\n", event, data));
+        if let Ok(mut clients) = self.clients.lock() {
+            clients.retain(|(client_topic, client)| {
+                *client_topic != topic || client.unbounded_send(message.clone()).is_ok()
+            });
+        }
+    }
+}
@@ -1,6 +1,13 @@
+use std::env;
+use std::net::IpAddr;
+use std::time::Duration;
+
 use dotenv::dotenv;
 use tracing_subscriber::EnvFilter;
 
+use yugabyte::model::email::{EmailTemplate, EmailTemplates, SmtpSettings, SmtpTls};
+use yugabyte::model::retention::RetentionPolicy;
+
 // Initiate the tracing subscriber for RUST_LOG
 pub fn start_tracing() {
     dotenv().ok();
@@ -8,4 +15,430 @@ pub fn start_tracing() {
     tracing_subscriber::fmt()
         .with_env_filter(EnvFilter::from_default_env())
         .init();
-}
\ No newline at end of file
+}
+
+// How links to the downloaded files are signed.
+pub struct FileConfig {
+    pub signing_key: Option<String>,
+    pub public_url: String,
+}
+
+impl Default for FileConfig {
+    fn default() -> Self {
+        let public_url = env::var("PUBLIC_URL").unwrap_or_else(|_| {
+            format!("http://{}:{}", env::var("HOST").unwrap(), env::var("PORT").unwrap())
+        });
+
+        FileConfig {
+            signing_key: env::var("DOWNLOAD_SIGNING_KEY").ok(),
+            public_url: public_url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+// How often the files of the completed jobs are checked, the task is off without an interval.
+pub struct ReconcileConfig {
+    pub interval: Option<Duration>,
+    pub requeue: bool,
+}
+
+impl Default for ReconcileConfig {
+    fn default() -> Self {
+        ReconcileConfig {
+            interval: env::var("RECONCILE_INTERVAL_SECONDS")
+                .ok()
+                .and_then(|seconds| seconds.parse().ok())
+                .map(Duration::from_secs),
+            requeue: env::var("RECONCILE_REQUEUE").map(|requeue| requeue == "true").unwrap_or(false),
+        }
+    }
+}
+
+// The largest page of a list and how far into a list an offset may go.
+pub struct PaginationConfig {
+    pub max_page_size: i64,
+    pub max_offset: i64,
+}
+
+impl Default for PaginationConfig {
+    fn default() -> Self {
+        PaginationConfig {
+            max_page_size: env::var("MAX_PAGE_SIZE")
+                .ok()
+                .and_then(|size| size.parse().ok())
+                .unwrap_or(100),
+            max_offset: env::var("MAX_PAGE_OFFSET")
+                .ok()
+                .and_then(|offset| offset.parse().ok())
+                .unwrap_or(10_000),
+        }
+    }
+}
+
+// How long a request made with an Idempotency-Key is remembered.
+pub struct IdempotencyConfig {
+    pub window_seconds: i64,
+}
+
+impl Default for IdempotencyConfig {
+    fn default() -> Self {
+        IdempotencyConfig {
+            window_seconds: env::var("IDEMPOTENCY_WINDOW_SECONDS")
+                .ok()
+                .and_then(|seconds| seconds.parse().ok())
+                .unwrap_or(86_400),
+        }
+    }
+}
+
+// How long a deleted job stays in the trash, `TRASH_RETENTION_DAYS=never` keeps it until it is purged by hand.
+#[derive(Clone)]
+pub struct TrashConfig {
+    pub retention_days: Option<i64>,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        let retention_days = env::var("TRASH_RETENTION_DAYS").ok();
+        TrashConfig {
+            retention_days: match retention_days.as_deref() {
+                Some("never") => None,
+                Some(days) => days.parse().ok().or(Some(30)),
+                None => Some(30),
+            },
+        }
+    }
+}
+
+// Who made a request. The proxy in front of the service authenticates it and sends the principal in
+// `PRINCIPAL_HEADER` and the client address in `X-Forwarded-For`, both are only taken from the comma-separated
+// `TRUSTED_PROXIES`. The `ADMIN_PRINCIPALS` may use the admin routes.
+pub struct PrincipalConfig {
+    pub header: String,
+    pub trusted_proxies: Vec<IpAddr>,
+    pub admins: Vec<String>,
+}
+
+fn env_list(name: &str) -> Vec<String> {
+    env::var(name)
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+impl Default for PrincipalConfig {
+    fn default() -> Self {
+        PrincipalConfig {
+            header: env::var("PRINCIPAL_HEADER").unwrap_or_else(|_| "X-Forwarded-User".to_string()),
+            trusted_proxies: env_list("TRUSTED_PROXIES")
+                .iter()
+                .filter_map(|proxy| match proxy.parse() {
+                    Ok(proxy_ip) => Some(proxy_ip),
+                    Err(_) => {
+                        tracing::warn!("the trusted proxy {:?} is not an IP address, it is left out", proxy);
+                        None
+                    }
+                })
+                .collect(),
+            admins: env_list("ADMIN_PRINCIPALS"),
+        }
+    }
+}
+
+// How long the audit log is kept, `AUDIT_RETENTION_DAYS=never` keeps it for ever.
+#[derive(Clone)]
+pub struct AuditConfig {
+    pub retention_days: Option<i64>,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        let retention_days = env::var("AUDIT_RETENTION_DAYS").ok();
+        AuditConfig {
+            retention_days: match retention_days.as_deref() {
+                Some("never") => None,
+                Some(days) => days.parse().ok().or(Some(365)),
+                None => Some(365),
+            },
+        }
+    }
+}
+
+// How the old progress samples are downsampled, to one per step once they are older than the period.
+#[derive(Clone)]
+pub struct ProgressConfig {
+    pub downsample_after_hours: i64,
+    pub downsample_step_seconds: i64,
+}
+
+impl Default for ProgressConfig {
+    fn default() -> Self {
+        ProgressConfig {
+            downsample_after_hours: env::var("PROGRESS_DOWNSAMPLE_AFTER_HOURS")
+                .ok()
+                .and_then(|hours| hours.parse().ok())
+                .unwrap_or(24),
+            downsample_step_seconds: env::var("PROGRESS_DOWNSAMPLE_STEP_SECONDS")
+                .ok()
+                .and_then(|seconds| seconds.parse().ok())
+                .filter(|seconds| *seconds > 0)
+                .unwrap_or(60),
+        }
+    }
+}
+
+// The watchdog of the downloads with no progress, `STALL_AFTER_SECONDS=never` turns it off.
+// A stalled download is reconnected to the next of `DOWNLOAD_MIRRORS` until it used `STALL_MAX_ATTEMPTS`.
+#[derive(Clone)]
+pub struct StallConfig {
+    pub stall_after_seconds: Option<i64>,
+    pub max_attempts: i32,
+    pub mirrors: Vec<String>,
+}
+
+impl Default for StallConfig {
+    fn default() -> Self {
+        let stall_after_seconds = env::var("STALL_AFTER_SECONDS").ok();
+        StallConfig {
+            stall_after_seconds: match stall_after_seconds.as_deref() {
+                Some("never") => None,
+                Some(seconds) => seconds.parse().ok().filter(|seconds| *seconds > 0).or(Some(900)),
+                None => Some(900),
+            },
+            max_attempts: env::var("STALL_MAX_ATTEMPTS")
+                .ok()
+                .and_then(|attempts| attempts.parse().ok())
+                .unwrap_or(3),
+            mirrors: env::var("DOWNLOAD_MIRRORS")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|mirror| !mirror.is_empty())
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AlertSink {
+    Log,
+    Webhook,
+    Sse,
+}
+
+// Where the alerts go, `ALERT_SINKS` lists `log`, `webhook` and `sse`, the webhook needs `ALERT_WEBHOOK_URL`.
+#[derive(Clone)]
+pub struct AlertConfig {
+    pub sinks: Vec<AlertSink>,
+    pub webhook_url: Option<String>,
+}
+
+impl Default for AlertConfig {
+    fn default() -> Self {
+        let webhook_url = env::var("ALERT_WEBHOOK_URL").ok().filter(|url| !url.is_empty());
+        let sinks = env::var("ALERT_SINKS")
+            .unwrap_or_else(|_| "log,sse".to_string())
+            .split(',')
+            .filter_map(|sink| match sink.trim() {
+                "log" => Some(AlertSink::Log),
+                "webhook" if webhook_url.is_some() => Some(AlertSink::Webhook),
+                "sse" => Some(AlertSink::Sse),
+                other => {
+                    tracing::warn!("the alert sink {:?} is unknown or not configured, it is left out", other);
+                    None
+                }
+            })
+            .collect();
+        AlertConfig { sinks, webhook_url }
+    }
+}
+
+// How the webhooks are posted: every `WEBHOOK_POLL_SECONDS`, a delivery is given up after `WEBHOOK_MAX_ATTEMPTS`.
+#[derive(Clone)]
+pub struct WebhookConfig {
+    pub poll_seconds: u64,
+    pub max_attempts: i32,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            poll_seconds: env::var("WEBHOOK_POLL_SECONDS")
+                .ok()
+                .and_then(|seconds| seconds.parse().ok())
+                .filter(|seconds| *seconds > 0)
+                .unwrap_or(5),
+            max_attempts: env::var("WEBHOOK_MAX_ATTEMPTS")
+                .ok()
+                .and_then(|attempts| attempts.parse().ok())
+                .unwrap_or(8),
+        }
+    }
+}
+
+// How the outbox is relayed to its sinks: every `OUTBOX_POLL_SECONDS`, an event a sink fails on is given up
+// after `OUTBOX_MAX_ATTEMPTS`, the events are kept `OUTBOX_RETENTION_DAYS` or forever with `never`.
+#[derive(Clone)]
+pub struct OutboxConfig {
+    pub poll_seconds: u64,
+    pub max_attempts: i32,
+    pub retention_days: Option<i64>,
+}
+
+impl Default for OutboxConfig {
+    fn default() -> Self {
+        let retention_days = env::var("OUTBOX_RETENTION_DAYS").ok();
+        OutboxConfig {
+            poll_seconds: env::var("OUTBOX_POLL_SECONDS")
+                .ok()
+                .and_then(|seconds| seconds.parse().ok())
+                .filter(|seconds| *seconds > 0)
+                .unwrap_or(1),
+            max_attempts: env::var("OUTBOX_MAX_ATTEMPTS")
+                .ok()
+                .and_then(|attempts| attempts.parse().ok())
+                .unwrap_or(8),
+            retention_days: match retention_days.as_deref() {
+                Some("never") => None,
+                Some(days) => days.parse().ok().or(Some(7)),
+                None => Some(7),
+            },
+        }
+    }
+}
+
+// The change feed LISTENs to the notifications of the database to stream the changes made on any server,
+// `CHANGE_FEED=off` turns it off for the databases without NOTIFY, the events are then streamed by the relay.
+#[derive(Clone)]
+pub struct ChangeFeedConfig {
+    pub database_url: Option<String>,
+}
+
+impl Default for ChangeFeedConfig {
+    fn default() -> Self {
+        ChangeFeedConfig {
+            database_url: match env::var("CHANGE_FEED").as_deref() {
+                Ok("off") => None,
+                _ => env::var("DATABASE_URL").ok(),
+            },
+        }
+    }
+}
+
+const COMPLETED_SUBJECT: &str = "Your download {{name}} is complete";
+const COMPLETED_BODY: &str = "Hello,\n\nThe download {{name}} is complete, {{downloaded_size}} bytes were downloaded.\n\
+File: {{file_name}}\nFinished at: {{finished_at}} UTC\n\nJob {{id}}\n";
+const FAILED_SUBJECT: &str = "Your download {{name}} failed";
+const FAILED_BODY: &str = "Hello,\n\nThe download {{name}} failed at {{percent_downloaded}}% after {{attempts}} attempts.\n\
+Reason: {{last_error}}\n\nJob {{id}}\n";
+const DIGEST_SUBJECT: &str = "Your downloads on {{date}}: {{count}} finished";
+
+// A template from the environment, `\n` stands for a new line.
+fn env_template(name: &str, default: &str) -> String {
+    env::var(name)
+        .ok()
+        .filter(|template| !template.is_empty())
+        .map(|template| template.replace("\\n", "\n"))
+        .unwrap_or_else(|| default.to_string())
+}
+
+// The email notifications, sent through `SMTP_HOST` and off without it. `SMTP_TLS` is `starttls` (default),
+// `tls` or `none` for a local relay or sink. The `EMAIL_COMPLETED_*` and `EMAIL_FAILED_*` templates take
+// the fields of the job, the digest ones are sent every day at `EMAIL_DIGEST_HOUR` UTC.
+#[derive(Clone)]
+pub struct EmailConfig {
+    pub smtp: Option<SmtpSettings>,
+    pub templates: EmailTemplates,
+    pub digest_subject: String,
+    pub digest_hour: u32,
+    pub poll_seconds: u64,
+    pub max_attempts: i32,
+    pub retention_days: Option<i64>,
+}
+
+impl Default for EmailConfig {
+    fn default() -> Self {
+        let tls = match env::var("SMTP_TLS").as_deref() {
+            Ok("none") => SmtpTls::None,
+            Ok("tls") => SmtpTls::Tls,
+            _ => SmtpTls::StartTls,
+        };
+        let smtp = env::var("SMTP_HOST").ok().filter(|host| !host.is_empty()).map(|host| SmtpSettings {
+            host,
+            port: env::var("SMTP_PORT").ok().and_then(|port| port.parse().ok()).unwrap_or(match tls {
+                SmtpTls::None => 25,
+                SmtpTls::StartTls => 587,
+                SmtpTls::Tls => 465,
+            }),
+            tls,
+            username: env::var("SMTP_USERNAME").ok().filter(|username| !username.is_empty()),
+            password: env::var("SMTP_PASSWORD").ok(),
+            from: env::var("EMAIL_FROM").unwrap_or_else(|_| "downloads@localhost".to_string()),
+        });
+        let retention_days = env::var("EMAIL_RETENTION_DAYS").ok();
+        EmailConfig {
+            smtp,
+            templates: EmailTemplates {
+                completed: EmailTemplate {
+                    subject: env_template("EMAIL_COMPLETED_SUBJECT", COMPLETED_SUBJECT),
+                    body: env_template("EMAIL_COMPLETED_BODY", COMPLETED_BODY),
+                },
+                failed: EmailTemplate {
+                    subject: env_template("EMAIL_FAILED_SUBJECT", FAILED_SUBJECT),
+                    body: env_template("EMAIL_FAILED_BODY", FAILED_BODY),
+                },
+            },
+            digest_subject: env_template("EMAIL_DIGEST_SUBJECT", DIGEST_SUBJECT),
+            digest_hour: env::var("EMAIL_DIGEST_HOUR")
+                .ok()
+                .and_then(|hour| hour.parse().ok())
+                .filter(|hour| *hour < 24)
+                .unwrap_or(8),
+            poll_seconds: env::var("EMAIL_POLL_SECONDS")
+                .ok()
+                .and_then(|seconds| seconds.parse().ok())
+                .filter(|seconds| *seconds > 0)
+                .unwrap_or(10),
+            max_attempts: env::var("EMAIL_MAX_ATTEMPTS")
+                .ok()
+                .and_then(|attempts| attempts.parse().ok())
+                .unwrap_or(5),
+            retention_days: match retention_days.as_deref() {
+                Some("never") => None,
+                Some(days) => days.parse().ok().or(Some(30)),
+                None => Some(30),
+            },
+        }
+    }
+}
+
+fn env_days(name: &str) -> Option<i64> {
+    env::var(name).ok().and_then(|days| days.parse().ok())
+}
+
+// The retention rules and how often they are applied, the task is off without an interval.
+#[derive(Clone)]
+pub struct RetentionConfig {
+    pub interval: Option<Duration>,
+    pub policy: RetentionPolicy,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        RetentionConfig {
+            interval: env::var("RETENTION_INTERVAL_SECONDS")
+                .ok()
+                .and_then(|seconds| seconds.parse().ok())
+                .map(Duration::from_secs),
+            policy: RetentionPolicy {
+                completed_files_days: env_days("RETENTION_COMPLETED_FILES_DAYS"),
+                failed_jobs_days: env_days("RETENTION_FAILED_JOBS_DAYS"),
+                keep_last_per_name: env_days("RETENTION_KEEP_LAST_PER_NAME"),
+            },
+        }
+    }
+}
@@ -0,0 +1,16 @@
+use actix_web::HttpResponse;
+use futures_util::StreamExt;
+use paperclip::actix::{api_v2_operation, web};
+
+use yugabyte::errors::Errors;
+
+use crate::broadcast::{EventBroadcaster, ALERTS_TOPIC};
+
+// Stream the alerts as server-sent events, each one named after its kind.
+#[api_v2_operation]
+pub(crate) fn stream_alerts(broadcaster: web::Data<EventBroadcaster>) -> Result<HttpResponse, Errors> {
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .streaming(broadcaster.subscribe(ALERTS_TOPIC).map(Ok::<_, actix_web::Error>)))
+}
@@ -0,0 +1,172 @@
+use std::future::Future;
+use std::slice;
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::{HeaderName, HeaderValue, Method, StatusCode};
+use actix_web::web::Data;
+use actix_web::{HttpMessage, HttpRequest};
+use paperclip::actix::{api_v2_operation, web::{self, Query}};
+use paperclip::actix::web::Json;
+use uuid::Uuid;
+
+use yugabyte::db_connection::{CoreDBPool, pgdata_to_pgconnection};
+use yugabyte::engine::audit::{get_audit_logs, record_audit_logs};
+use yugabyte::errors::Errors;
+use yugabyte::errors::StateCode::DBError;
+use yugabyte::model::audit::{AuditFilterDTO, AuditLog, AuditedCall, NewAuditLog, AUDIT_FAILURE, AUDIT_SUCCESS};
+use yugabyte::model::general::{PaginatedResponseDTO, PaginationDTO};
+
+use crate::config::PaginationConfig;
+use crate::handler::{client_ip, request_actor, JOBS_PATH};
+
+pub const REQUEST_ID: &str = "x-request-id";
+
+// The id given to the request, kept with it for its audit log.
+struct RequestId(String);
+
+// Set once a call wrote its audit log in the transaction of its change.
+struct AuditRecorded;
+
+// What a call did, from its route.
+fn audit_action(method: &Method, pattern: &str, path: &str) -> String {
+    let route = pattern.strip_prefix(JOBS_PATH).unwrap_or(pattern);
+    let action = match (method.as_str(), route) {
+        ("POST", "") | ("POST", "/feature/add") => "create",
+        ("PUT", "/by-ref/{external_ref}") => "upsert",
+        ("PUT", "/{job_id}") | ("PATCH", "/{job_id}") | ("PUT", "/feature/update") => "update",
+        ("DELETE", "/{job_id}") | ("DELETE", "/feature/remove/{feature_id}") => "delete",
+        ("PUT", "/feature/{feature_id}/activate/{is_active}") => "activate",
+        ("POST", "/bulk") => "bulk_create",
+        ("POST", "/bulk/{action}") => {
+            return format!("bulk_{}", path.rsplit('/').next().unwrap_or_default())
+        }
+        ("POST", "/trash/{job_id}/restore") => "restore",
+        ("DELETE", "/trash/{job_id}") => "purge",
+        ("POST", "/{job_id}/progress") => "report_progress",
+        _ => return method.as_str().to_lowercase(),
+    };
+    action.to_string()
+}
+
+// The entry of the call for the audit log, answered with `status_code`.
+fn call_audit_log(req: &HttpRequest, status_code: StatusCode) -> NewAuditLog {
+    let request_id = req
+        .extensions()
+        .get::<RequestId>()
+        .map(|request_id| request_id.0.clone())
+        .unwrap_or_default();
+    let path = req.path().to_string();
+    let pattern = req.match_pattern().unwrap_or_else(|| path.clone());
+    NewAuditLog {
+        request_id,
+        principal: request_actor(req),
+        source_ip: client_ip(req).map(|ip| ip.to_string()),
+        method: req.method().to_string(),
+        action: audit_action(req.method(), &pattern, &path),
+        path,
+        job_id: None,
+        before: None,
+        after: None,
+        status_code: status_code.as_u16() as i32,
+        result: if status_code.is_success() { AUDIT_SUCCESS } else { AUDIT_FAILURE }.to_string(),
+    }
+}
+
+// The audit log of a call changing the jobs of `job_ids`, written with the change when it is answered with
+// `status_code`. A failed write rolls the change back.
+pub(crate) fn audited_call(req: &HttpRequest, status_code: StatusCode, job_ids: Vec<Uuid>) -> AuditedCall {
+    req.extensions_mut().insert(AuditRecorded);
+    AuditedCall {
+        audit_log: call_audit_log(req, status_code),
+        job_ids,
+    }
+}
+
+// Log a mutating call that did not write its own audit log once it is answered: a failed call, whose change
+// was rolled back, or a call changing no job.
+fn record_audit<B>(response: &ServiceResponse<B>) {
+    let req = response.request();
+    if response.status().is_success() && req.extensions().get::<AuditRecorded>().is_some() {
+        return;
+    }
+    let new_audit_log = call_audit_log(req, response.status());
+
+    let pool = match req.app_data::<Data<CoreDBPool>>() {
+        Some(pool) => pool.clone(),
+        None => return,
+    };
+    let connection = pgdata_to_pgconnection(pool);
+    if let Err(e) = record_audit_logs(slice::from_ref(&new_audit_log), &connection) {
+        tracing::error!(request_id = new_audit_log.request_id.as_str(), "writing the audit log failed: {}", e);
+    }
+}
+
+// Give every request an id, answered in `X-Request-Id`, and log the calls that change something.
+pub fn audit_request<S, B>(
+    req: ServiceRequest,
+    service: &mut S,
+) -> impl Future<Output = Result<ServiceResponse<B>, actix_web::Error>>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+{
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty() && value.len() <= 255)
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    let audited = !matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    req.extensions_mut().insert(RequestId(request_id.clone()));
+
+    let response = service.call(req);
+    async move {
+        let mut response = response.await?;
+        if audited {
+            record_audit(&response);
+        }
+        if let Ok(value) = HeaderValue::from_str(&request_id) {
+            response.headers_mut().insert(HeaderName::from_static(REQUEST_ID), value);
+        }
+        Ok(response)
+    }
+}
+
+#[api_v2_operation]
+pub(crate) fn list_audit_logs(
+    Query(pagination_dto): Query<PaginationDTO>,
+    Query(filter): Query<AuditFilterDTO>,
+    pool: web::Data<CoreDBPool>,
+    pagination_config: web::Data<PaginationConfig>,
+) -> Result<Json<PaginatedResponseDTO<AuditLog>>, Errors> {
+    // Step 1: the pagination must be valid.
+    pagination_dto
+        .check(pagination_config.max_page_size, pagination_config.max_offset)
+        .map_err(Errors::BadReq)?;
+
+    // Step 2: get the connection from pool data
+    let connection = pgdata_to_pgconnection(pool);
+
+    // Step 3: fire the page of the audit log, the newest first.
+    get_audit_logs(&pagination_dto, &filter, &connection)
+        .map(Json)
+        .map_err(|_| Errors::InternalServerError(DBError.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn audit_actions_follow_the_routes() {
+        assert_eq!(audit_action(&Method::POST, "/api/v1/jobs", "/api/v1/jobs"), "create");
+        assert_eq!(audit_action(&Method::POST, "/feature/add", "/feature/add"), "create");
+        assert_eq!(audit_action(&Method::PATCH, "/api/v1/jobs/{job_id}", "/api/v1/jobs/1"), "update");
+        assert_eq!(audit_action(&Method::DELETE, "/api/v1/jobs/trash/{job_id}", "/api/v1/jobs/trash/1"), "purge");
+        assert_eq!(
+            audit_action(&Method::POST, "/api/v1/jobs/bulk/{action}", "/api/v1/jobs/bulk/deactivate"),
+            "bulk_deactivate"
+        );
+        assert_eq!(audit_action(&Method::POST, "/unknown", "/unknown"), "post");
+    }
+}
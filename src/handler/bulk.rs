@@ -0,0 +1,98 @@
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse};
+use paperclip::actix::{api_v2_operation, web};
+use uuid::Uuid;
+
+use yugabyte::db_connection::{CoreDBPool, pgdata_to_pgconnection};
+use yugabyte::engine::audit::audited_change;
+use yugabyte::engine::bulk::{bulk_create_jobs, bulk_update_jobs, select_bulk_jobs};
+use yugabyte::errors::{Error, Errors};
+use yugabyte::errors::StateCode::DBError;
+use yugabyte::model::bulk::{BulkAction, BulkCreateDTO, BulkResultDTO, BulkSelectionDTO};
+use yugabyte::storage::StorageConfig;
+
+use crate::config::IdempotencyConfig;
+use crate::handler::audit::audited_call;
+use crate::handler::idempotency::{idempotent, json_response};
+use crate::handler::request_actor;
+
+// The largest body of a bulk request.
+pub const BULK_BODY_LIMIT: usize = 1 << 20;
+
+// Every item succeeded, or 207 with the result of each item.
+fn bulk_status(bulk_result: &BulkResultDTO) -> StatusCode {
+    if bulk_result.failed == 0 {
+        StatusCode::OK
+    } else {
+        StatusCode::MULTI_STATUS
+    }
+}
+
+fn bulk_response(bulk_result: BulkResultDTO) -> HttpResponse {
+    HttpResponse::build(bulk_status(&bulk_result)).json(bulk_result)
+}
+
+// The jobs the batch changed, none when it was rolled back.
+fn succeeded_ids(bulk_result: &BulkResultDTO) -> Vec<Uuid> {
+    bulk_result
+        .results
+        .iter()
+        .filter(|result| result.success)
+        .filter_map(|result| result.id)
+        .collect()
+}
+
+fn bulk_error(e: Error) -> Errors {
+    match e {
+        Error::ValidationError(errors) => Errors::BadReq(errors),
+        Error::Conflict(state_code) => Errors::Conflict(state_code.into()),
+        _ => Errors::InternalServerError(DBError.into()),
+    }
+}
+
+#[api_v2_operation]
+pub(crate) fn create_jobs_in_bulk(
+    req: HttpRequest,
+    bulk_create_dto: web::Json<BulkCreateDTO>,
+    pool: web::Data<CoreDBPool>,
+    storage_config: web::Data<StorageConfig>,
+    idempotency_config: web::Data<IdempotencyConfig>,
+) -> Result<HttpResponse, Errors> {
+    // Step 1: get the connection from pool data
+    let connection = pgdata_to_pgconnection(pool);
+
+    // Step 2: create the jobs, then fire the result of each one.
+    idempotent(&req, &*bulk_create_dto, &idempotency_config, &connection, bulk_error, || {
+        let bulk_result = audited_change(
+            &[],
+            &connection,
+            || bulk_create_jobs(&bulk_create_dto, &storage_config, &request_actor(&req), &connection),
+            |bulk_result| audited_call(&req, bulk_status(bulk_result), succeeded_ids(bulk_result)),
+        )?;
+        json_response(bulk_status(&bulk_result), Vec::new(), &bulk_result)
+    })
+}
+
+#[api_v2_operation]
+pub(crate) fn apply_bulk_action(
+    req: HttpRequest,
+    web::Path(action): web::Path<BulkAction>,
+    bulk_selection_dto: web::Json<BulkSelectionDTO>,
+    pool: web::Data<CoreDBPool>,
+) -> Result<HttpResponse, Errors> {
+    // Step 1: get the connection from pool data
+    let connection = pgdata_to_pgconnection(pool);
+
+    // Step 2: find the jobs by their ids or by the filter.
+    let ids = select_bulk_jobs(&bulk_selection_dto, &connection).map_err(bulk_error)?;
+
+    // Step 3: apply the action, then fire the result of each job.
+    let bulk_result = audited_change(
+        &ids,
+        &connection,
+        || bulk_update_jobs(action, &ids, bulk_selection_dto.mode, &request_actor(&req), &connection),
+        |bulk_result| audited_call(&req, bulk_status(bulk_result), succeeded_ids(bulk_result)),
+    )
+    .map_err(bulk_error)?;
+    Ok(bulk_response(bulk_result))
+}
@@ -0,0 +1,56 @@
+use actix_web::HttpResponse;
+use futures_util::StreamExt;
+use paperclip::actix::{api_v2_operation, web::{self, Query}};
+use paperclip::actix::web::Json;
+use uuid::Uuid;
+
+use yugabyte::db_connection::{CoreDBPool, pgdata_to_pgconnection};
+use yugabyte::engine::event::get_job_events;
+use yugabyte::errors::{Error, Errors};
+use yugabyte::errors::StateCode::{DBError, NotFound};
+use yugabyte::model::event::JobEvent;
+use yugabyte::model::general::{PaginatedResponseDTO, PaginationDTO};
+
+use crate::broadcast::{EventBroadcaster, CHANGES_TOPIC, EVENTS_TOPIC};
+use crate::config::PaginationConfig;
+
+#[api_v2_operation]
+pub(crate) fn list_job_events(
+    web::Path(job_id): web::Path<Uuid>,
+    Query(pagination_dto): Query<PaginationDTO>,
+    pool: web::Data<CoreDBPool>,
+    pagination_config: web::Data<PaginationConfig>,
+) -> Result<Json<PaginatedResponseDTO<JobEvent>>, Errors> {
+    // Step 1: the pagination must be valid.
+    pagination_dto
+        .check(pagination_config.max_page_size, pagination_config.max_offset)
+        .map_err(Errors::BadReq)?;
+
+    // Step 2: get the connection from pool data
+    let connection = pgdata_to_pgconnection(pool);
+
+    // Step 3: fire the page of the history of the job, oldest first.
+    match get_job_events(&job_id, &pagination_dto, &connection) {
+        Ok(page) => Ok(Json(page)),
+        Err(Error::DBError(diesel::result::Error::NotFound)) => Err(Errors::NotFound(NotFound.into())),
+        Err(_) => Err(Errors::InternalServerError(DBError.into())),
+    }
+}
+
+// Stream the domain events of the jobs as server-sent events, each one named after the event, `JobCompleted` say.
+#[api_v2_operation]
+pub(crate) fn stream_events(broadcaster: web::Data<EventBroadcaster>) -> Result<HttpResponse, Errors> {
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .streaming(broadcaster.subscribe(EVENTS_TOPIC).map(Ok::<_, actix_web::Error>)))
+}
+
+// Stream the row changes of the jobs as server-sent events named after the change: `insert`, `update` or `delete`.
+#[api_v2_operation]
+pub(crate) fn stream_job_changes(broadcaster: web::Data<EventBroadcaster>) -> Result<HttpResponse, Errors> {
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .streaming(broadcaster.subscribe(CHANGES_TOPIC).map(Ok::<_, actix_web::Error>)))
+}
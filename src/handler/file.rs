@@ -0,0 +1,308 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use actix_web::http::header::{
+    ContentDisposition, DispositionParam, DispositionType, HttpDate, ACCEPT_RANGES,
+    CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, LOCATION, RANGE,
+};
+use actix_web::web::Bytes;
+use actix_web::{HttpRequest, HttpResponse};
+use chrono::{Duration, Utc};
+use futures_util::stream::{self, Stream};
+use paperclip::actix::{
+    api_v2_operation,
+    web::{self, Query},
+};
+use paperclip::actix::web::Json;
+use uuid::Uuid;
+
+use yugabyte::db_connection::{CoreDBPool, pgdata_to_pgconnection, PgPooledConnection};
+use yugabyte::engine::file::{find_job_file, JobFile};
+use yugabyte::errors::{Error, Errors};
+use yugabyte::errors::StateCode::{
+    FileNotFound, FileNotReady, InternalServerError, InvalidSignature, LinkExpired, NotFound,
+    SignedLinksDisabled,
+};
+use yugabyte::model::file::{FileLinkDTO, SignedFileDTO, SignedFileLink};
+use yugabyte::storage::StorageConfig;
+use yugabyte::util::signature::{sign_file_link, verify_file_link};
+
+use crate::config::FileConfig;
+
+const CHUNK_SIZE: u64 = 64 * 1024;
+const DEFAULT_LINK_TTL_SECONDS: i64 = 60 * 60;
+const MAX_LINK_TTL_SECONDS: i64 = 7 * 24 * 60 * 60;
+const REMOTE_LINK_TTL_SECONDS: i64 = 15 * 60;
+
+#[derive(Debug, PartialEq)]
+enum ByteRange {
+    Full,
+    Partial(u64, u64),
+    Unsatisfiable,
+}
+
+// Parse a single `bytes=` range, anything we cannot honour falls back to the full file.
+fn parse_range(header: &str, length: u64) -> ByteRange {
+    let spec = match header.trim().strip_prefix("bytes=") {
+        Some(spec) if !spec.contains(',') => spec.trim(),
+        _ => return ByteRange::Full,
+    };
+    let (start, end) = match spec.split_once('-') {
+        Some(bounds) => bounds,
+        None => return ByteRange::Full,
+    };
+
+    match (start.parse::<u64>(), end.parse::<u64>()) {
+        // bytes=-500 means the last 500 bytes.
+        (Err(_), Ok(suffix)) if start.is_empty() => {
+            if suffix == 0 || length == 0 {
+                ByteRange::Unsatisfiable
+            } else {
+                ByteRange::Partial(length.saturating_sub(suffix), length - 1)
+            }
+        }
+        // bytes=500- means everything from the 500th byte.
+        (Ok(first), Err(_)) if end.is_empty() => {
+            if first >= length {
+                ByteRange::Unsatisfiable
+            } else {
+                ByteRange::Partial(first, length - 1)
+            }
+        }
+        (Ok(first), Ok(last)) if first <= last => {
+            if first >= length {
+                ByteRange::Unsatisfiable
+            } else {
+                ByteRange::Partial(first, last.min(length - 1))
+            }
+        }
+        _ => ByteRange::Full,
+    }
+}
+
+// Stream `length` bytes of the file starting at `start`, chunk by chunk.
+fn file_stream(
+    mut file: File,
+    start: u64,
+    length: u64,
+) -> Result<impl Stream<Item = Result<Bytes, actix_web::Error>>, std::io::Error> {
+    file.seek(SeekFrom::Start(start))?;
+
+    Ok(Box::pin(stream::unfold((file, length), |(mut file, remaining)| async move {
+        if remaining == 0 {
+            return None;
+        }
+        let mut buffer = vec![0; remaining.min(CHUNK_SIZE) as usize];
+        match file.read(&mut buffer) {
+            Ok(0) => None,
+            Ok(read) => {
+                buffer.truncate(read);
+                Some((Ok(Bytes::from(buffer)), (file, remaining - read as u64)))
+            }
+            Err(e) => Some((Err(e.into()), (file, 0))),
+        }
+    })))
+}
+
+fn serve_file(req: &HttpRequest, path: &Path, file_name: Option<&str>) -> Result<HttpResponse, Errors> {
+    // Step 1: open the file and read its metadata.
+    let file = File::open(path).map_err(|_| Errors::NotFound(FileNotFound.into()))?;
+    let metadata = file.metadata().map_err(|_| Errors::NotFound(FileNotFound.into()))?;
+    let length = metadata.len();
+    let modified = metadata.modified().ok();
+    let modified_secs = modified
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let etag = format!("\"{:x}-{:x}\"", length, modified_secs);
+    let last_modified = modified.map(|time| HttpDate::from(time).to_string());
+
+    // Step 2: the range is only honoured if the validator in If-Range still matches.
+    let if_range_matches = match req.headers().get(IF_RANGE).and_then(|value| value.to_str().ok()) {
+        Some(validator) => validator == etag || Some(validator) == last_modified.as_deref(),
+        None => true,
+    };
+    let range = match req.headers().get(RANGE).and_then(|value| value.to_str().ok()) {
+        Some(header) if if_range_matches => parse_range(header, length),
+        _ => ByteRange::Full,
+    };
+
+    let mut response = match range {
+        ByteRange::Unsatisfiable => {
+            return Ok(HttpResponse::RangeNotSatisfiable()
+                .header(CONTENT_RANGE, format!("bytes */{}", length))
+                .finish());
+        }
+        ByteRange::Partial(first, last) => {
+            let mut response = HttpResponse::PartialContent();
+            response.header(CONTENT_RANGE, format!("bytes {}-{}/{}", first, last, length));
+            response
+        }
+        ByteRange::Full => HttpResponse::Ok(),
+    };
+    let (start, end) = match range {
+        ByteRange::Partial(first, last) => (first, last + 1),
+        _ => (0, length),
+    };
+
+    // Step 3: describe the file, then stream the requested bytes.
+    let file_name = match file_name {
+        Some(file_name) => file_name.to_string(),
+        None => path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default(),
+    };
+    response
+        .content_type(mime_guess::from_path(&file_name).first_or_octet_stream().to_string())
+        .set(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(file_name)],
+        })
+        .header(ACCEPT_RANGES, "bytes")
+        .header(ETAG, etag);
+    if let Some(last_modified) = last_modified {
+        response.header(LAST_MODIFIED, last_modified);
+    }
+
+    let body = file_stream(file, start, end - start)
+        .map_err(|_| Errors::NotFound(FileNotFound.into()))?;
+    Ok(response.no_chunking(end - start).streaming(body))
+}
+
+fn job_file_error(e: Error) -> Errors {
+    match e {
+        Error::BadRequest(_) => Errors::BadRequest(FileNotReady.into()),
+        Error::NotFound(_) => Errors::NotFound(FileNotFound.into()),
+        Error::DBError(_) => Errors::NotFound(NotFound.into()),
+        _ => Errors::InternalServerError(InternalServerError.into()),
+    }
+}
+
+fn serve_job_file(
+    req: &HttpRequest,
+    job_id: &Uuid,
+    storage_config: &StorageConfig,
+    connection: &PgPooledConnection,
+) -> Result<HttpResponse, Errors> {
+    match find_job_file(job_id, storage_config, REMOTE_LINK_TTL_SECONDS, connection) {
+        // Blobs of the content-addressed store are named by their checksum, so serve the original name.
+        Ok((found_job, JobFile::Local(path))) => serve_file(req, &path, found_job.file_name.as_deref()),
+        // Remote backends serve the file and its ranges themselves.
+        Ok((_, JobFile::Remote(url))) => Ok(HttpResponse::TemporaryRedirect().header(LOCATION, url).finish()),
+        Err(e) => Err(job_file_error(e)),
+    }
+}
+
+#[api_v2_operation]
+pub(crate) fn download_job_file(
+    req: HttpRequest,
+    web::Path(job_id): web::Path<Uuid>,
+    pool: web::Data<CoreDBPool>,
+    storage_config: web::Data<StorageConfig>,
+) -> Result<HttpResponse, Errors> {
+    // Step 1: get the connection from pool data
+    let connection = pgdata_to_pgconnection(pool);
+
+    // Step 2: stream the file of the job.
+    serve_job_file(&req, &job_id, &storage_config, &connection)
+}
+
+#[api_v2_operation]
+pub(crate) fn create_job_file_link(
+    web::Path(job_id): web::Path<Uuid>,
+    Query(file_link_dto): Query<FileLinkDTO>,
+    pool: web::Data<CoreDBPool>,
+    file_config: web::Data<FileConfig>,
+    storage_config: web::Data<StorageConfig>,
+) -> Result<Json<SignedFileLink>, Errors> {
+    // Step 1: links can only be signed when a key is configured.
+    let signing_key = match &file_config.signing_key {
+        Some(signing_key) => signing_key,
+        None => return Err(Errors::InternalServerError(SignedLinksDisabled.into())),
+    };
+
+    // Step 2: get the connection from pool data, then make sure the file can be served.
+    let connection = pgdata_to_pgconnection(pool);
+    if let Err(e) = find_job_file(&job_id, &storage_config, REMOTE_LINK_TTL_SECONDS, &connection) {
+        return Err(job_file_error(e));
+    }
+
+    // Step 3: sign the link, then fire the response.
+    let ttl_seconds = file_link_dto
+        .ttl_seconds
+        .unwrap_or(DEFAULT_LINK_TTL_SECONDS)
+        .clamp(1, MAX_LINK_TTL_SECONDS);
+    let expires_at = Utc::now() + Duration::seconds(ttl_seconds);
+    let expires = expires_at.timestamp();
+    let signature = sign_file_link(signing_key, &job_id, expires);
+
+    Ok(Json(SignedFileLink {
+        url: format!(
+            "{}/file/{}?expires={}&signature={}",
+            file_config.public_url, job_id, expires, signature
+        ),
+        expires_at: expires_at.naive_utc(),
+    }))
+}
+
+#[api_v2_operation]
+pub(crate) fn download_signed_job_file(
+    req: HttpRequest,
+    web::Path(job_id): web::Path<Uuid>,
+    Query(signed_file_dto): Query<SignedFileDTO>,
+    pool: web::Data<CoreDBPool>,
+    file_config: web::Data<FileConfig>,
+    storage_config: web::Data<StorageConfig>,
+) -> Result<HttpResponse, Errors> {
+    // Step 1: check the signature and the expiry of the link.
+    let signing_key = match &file_config.signing_key {
+        Some(signing_key) => signing_key,
+        None => return Err(Errors::Forbidden(SignedLinksDisabled.into())),
+    };
+    if !verify_file_link(signing_key, &job_id, signed_file_dto.expires, &signed_file_dto.signature) {
+        return Err(Errors::Forbidden(InvalidSignature.into()));
+    }
+    if signed_file_dto.expires < Utc::now().timestamp() {
+        return Err(Errors::Forbidden(LinkExpired.into()));
+    }
+
+    // Step 2: get the connection from pool data
+    let connection = pgdata_to_pgconnection(pool);
+
+    // Step 3: stream the file of the job.
+    serve_job_file(&req, &job_id, &storage_config, &connection)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_single_ranges() {
+        assert_eq!(parse_range("bytes=0-99", 1000), ByteRange::Partial(0, 99));
+        assert_eq!(parse_range("bytes=900-", 1000), ByteRange::Partial(900, 999));
+        assert_eq!(parse_range("bytes=-100", 1000), ByteRange::Partial(900, 999));
+        assert_eq!(parse_range("bytes=-5000", 1000), ByteRange::Partial(0, 999));
+        assert_eq!(parse_range("bytes=500-5000", 1000), ByteRange::Partial(500, 999));
+    }
+
+    #[test]
+    fn parse_unsatisfiable_ranges() {
+        assert_eq!(parse_range("bytes=1000-", 1000), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=1000-1200", 1000), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 1000), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-10", 0), ByteRange::Unsatisfiable);
+    }
+
+    #[test]
+    fn ignore_invalid_ranges() {
+        assert_eq!(parse_range("items=0-99", 1000), ByteRange::Full);
+        assert_eq!(parse_range("bytes=0-9,20-29", 1000), ByteRange::Full);
+        assert_eq!(parse_range("bytes=99-0", 1000), ByteRange::Full);
+        assert_eq!(parse_range("bytes=abc", 1000), ByteRange::Full);
+        assert_eq!(parse_range("bytes=-", 1000), ByteRange::Full);
+    }
+}
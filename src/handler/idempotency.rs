@@ -0,0 +1,90 @@
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse};
+use diesel::PgConnection;
+use serde::Serialize;
+
+use yugabyte::engine::idempotency::{request_hash, with_idempotency_key};
+use yugabyte::errors::{Error, Errors};
+use yugabyte::errors::StateCode::InvalidIdempotencyKey;
+use yugabyte::model::idempotency::StoredResponse;
+
+use crate::config::IdempotencyConfig;
+
+pub const IDEMPOTENCY_KEY: &str = "Idempotency-Key";
+pub const IDEMPOTENT_REPLAYED: &str = "Idempotent-Replayed";
+
+// The key the client sent with the request, if any.
+fn idempotency_key(req: &HttpRequest) -> Result<Option<String>, Errors> {
+    match req.headers().get(IDEMPOTENCY_KEY) {
+        None => Ok(None),
+        Some(value) => match value.to_str() {
+            Ok(key) if !key.is_empty() && key.len() <= 255 => Ok(Some(key.to_string())),
+            _ => Err(Errors::BadRequest(InvalidIdempotencyKey.into())),
+        },
+    }
+}
+
+// A JSON response in the form it is kept for the retries.
+pub(crate) fn json_response<T: Serialize>(
+    status: StatusCode,
+    headers: Vec<(String, String)>,
+    body: &T,
+) -> Result<StoredResponse, Error> {
+    Ok(StoredResponse {
+        status: status.as_u16(),
+        headers,
+        body: serde_json::to_string(body).map_err(|e| Error::InternalServerError(e.to_string()))?,
+    })
+}
+
+fn send(stored_response: StoredResponse, replayed: bool) -> HttpResponse {
+    let status = StatusCode::from_u16(stored_response.status).unwrap_or(StatusCode::OK);
+    let mut response = HttpResponse::build(status);
+    for (name, value) in &stored_response.headers {
+        response.header(name.as_str(), value.as_str());
+    }
+    if replayed {
+        response.header(IDEMPOTENT_REPLAYED, "true");
+    }
+    response
+        .content_type("application/json")
+        .body(stored_response.body)
+}
+
+// Answer the request once per Idempotency-Key, a retry with the same key and body gets the
+// first response again and a retry with another body is a conflict. Without a key the request
+// is simply answered.
+pub(crate) fn idempotent<T, F>(
+    req: &HttpRequest,
+    request: &T,
+    idempotency_config: &IdempotencyConfig,
+    connection: &PgConnection,
+    map_error: fn(Error) -> Errors,
+    respond: F,
+) -> Result<HttpResponse, Errors>
+where
+    T: Serialize,
+    F: FnOnce() -> Result<StoredResponse, Error>,
+{
+    // Step 1: the key is optional but must be valid when sent.
+    let key = match idempotency_key(req)? {
+        Some(key) => key,
+        None => return respond().map(|stored_response| send(stored_response, false)).map_err(map_error),
+    };
+
+    // Step 2: the same key may be used on different endpoints.
+    let endpoint = format!("{} {}", req.method(), req.path());
+    match with_idempotency_key(
+        &key,
+        &endpoint,
+        &request_hash(request),
+        idempotency_config.window_seconds,
+        connection,
+        respond,
+    ) {
+        Ok((stored_response, replayed)) => Ok(send(stored_response, replayed)),
+        Err(Error::Conflict(state_code)) => Err(Errors::Conflict(state_code.into())),
+        Err(e) => Err(map_error(e)),
+    }
+}
+
@@ -1,74 +1,221 @@
+use actix_web::dev::HttpResponseBuilder;
+use actix_web::http::{header, StatusCode};
+use actix_web::{HttpRequest, HttpResponse};
+use diesel::PgConnection;
 use paperclip::actix::{
     api_v2_operation,
     web::{self, Query},
+    NoContent,
 };
 use paperclip::actix::web::Json;
+use serde_json::Value;
 use uuid::Uuid;
 
 use yugabyte::db_connection::{CoreDBPool, pgdata_to_pgconnection};
-use yugabyte::engine::job::{count_jobs, delete_job_by_id, find_job_by_id, get_all_paginated_jobs, set_activate_job, update_job, get_job_info};
+use yugabyte::engine::audit::{audited_change, audited_change_by_external_ref};
+use yugabyte::engine::job::{count_jobs, delete_job_by_id, estimate_jobs, find_job_by_id, get_all_paginated_jobs, get_job_page, patch_job, set_activate_job, get_job_info, upsert_job};
 use yugabyte::errors::{Error, Errors};
 use yugabyte::errors::StateCode::{
-    DBError, DuplicationError, InternalServerError, NotFound, PaginationError,
+    CursorSortConflict, DBError, DeletedDuplicationError, DuplicationError, InternalServerError, InvalidCursor, NotFound,
+    VersionMismatch, VersionRequired,
 };
-use yugabyte::model::general::{PaginatedResponseDTO, PaginationDTO};
-use yugabyte::model::job::{Job, NewJob, JobInfo};
+use yugabyte::model::filter::JobFilterDTO;
+use yugabyte::model::general::{CountMode, PaginatedResponseDTO, PaginationDTO};
+use yugabyte::model::job::{Job, JobInfo, JobPatch, JobUpdateDTO, NewJob};
+use yugabyte::storage::StorageConfig;
+
+use crate::config::{IdempotencyConfig, PaginationConfig};
+use crate::handler::audit::audited_call;
+use crate::handler::idempotency::{idempotent, json_response};
+use crate::handler::{request_actor, JOBS_PATH};
+
+// Path of a job in the jobs resource.
+pub(crate) fn job_location(job_id: &Uuid) -> String {
+    format!("{}/{}", JOBS_PATH, job_id)
+}
+
+fn insert_error(e: Error) -> Errors {
+    match e {
+        Error::ValidationError(errors) => Errors::BadReq(errors),
+        Error::Conflict(state_code) => Errors::Conflict(state_code.into()),
+        Error::DuplicationError => Errors::Conflict(DuplicationError.into()),
+        Error::DeletedDuplicationError => Errors::Conflict(DeletedDuplicationError.into()),
+        Error::DBError(_) => Errors::InternalServerError(DBError.into()),
+        _ => Errors::InternalServerError(InternalServerError.into()),
+    }
+}
 
 #[api_v2_operation]
 pub(crate) fn add_job(
+    req: HttpRequest,
     new_job: web::Json<NewJob>,
     pool: web::Data<CoreDBPool>,
-) -> Result<Json<Job>, Errors> {
+    storage_config: web::Data<StorageConfig>,
+    idempotency_config: web::Data<IdempotencyConfig>,
+) -> Result<HttpResponse, Errors> {
     // Step 1: get the connection from pool data
     let connection = pgdata_to_pgconnection(pool);
 
-    // Step 2: add the new job.
-    match new_job.add_job(&connection) {
-        // Step 3: fire the response
-        Ok(job) => Ok(Json(job)),
-        Err(e) => {
-            match e {
-                Error::DuplicationError => Err(Errors::InternalServerError(DuplicationError.into())),
-                _ => Err(Errors::InternalServerError(InternalServerError.into())),
-            }
-        }
+    // Step 2: add the job, it completes at once if its checksum is already stored, then fire the response.
+    idempotent(&req, &*new_job, &idempotency_config, &connection, insert_error, || {
+        let job = audited_change(
+            &[],
+            &connection,
+            || new_job.add_job(&storage_config, &request_actor(&req), &connection),
+            |job| audited_call(&req, StatusCode::OK, vec![job.id]),
+        )?;
+        json_response(StatusCode::OK, Vec::new(), &job)
+    })
+}
+
+#[api_v2_operation]
+pub(crate) fn create_job(
+    req: HttpRequest,
+    new_job: web::Json<NewJob>,
+    pool: web::Data<CoreDBPool>,
+    storage_config: web::Data<StorageConfig>,
+    idempotency_config: web::Data<IdempotencyConfig>,
+) -> Result<HttpResponse, Errors> {
+    // Step 1: get the connection from pool data
+    let connection = pgdata_to_pgconnection(pool);
+
+    // Step 2: add the job, then fire the response with the location and the version of the new job.
+    idempotent(&req, &*new_job, &idempotency_config, &connection, insert_error, || {
+        let job = audited_change(
+            &[],
+            &connection,
+            || new_job.add_job(&storage_config, &request_actor(&req), &connection),
+            |job| audited_call(&req, StatusCode::CREATED, vec![job.id]),
+        )?;
+        let headers = vec![
+            (header::LOCATION.to_string(), job_location(&job.id)),
+            (header::ETAG.to_string(), job.etag()),
+        ];
+        json_response(StatusCode::CREATED, headers, &job)
+    })
+}
+
+#[api_v2_operation]
+pub(crate) fn upsert_job_by_ref(
+    req: HttpRequest,
+    web::Path(external_ref): web::Path<String>,
+    new_job: web::Json<NewJob>,
+    pool: web::Data<CoreDBPool>,
+    storage_config: web::Data<StorageConfig>,
+) -> Result<HttpResponse, Errors> {
+    // Step 1: get the connection from pool data
+    let connection = pgdata_to_pgconnection(pool);
+
+    // Step 2: create or update the job of the reference, `If-Match` is only checked when it is sent.
+    let if_match = req.headers().get(header::IF_MATCH).and_then(|value| value.to_str().ok());
+    let actor = request_actor(&req);
+    let (upserted_job, created) = audited_change_by_external_ref(
+        &external_ref,
+        &connection,
+        || upsert_job(&external_ref, &new_job, if_match, &storage_config, &actor, &connection),
+        |(upserted_job, created)| {
+            let status_code = if *created { StatusCode::CREATED } else { StatusCode::OK };
+            audited_call(&req, status_code, vec![upserted_job.id])
+        },
+    )
+    .map_err(|e| match e {
+        Error::PreconditionFailed | Error::DBError(diesel::result::Error::NotFound) => write_error(e),
+        _ => insert_error(e),
+    })?;
+
+    // Step 3: fire the job, with its location when it was created.
+    if created {
+        let mut response = HttpResponse::Created();
+        response.header(header::LOCATION, job_location(&upserted_job.id));
+        Ok(job_response(response, upserted_job))
+    } else {
+        Ok(job_response(HttpResponse::Ok(), upserted_job))
+    }
+}
+
+#[api_v2_operation]
+pub(crate) fn get_job(
+    web::Path(job_id): web::Path<Uuid>,
+    pool: web::Data<CoreDBPool>,
+) -> Result<HttpResponse, Errors> {
+    // Step 1: get the connection from pool data
+    let connection = pgdata_to_pgconnection(pool);
+
+    // Step 2: search in the database for the required job.
+    match find_job_by_id(&job_id, &connection) {
+        Ok(found_job) => Ok(job_response(HttpResponse::Ok(), found_job)),
+        Err(Error::DBError(diesel::result::Error::NotFound)) => Err(Errors::NotFound(NotFound.into())),
+        Err(_) => Err(Errors::InternalServerError(DBError.into())),
     }
 }
 
 #[api_v2_operation]
 pub(crate) fn list_paginated_jobs(
     Query(pagination_dto): Query<PaginationDTO>,
+    Query(filter): Query<JobFilterDTO>,
     pool: web::Data<CoreDBPool>,
+    pagination_config: web::Data<PaginationConfig>,
 ) -> Result<Json<PaginatedResponseDTO<Job>>, Errors> {
-    // Step 1: get the connection from pool data
+    // Step 1: the pagination, the filters and the sort order must be valid.
+    let mut errors = Vec::new();
+    if let Err(pagination_errors) =
+        pagination_dto.check(pagination_config.max_page_size, pagination_config.max_offset)
+    {
+        errors.extend(pagination_errors);
+    }
+    if let Err(filter_errors) = filter.check() {
+        errors.extend(filter_errors);
+    }
+    if !errors.is_empty() {
+        return Err(Errors::BadReq(errors));
+    }
+    let custom_order = filter.sort_keys().is_ok_and(|sort_keys| !sort_keys.is_empty());
+    if custom_order && pagination_dto.cursor.is_some() {
+        return Err(Errors::BadRequest(CursorSortConflict.into()));
+    }
+
+    // Step 2: get the connection from pool data
     let connection = pgdata_to_pgconnection(pool);
 
-    // Step 2: count all jobs in the db.
-    match count_jobs(&connection) {
-        Ok(jobs_count) => {
-            // Step 3: get the job list.
-            match get_all_paginated_jobs(&pagination_dto, &connection) {
-                Ok(paginated_list) => {
-                    let response = PaginatedResponseDTO {
-                        paginated_list,
-                        count: jobs_count,
-                    };
-                    // Step 4: fire the response
-                    Ok(Json(response))
-                }
-                Err(_) => {
-                    Err(Errors::BadRequest(PaginationError.into()))
-                }
+    // Step 3: get the page, a custom order is paged by offset and has no cursors.
+    let page = if custom_order {
+        get_all_paginated_jobs(&pagination_dto, &filter, &connection).map(|paginated_list| {
+            PaginatedResponseDTO {
+                paginated_list,
+                count: None,
+                next: None,
+                prev: None,
             }
-        }
-        Err(_) => {
-            Err(Errors::InternalServerError(DBError.into()))
-        }
+        })
+    } else {
+        get_job_page(&pagination_dto, &filter, &connection)
+    };
+    let mut response = match page {
+        Ok(page) => page,
+        Err(Error::BadRequest(_)) => return Err(Errors::BadRequest(InvalidCursor.into())),
+        Err(_) => return Err(Errors::InternalServerError(DBError.into())),
+    };
+
+    // Step 4: count the jobs matching the filters, only when asked to after the first page.
+    let count_mode = pagination_dto.count.unwrap_or(match pagination_dto.cursor {
+        Some(_) => CountMode::None,
+        None => CountMode::Exact,
+    });
+    response.count = match count_mode {
+        CountMode::Exact => Some(count_jobs(&filter, &connection)),
+        CountMode::Approximate => Some(estimate_jobs(&filter, &connection)),
+        CountMode::None => None,
     }
+    .transpose()
+    .map_err(|_| Errors::InternalServerError(DBError.into()))?;
+
+    // Step 5: fire the response
+    Ok(Json(response))
 }
 
 #[api_v2_operation]
 pub(crate) fn remove_job_by_id(
+    req: HttpRequest,
     job_id: web::Path<Uuid>,
     pool: web::Data<CoreDBPool>,
 ) -> Result<Json<Job>, Errors> {
@@ -76,64 +223,163 @@ pub(crate) fn remove_job_by_id(
     let connection = pgdata_to_pgconnection(pool);
 
     // Step 2: delete the job from the db.
-    match delete_job_by_id(&job_id, &connection) {
+    let deleted = audited_change(
+        &[*job_id],
+        &connection,
+        || delete_job_by_id(&job_id, None, &request_actor(&req), &connection),
+        |deleted_job| audited_call(&req, StatusCode::OK, vec![deleted_job.id]),
+    );
+    match deleted {
         Ok(deleted_job) => Ok(Json(deleted_job)),
+        Err(Error::DBError(diesel::result::Error::NotFound)) => Err(Errors::NotFound(NotFound.into())),
         Err(_) => Err(Errors::InternalServerError(DBError.into())),
     }
 }
 
 
+// The `If-Match` header of the request, the jobs resource refuses writes without one.
+fn required_if_match(req: &HttpRequest) -> Result<String, Errors> {
+    req.headers()
+        .get(header::IF_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+        .ok_or_else(|| Errors::PreconditionRequired(VersionRequired.into()))
+}
+
+fn write_error(e: Error) -> Errors {
+    match e {
+        Error::DBError(diesel::result::Error::NotFound) => Errors::NotFound(NotFound.into()),
+        Error::PreconditionFailed => Errors::PreconditionFailed(VersionMismatch.into()),
+        Error::ValidationError(errors) => Errors::BadReq(errors),
+        Error::Conflict(state_code) => Errors::Conflict(state_code.into()),
+        _ => Errors::InternalServerError(DBError.into()),
+    }
+}
+
+// The job in the body of the response, its version in the ETag header.
+fn job_response(mut response: HttpResponseBuilder, found_job: Job) -> HttpResponse {
+    response.header(header::ETAG, found_job.etag()).json(found_job)
+}
+
+fn save_job(
+    req: &HttpRequest,
+    job_id: &Uuid,
+    job_patch: JobPatch,
+    if_match: Option<&str>,
+    connection: &PgConnection,
+) -> Result<Job, Errors> {
+    audited_change(
+        &[*job_id],
+        connection,
+        || patch_job(job_id, job_patch, if_match, &request_actor(req), connection),
+        |saved_job| audited_call(req, StatusCode::OK, vec![saved_job.id]),
+    )
+    .map_err(write_error)
+}
+
 #[api_v2_operation]
 pub(crate) fn update_job_api(
-    incoming_job: web::Json<Job>,
+    req: HttpRequest,
+    job_update_dto: web::Json<JobUpdateDTO>,
     pool: web::Data<CoreDBPool>,
 ) -> Result<Json<Job>, Errors> {
     // Step 1: get the connection from pool data.
     let connection = pgdata_to_pgconnection(pool);
 
-    let job_id = &incoming_job.id.clone();
+    // Step 2: update the job, the deprecated route only checks the version when it is sent.
+    let if_match = req.headers().get(header::IF_MATCH).and_then(|value| value.to_str().ok());
+    // only the fields a client owns are taken from the body.
+    save_job(&req, &job_update_dto.id, JobPatch::replacing(&job_update_dto), if_match, &connection).map(Json)
+}
 
-    // Step 2: search in the database for the required job.
-    match find_job_by_id(job_id, &connection) {
-        Ok(mut found_job) => {
-            found_job.total_size = incoming_job.total_size;
-            found_job.downloaded_size = incoming_job.downloaded_size;
-            found_job.percent_downloaded = incoming_job.percent_downloaded;
-            found_job.status = incoming_job.status.clone();
-            found_job.is_active = incoming_job.is_active;
-            found_job.creation_date = incoming_job.creation_date;
-            found_job.expiration_date = incoming_job.expiration_date;
-
-            // Step 3: update the job, then send response to the client.
-            match update_job(&found_job, &connection) {
-                Ok(updated_job) => Ok(Json(updated_job)),
-                Err(_) => Err(Errors::InternalServerError(DBError.into())),
-            }
-        }
-        Err(_) => {
-            Err(Errors::NotFound(NotFound.into()))
-        }
-    }
+#[api_v2_operation]
+pub(crate) fn replace_job(
+    req: HttpRequest,
+    web::Path(job_id): web::Path<Uuid>,
+    replacement: web::Json<Value>,
+    pool: web::Data<CoreDBPool>,
+) -> Result<HttpResponse, Errors> {
+    // Step 1: the client must send the version of the job it read.
+    let if_match = required_if_match(&req)?;
+
+    // Step 2: read the new job, only the fields a client owns may be in it.
+    let job_patch = JobPatch::from_replacement(&replacement).map_err(Errors::BadReq)?;
+
+    // Step 3: get the connection from pool data.
+    let connection = pgdata_to_pgconnection(pool);
+
+    // Step 4: replace the job, then send response to the client.
+    let updated_job = save_job(&req, &job_id, job_patch, Some(&if_match), &connection)?;
+    Ok(job_response(HttpResponse::Ok(), updated_job))
+}
+
+#[api_v2_operation]
+pub(crate) fn patch_job_api(
+    req: HttpRequest,
+    web::Path(job_id): web::Path<Uuid>,
+    merge_patch: web::Json<Value>,
+    pool: web::Data<CoreDBPool>,
+) -> Result<HttpResponse, Errors> {
+    // Step 1: the client must send the version of the job it read.
+    let if_match = required_if_match(&req)?;
+
+    // Step 2: read the merge patch, only the fields a client owns may be in it.
+    let job_patch = JobPatch::from_merge_patch(&merge_patch).map_err(Errors::BadReq)?;
+
+    // Step 3: get the connection from pool data.
+    let connection = pgdata_to_pgconnection(pool);
+
+    // Step 4: patch the job, then send response to the client.
+    let patched_job = save_job(&req, &job_id, job_patch, Some(&if_match), &connection)?;
+    Ok(job_response(HttpResponse::Ok(), patched_job))
+}
+
+#[api_v2_operation]
+pub(crate) fn delete_job(
+    req: HttpRequest,
+    web::Path(job_id): web::Path<Uuid>,
+    pool: web::Data<CoreDBPool>,
+) -> Result<NoContent, Errors> {
+    // Step 1: the client must send the version of the job it read.
+    let if_match = required_if_match(&req)?;
+
+    // Step 2: get the connection from pool data
+    let connection = pgdata_to_pgconnection(pool);
+
+    // Step 3: delete the job from the db.
+    audited_change(
+        &[job_id],
+        &connection,
+        || delete_job_by_id(&job_id, Some(&if_match), &request_actor(&req), &connection),
+        |deleted_job| audited_call(&req, StatusCode::NO_CONTENT, vec![deleted_job.id]),
+    )
+    .map_err(write_error)?;
+    Ok(NoContent)
 }
 
 #[api_v2_operation]
 pub(crate) fn activate_job(
+    req: HttpRequest,
     web::Path((job_id, is_active)): web::Path<(Uuid, bool)>,
     pool: web::Data<CoreDBPool>,
 ) -> Result<Json<bool>, Errors> {
     // Step 1: get the connection from pool data
     let connection = pgdata_to_pgconnection(pool);
 
-    // Step 2: activate/deactivate the job.
-    match set_activate_job(&job_id, is_active, &connection) {
-        Ok(state) => {
-            if state == 1 {
-                // Step 4: fire the response
-                Ok(Json(true))
-            } else {
-                Err(Errors::NotFound(NotFound.into()))
-            }
-        }
+    // Step 2: activate/deactivate the job, a job that is not there is not found.
+    let activated = audited_change(
+        &[job_id],
+        &connection,
+        || match set_activate_job(&job_id, is_active, &request_actor(&req), &connection)? {
+            1 => Ok(()),
+            _ => Err(Error::DBError(diesel::result::Error::NotFound)),
+        },
+        |_| audited_call(&req, StatusCode::OK, vec![job_id]),
+    );
+    match activated {
+        // Step 3: fire the response
+        Ok(()) => Ok(Json(true)),
+        Err(Error::DBError(diesel::result::Error::NotFound)) => Err(Errors::NotFound(NotFound.into())),
         Err(_) => {
             Err(Errors::InternalServerError(DBError.into()))
         }
@@ -151,6 +397,7 @@ pub(crate) fn download_info(
     // Step 2: Get the download info.
     match get_job_info(&job_id, &connection) {
         Ok(job_info) => Ok(Json(job_info)),
-        Err(_) => Err(Errors::InternalServerError(NotFound.into()))
+        Err(Error::DBError(diesel::result::Error::NotFound)) => Err(Errors::NotFound(NotFound.into())),
+        Err(_) => Err(Errors::InternalServerError(DBError.into())),
     }
 }
\ No newline at end of file
@@ -1,15 +1,174 @@
+use std::net::{IpAddr, SocketAddr};
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::error::QueryPayloadError;
+use actix_web::http::HeaderMap;
+use actix_web::middleware::DefaultHeaders;
+use actix_web::HttpRequest;
+use actix_web::web::{Data, JsonConfig};
+use futures_util::future::{ready, Either, Ready};
 use paperclip::actix::web;
 use paperclip::actix::web::ServiceConfig;
 
-use crate::handler::job::{activate_job, add_job, list_paginated_jobs, remove_job_by_id, update_job_api};
+use crate::config::PrincipalConfig;
+use crate::handler::alert::stream_alerts;
+use crate::handler::audit::list_audit_logs;
+use crate::handler::bulk::{apply_bulk_action, create_jobs_in_bulk, BULK_BODY_LIMIT};
+use crate::handler::event::{list_job_events, stream_events, stream_job_changes};
+use crate::handler::file::{create_job_file_link, download_job_file, download_signed_job_file};
+use crate::handler::job::{
+    activate_job, add_job, create_job, delete_job, download_info, get_job, list_paginated_jobs,
+    patch_job_api, remove_job_by_id, replace_job, update_job_api, upsert_job_by_ref,
+};
+use crate::handler::progress::{get_job_progress, report_job_progress};
+use crate::handler::retention::retention_report;
+use crate::handler::trash::{list_trashed_jobs, purge_job_from_trash, restore_trashed_job};
+use crate::handler::webhook::{
+    create_webhook, delete_webhook, get_webhook, list_webhook_deliveries, list_webhooks, redeliver_webhook,
+};
+use yugabyte::errors::{ErrorCode, Errors};
+use yugabyte::errors::StateCode::AdminOnly;
 
+pub mod alert;
+pub mod audit;
+pub mod bulk;
+pub mod event;
+pub mod file;
+pub mod idempotency;
 pub mod job;
+pub mod progress;
+pub mod retention;
+pub mod trash;
+pub mod webhook;
+
+pub const JOBS_PATH: &str = "/api/v1/jobs";
+pub const ANONYMOUS_ACTOR: &str = "anonymous";
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+fn with_principal_config<T>(app_data: Option<&Data<PrincipalConfig>>, read: impl FnOnce(&PrincipalConfig) -> T) -> T {
+    match app_data {
+        Some(principal_config) => read(principal_config),
+        None => read(&PrincipalConfig::default()),
+    }
+}
+
+// The principal authenticated by a trusted proxy in front of the service. Anyone can send the header, it is
+// ignored on the other connections.
+fn authenticated_principal(peer_address: Option<SocketAddr>, headers: &HeaderMap, principal_config: &PrincipalConfig) -> String {
+    if !peer_address.is_some_and(|peer_address| principal_config.trusted_proxies.contains(&peer_address.ip())) {
+        return ANONYMOUS_ACTOR.to_string();
+    }
+    headers
+        .get(principal_config.header.as_str())
+        .and_then(|value| value.to_str().ok())
+        .filter(|principal| !principal.is_empty())
+        .unwrap_or(ANONYMOUS_ACTOR)
+        .to_string()
+}
+
+// Who made the request.
+pub(crate) fn request_actor(req: &HttpRequest) -> String {
+    with_principal_config(req.app_data(), |principal_config| {
+        authenticated_principal(req.peer_addr(), req.headers(), principal_config)
+    })
+}
+
+// Where the request comes from: the peer of the connection, or for a trusted proxy the address it got the
+// request from. Every proxy appends that address to `X-Forwarded-For`, so it is read from the right and
+// the addresses before the first untrusted one are the client's own word.
+pub(crate) fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    let peer_ip = req.peer_addr()?.ip();
+    with_principal_config(req.app_data(), |principal_config| {
+        let mut client_ip = peer_ip;
+        if !principal_config.trusted_proxies.contains(&peer_ip) {
+            return Some(client_ip);
+        }
+        let forwarded: Vec<&str> = req
+            .headers()
+            .get_all(X_FORWARDED_FOR)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect();
+        for address in forwarded.into_iter().rev() {
+            match address.parse::<IpAddr>() {
+                Ok(forwarded_ip) => client_ip = forwarded_ip,
+                Err(_) => break,
+            }
+            if !principal_config.trusted_proxies.contains(&client_ip) {
+                break;
+            }
+        }
+        Some(client_ip)
+    })
+}
+
+// Let only the `ADMIN_PRINCIPALS` through.
+pub fn admin_only<S>(
+    req: ServiceRequest,
+    service: &mut S,
+) -> Either<S::Future, Ready<Result<ServiceResponse, actix_web::Error>>>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+{
+    let admin = with_principal_config(req.app_data(), |principal_config| {
+        let principal = authenticated_principal(req.peer_addr(), req.headers(), principal_config);
+        principal != ANONYMOUS_ACTOR && principal_config.admins.contains(&principal)
+    });
+    if admin {
+        Either::Left(service.call(req))
+    } else {
+        Either::Right(ready(Err(Errors::Forbidden(AdminOnly.into()).into())))
+    }
+}
+
+// Query strings that cannot be read are answered like the other invalid requests.
+pub fn query_error(error: QueryPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    Errors::BadReq(vec![ErrorCode {
+        error_code: "invalid-query".to_string(),
+        message: error.to_string(),
+    }])
+    .into()
+}
 
 pub fn routes(config: &mut ServiceConfig) {
     config
+        .service(
+            web::scope(JOBS_PATH)
+                // a batch of jobs does not fit in the default body limit.
+                .service(
+                    web::scope("/bulk")
+                        .data(JsonConfig::default().limit(BULK_BODY_LIMIT))
+                        .route("", web::post().to(create_jobs_in_bulk))
+                        .route("/{action}", web::post().to(apply_bulk_action)),
+                )
+                .route("", web::get().to(list_paginated_jobs))
+                .route("", web::post().to(create_job))
+                .route("/by-ref/{external_ref}", web::put().to(upsert_job_by_ref))
+                .route("/changes", web::get().to(stream_job_changes))
+                .route("/trash", web::get().to(list_trashed_jobs))
+                .route("/trash/{job_id}", web::delete().to(purge_job_from_trash))
+                .route("/trash/{job_id}/restore", web::post().to(restore_trashed_job))
+                .route("/{job_id}", web::get().to(get_job))
+                .route("/{job_id}", web::put().to(replace_job))
+                .route("/{job_id}", web::patch().to(patch_job_api))
+                .route("/{job_id}", web::delete().to(delete_job))
+                .route("/{job_id}/events", web::get().to(list_job_events))
+                .route("/{job_id}/info", web::get().to(download_info))
+                .route("/{job_id}/progress", web::get().to(get_job_progress))
+                .route("/{job_id}/progress", web::post().to(report_job_progress))
+                .route("/{job_id}/file", web::get().to(download_job_file))
+                .route("/{job_id}/file/link", web::get().to(create_job_file_link)),
+        )
+        // The old routes are kept until the clients move to the jobs resource.
         .service(
             web::scope("/feature")
                 // .wrap(AuthMiddleware)
+                .wrap(
+                    DefaultHeaders::new()
+                        .header("Deprecation", "true")
+                        .header("Link", format!("<{}>; rel=\"successor-version\"", JOBS_PATH)),
+                )
                 .route("", web::get().to(list_paginated_jobs))
                 .route("/update", web::put().to(update_job_api))
                 .route("/add", web::post().to(add_job))
@@ -17,6 +176,82 @@ pub fn routes(config: &mut ServiceConfig) {
                 .route(
                     "/{feature_id}/activate/{is_active}",
                     web::put().to(activate_job),
-                ),
+                )
+                .route("/{feature_id}/file", web::get().to(download_job_file))
+                .route("/{feature_id}/file/link", web::get().to(create_job_file_link)),
+        )
+        // Signed links are shared without an API session, so they stay out of the guarded scope.
+        .service(
+            web::scope("/file")
+                .route("/{feature_id}", web::get().to(download_signed_job_file)),
+        )
+        // The audit log is for the administrators only.
+        .service(
+            web::scope("/admin")
+                .wrap_fn(admin_only)
+                .route("/audit", web::get().to(list_audit_logs)),
+        )
+        .service(
+            web::scope("/api/v1/webhooks")
+                .route("", web::get().to(list_webhooks))
+                .route("", web::post().to(create_webhook))
+                .route("/deliveries/{delivery_id}/redeliver", web::post().to(redeliver_webhook))
+                .route("/{webhook_id}", web::get().to(get_webhook))
+                .route("/{webhook_id}", web::delete().to(delete_webhook))
+                .route("/{webhook_id}/deliveries", web::get().to(list_webhook_deliveries)),
+        )
+        .service(
+            web::scope("/api/v1/events")
+                .route("", web::get().to(stream_events)),
+        )
+        .service(
+            web::scope("/api/v1/alerts")
+                .route("", web::get().to(stream_alerts)),
+        )
+        .service(
+            web::scope("/retention")
+                .route("/report", web::get().to(retention_report)),
+        );
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    fn behind_proxies(req: TestRequest) -> HttpRequest {
+        req.app_data(Data::new(PrincipalConfig {
+            header: "X-Forwarded-User".to_string(),
+            trusted_proxies: vec!["10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()],
+            admins: vec!["alice".to_string()],
+        }))
+        .to_http_request()
+    }
+
+    #[test]
+    fn a_client_cannot_tell_who_it_is_or_where_it_comes_from() {
+        let req = behind_proxies(
+            TestRequest::default()
+                .peer_addr("192.0.2.5:4000".parse().unwrap())
+                .header("X-Forwarded-User", "alice")
+                .header(X_FORWARDED_FOR, "10.0.0.1"),
+        );
+
+        assert_eq!(request_actor(&req), ANONYMOUS_ACTOR);
+        assert_eq!(client_ip(&req), Some("192.0.2.5".parse().unwrap()));
+    }
+
+    #[test]
+    fn a_trusted_proxy_tells_the_principal_and_the_client_address() {
+        let req = behind_proxies(
+            TestRequest::default()
+                .peer_addr("10.0.0.1:4000".parse().unwrap())
+                .header("X-Forwarded-User", "alice")
+                .header(X_FORWARDED_FOR, "198.51.100.1, 203.0.113.7, 10.0.0.2"),
         );
-}
\ No newline at end of file
+
+        assert_eq!(request_actor(&req), "alice");
+        assert_eq!(client_ip(&req), Some("203.0.113.7".parse().unwrap()));
+    }
+}
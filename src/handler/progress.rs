@@ -0,0 +1,51 @@
+use paperclip::actix::{api_v2_operation, web::{self, Query}};
+use paperclip::actix::web::Json;
+use uuid::Uuid;
+
+use yugabyte::db_connection::{CoreDBPool, pgdata_to_pgconnection};
+use yugabyte::engine::progress::{get_progress_series, report_progress};
+use yugabyte::errors::{Error, Errors};
+use yugabyte::errors::StateCode::{DBError, InvalidProgressStep, NotFound};
+use yugabyte::model::job::Job;
+use yugabyte::model::progress::{ProgressReportDTO, ProgressSeriesDTO, ProgressSeriesQuery};
+
+#[api_v2_operation]
+pub(crate) fn get_job_progress(
+    web::Path(job_id): web::Path<Uuid>,
+    Query(series_query): Query<ProgressSeriesQuery>,
+    pool: web::Data<CoreDBPool>,
+) -> Result<Json<ProgressSeriesDTO>, Errors> {
+    // Step 1: the step must be at least a second.
+    if matches!(series_query.step_seconds, Some(step_seconds) if step_seconds < 1) {
+        return Err(Errors::BadRequest(InvalidProgressStep.into()));
+    }
+
+    // Step 2: get the connection from pool data
+    let connection = pgdata_to_pgconnection(pool);
+
+    // Step 3: fire the progress series of the job, oldest first.
+    match get_progress_series(&job_id, &series_query, &connection) {
+        Ok(series) => Ok(Json(series)),
+        Err(Error::DBError(diesel::result::Error::NotFound)) => Err(Errors::NotFound(NotFound.into())),
+        Err(_) => Err(Errors::InternalServerError(DBError.into())),
+    }
+}
+
+#[api_v2_operation]
+pub(crate) fn report_job_progress(
+    web::Path(job_id): web::Path<Uuid>,
+    progress_report: web::Json<ProgressReportDTO>,
+    pool: web::Data<CoreDBPool>,
+) -> Result<Json<Job>, Errors> {
+    // Step 1: get the connection from pool data
+    let connection = pgdata_to_pgconnection(pool);
+
+    // Step 2: record the progress the downloader reports, then fire the job.
+    match report_progress(&job_id, &progress_report, &connection) {
+        Ok(reported_job) => Ok(Json(reported_job)),
+        Err(Error::DBError(diesel::result::Error::NotFound)) => Err(Errors::NotFound(NotFound.into())),
+        Err(Error::ValidationError(errors)) => Err(Errors::BadReq(errors)),
+        Err(Error::Conflict(state_code)) => Err(Errors::Conflict(state_code.into())),
+        Err(_) => Err(Errors::InternalServerError(DBError.into())),
+    }
+}
@@ -0,0 +1,28 @@
+use paperclip::actix::{api_v2_operation, web};
+use paperclip::actix::web::Json;
+
+use yugabyte::db_connection::{CoreDBPool, pgdata_to_pgconnection};
+use yugabyte::engine::retention::apply_retention;
+use yugabyte::errors::Errors;
+use yugabyte::errors::StateCode::DBError;
+use yugabyte::model::retention::RetentionReport;
+use yugabyte::storage::StorageConfig;
+
+use crate::config::RetentionConfig;
+
+#[api_v2_operation]
+pub(crate) fn retention_report(
+    pool: web::Data<CoreDBPool>,
+    storage_config: web::Data<StorageConfig>,
+    retention_config: web::Data<RetentionConfig>,
+) -> Result<Json<RetentionReport>, Errors> {
+    // Step 1: get the connection from pool data
+    let connection = pgdata_to_pgconnection(pool);
+
+    // Step 2: dry run the retention rules, nothing is removed.
+    match apply_retention(&retention_config.policy, &storage_config, true, &connection) {
+        // Step 3: fire the response
+        Ok(report) => Ok(Json(report)),
+        Err(_) => Err(Errors::InternalServerError(DBError.into())),
+    }
+}
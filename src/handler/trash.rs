@@ -0,0 +1,82 @@
+use actix_web::http::StatusCode;
+use actix_web::HttpRequest;
+use paperclip::actix::{api_v2_operation, web::{self, Query}, NoContent};
+use paperclip::actix::web::Json;
+use uuid::Uuid;
+
+use yugabyte::db_connection::{CoreDBPool, pgdata_to_pgconnection};
+use yugabyte::engine::audit::audited_change;
+use yugabyte::engine::trash::{get_trashed_jobs, purge_trashed_job, restore_job};
+use yugabyte::errors::{Error, Errors};
+use yugabyte::errors::StateCode::{DBError, NotFound};
+use yugabyte::model::general::{PaginatedResponseDTO, PaginationDTO};
+use yugabyte::model::job::Job;
+use yugabyte::storage::StorageConfig;
+
+use crate::config::PaginationConfig;
+use crate::handler::audit::audited_call;
+use crate::handler::request_actor;
+
+fn trash_error(e: Error) -> Errors {
+    match e {
+        Error::DBError(diesel::result::Error::NotFound) => Errors::NotFound(NotFound.into()),
+        Error::Conflict(state_code) => Errors::Conflict(state_code.into()),
+        _ => Errors::InternalServerError(DBError.into()),
+    }
+}
+
+#[api_v2_operation]
+pub(crate) fn list_trashed_jobs(
+    Query(pagination_dto): Query<PaginationDTO>,
+    pool: web::Data<CoreDBPool>,
+    pagination_config: web::Data<PaginationConfig>,
+) -> Result<Json<PaginatedResponseDTO<Job>>, Errors> {
+    // Step 1: the pagination must be valid.
+    pagination_dto
+        .check(pagination_config.max_page_size, pagination_config.max_offset)
+        .map_err(Errors::BadReq)?;
+
+    // Step 2: get the connection from pool data
+    let connection = pgdata_to_pgconnection(pool);
+
+    // Step 3: fire the page of the trash, the last deleted first.
+    get_trashed_jobs(&pagination_dto, &connection).map(Json).map_err(trash_error)
+}
+
+#[api_v2_operation]
+pub(crate) fn restore_trashed_job(
+    req: HttpRequest,
+    web::Path(job_id): web::Path<Uuid>,
+    pool: web::Data<CoreDBPool>,
+) -> Result<Json<Job>, Errors> {
+    // Step 1: get the connection from pool data
+    let connection = pgdata_to_pgconnection(pool);
+
+    // Step 2: take the job out of the trash, then fire it.
+    audited_change(
+        &[job_id],
+        &connection,
+        || restore_job(&job_id, &request_actor(&req), &connection),
+        |_| audited_call(&req, StatusCode::OK, vec![job_id]),
+    )
+    .map(Json)
+    .map_err(trash_error)
+}
+
+#[api_v2_operation]
+pub(crate) fn purge_job_from_trash(
+    req: HttpRequest,
+    web::Path(job_id): web::Path<Uuid>,
+    pool: web::Data<CoreDBPool>,
+    storage_config: web::Data<StorageConfig>,
+) -> Result<NoContent, Errors> {
+    // Step 1: get the connection from pool data
+    let connection = pgdata_to_pgconnection(pool);
+
+    // Step 2: delete the job and its file for good.
+    purge_trashed_job(&job_id, &storage_config, &connection, |_| {
+        audited_call(&req, StatusCode::NO_CONTENT, vec![job_id])
+    })
+    .map_err(trash_error)?;
+    Ok(NoContent)
+}
@@ -0,0 +1,116 @@
+use actix_web::HttpResponse;
+use paperclip::actix::{api_v2_operation, web::{self, Query}, NoContent};
+use paperclip::actix::web::Json;
+use uuid::Uuid;
+
+use yugabyte::db_connection::{CoreDBPool, pgdata_to_pgconnection};
+use yugabyte::engine::webhook::{
+    create_subscription, delete_subscription, find_subscription, get_subscriptions, get_webhook_deliveries, redeliver,
+};
+use yugabyte::errors::{Error, Errors};
+use yugabyte::errors::StateCode::{DBError, NotFound};
+use yugabyte::model::general::{PaginatedResponseDTO, PaginationDTO};
+use yugabyte::model::webhook::{DeliveryFilterDTO, WebhookDelivery, WebhookSubscription, WebhookSubscriptionDTO};
+
+use crate::config::PaginationConfig;
+
+fn webhook_error(e: Error) -> Errors {
+    match e {
+        Error::DBError(diesel::result::Error::NotFound) => Errors::NotFound(NotFound.into()),
+        _ => Errors::InternalServerError(DBError.into()),
+    }
+}
+
+#[api_v2_operation]
+pub(crate) fn create_webhook(
+    subscription_dto: web::Json<WebhookSubscriptionDTO>,
+    pool: web::Data<CoreDBPool>,
+) -> Result<HttpResponse, Errors> {
+    // Step 1: the URL, event types and secret must be valid.
+    subscription_dto.check().map_err(Errors::BadReq)?;
+
+    // Step 2: get the connection from pool data
+    let connection = pgdata_to_pgconnection(pool);
+
+    // Step 3: subscribe, then fire the subscription without its secret.
+    let subscription = create_subscription(&subscription_dto, &connection).map_err(webhook_error)?;
+    Ok(HttpResponse::Created().json(subscription))
+}
+
+#[api_v2_operation]
+pub(crate) fn list_webhooks(
+    Query(pagination_dto): Query<PaginationDTO>,
+    pool: web::Data<CoreDBPool>,
+    pagination_config: web::Data<PaginationConfig>,
+) -> Result<Json<PaginatedResponseDTO<WebhookSubscription>>, Errors> {
+    // Step 1: the pagination must be valid.
+    pagination_dto
+        .check(pagination_config.max_page_size, pagination_config.max_offset)
+        .map_err(Errors::BadReq)?;
+
+    // Step 2: get the connection from pool data
+    let connection = pgdata_to_pgconnection(pool);
+
+    // Step 3: fire the page of the subscriptions, the newest first.
+    get_subscriptions(&pagination_dto, &connection).map(Json).map_err(webhook_error)
+}
+
+#[api_v2_operation]
+pub(crate) fn get_webhook(
+    web::Path(webhook_id): web::Path<Uuid>,
+    pool: web::Data<CoreDBPool>,
+) -> Result<Json<WebhookSubscription>, Errors> {
+    // Step 1: get the connection from pool data
+    let connection = pgdata_to_pgconnection(pool);
+
+    // Step 2: fire the subscription.
+    find_subscription(&webhook_id, &connection).map(Json).map_err(webhook_error)
+}
+
+#[api_v2_operation]
+pub(crate) fn delete_webhook(
+    web::Path(webhook_id): web::Path<Uuid>,
+    pool: web::Data<CoreDBPool>,
+) -> Result<NoContent, Errors> {
+    // Step 1: get the connection from pool data
+    let connection = pgdata_to_pgconnection(pool);
+
+    // Step 2: drop the subscription and its delivery log.
+    delete_subscription(&webhook_id, &connection).map_err(webhook_error)?;
+    Ok(NoContent)
+}
+
+#[api_v2_operation]
+pub(crate) fn list_webhook_deliveries(
+    web::Path(webhook_id): web::Path<Uuid>,
+    Query(pagination_dto): Query<PaginationDTO>,
+    Query(filter): Query<DeliveryFilterDTO>,
+    pool: web::Data<CoreDBPool>,
+    pagination_config: web::Data<PaginationConfig>,
+) -> Result<Json<PaginatedResponseDTO<WebhookDelivery>>, Errors> {
+    // Step 1: the pagination must be valid.
+    pagination_dto
+        .check(pagination_config.max_page_size, pagination_config.max_offset)
+        .map_err(Errors::BadReq)?;
+
+    // Step 2: get the connection from pool data
+    let connection = pgdata_to_pgconnection(pool);
+
+    // Step 3: fire the page of the delivery log, the newest first.
+    get_webhook_deliveries(&webhook_id, &pagination_dto, &filter, &connection)
+        .map(Json)
+        .map_err(webhook_error)
+}
+
+#[api_v2_operation]
+pub(crate) fn redeliver_webhook(
+    web::Path(delivery_id): web::Path<Uuid>,
+    pool: web::Data<CoreDBPool>,
+) -> Result<HttpResponse, Errors> {
+    // Step 1: get the connection from pool data
+    let connection = pgdata_to_pgconnection(pool);
+
+    // Step 2: queue the delivery again, it is posted by the delivery task.
+    let delivery = redeliver(&delivery_id, &connection).map_err(webhook_error)?;
+    Ok(HttpResponse::Accepted().json(delivery))
+}
@@ -1,2 +1,5 @@
+pub mod alert;
+pub mod broadcast;
 pub mod config;
 pub mod handler;
+pub mod task;
@@ -1,22 +1,85 @@
 use std::env;
 
-use actix_web::{App, HttpServer, middleware::Logger, web::Data, web::JsonConfig};
+use actix_web::{App, HttpServer, middleware::Logger, web::Data, web::JsonConfig, web::QueryConfig};
 use paperclip::actix::OpenApiExt;
 
-use exam::config::start_tracing;
-use exam::handler::routes;
+use exam::broadcast::EventBroadcaster;
+use exam::config::{AlertConfig, AuditConfig, ChangeFeedConfig, EmailConfig, FileConfig, IdempotencyConfig, OutboxConfig, PaginationConfig, PrincipalConfig, ProgressConfig, ReconcileConfig, RetentionConfig, StallConfig, TrashConfig, WebhookConfig, start_tracing};
+use exam::handler::audit::audit_request;
+use exam::handler::{query_error, routes};
+use exam::task::audit::spawn_audit_task;
+use exam::task::email::spawn_email_task;
+use exam::task::idempotency::spawn_idempotency_task;
+use exam::task::notify::spawn_change_feed_task;
+use exam::task::outbox::spawn_outbox_task;
+use exam::task::progress::spawn_progress_task;
+use exam::task::reconcile::{run_reconcile_command, spawn_reconcile_task};
+use exam::task::retention::spawn_retention_task;
+use exam::task::stall::spawn_stall_task;
+use exam::task::trash::spawn_trash_task;
+use exam::task::webhook::spawn_webhook_task;
 use yugabyte::db_connection::CoreDBPool;
+use yugabyte::storage::StorageConfig;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     start_tracing();
     let core_db_pool_data = Data::new(CoreDBPool::default());
+    let file_config_data = Data::new(FileConfig::default());
+    let storage_config_data = Data::new(StorageConfig::default());
+    let retention_config_data = Data::new(RetentionConfig::default());
+    let pagination_config_data = Data::new(PaginationConfig::default());
+    let idempotency_config_data = Data::new(IdempotencyConfig::default());
+    let principal_config_data = Data::new(PrincipalConfig::default());
+    let event_broadcaster_data = Data::new(EventBroadcaster::default());
+
+    if env::args().nth(1).as_deref() == Some("reconcile") {
+        let requeue = env::args().any(|arg| arg == "--requeue");
+        return run_reconcile_command(core_db_pool_data, &storage_config_data, requeue);
+    }
+    spawn_reconcile_task(
+        core_db_pool_data.clone(),
+        storage_config_data.clone(),
+        ReconcileConfig::default(),
+    );
+    spawn_retention_task(
+        core_db_pool_data.clone(),
+        storage_config_data.clone(),
+        retention_config_data.get_ref().clone(),
+    );
+    spawn_trash_task(core_db_pool_data.clone(), storage_config_data.clone(), TrashConfig::default());
+    spawn_audit_task(core_db_pool_data.clone(), AuditConfig::default());
+    spawn_progress_task(core_db_pool_data.clone(), ProgressConfig::default());
+    spawn_stall_task(core_db_pool_data.clone(), StallConfig::default());
+    let change_feed_config = ChangeFeedConfig::default();
+    let email_config = EmailConfig::default();
+    spawn_outbox_task(
+        core_db_pool_data.clone(),
+        OutboxConfig::default(),
+        change_feed_config.clone(),
+        AlertConfig::default(),
+        email_config.clone(),
+        event_broadcaster_data.clone(),
+    );
+    spawn_change_feed_task(change_feed_config, event_broadcaster_data.clone());
+    spawn_webhook_task(core_db_pool_data.clone(), WebhookConfig::default());
+    spawn_email_task(core_db_pool_data.clone(), email_config);
+    spawn_idempotency_task(core_db_pool_data.clone(), idempotency_config_data.clone());
 
     HttpServer::new(move || {
         App::new()
+            .wrap_fn(audit_request)
             .wrap(Logger::default())
             .data(JsonConfig::default().limit(4096))
+            .app_data(QueryConfig::default().error_handler(query_error))
             .app_data(core_db_pool_data.clone())
+            .app_data(file_config_data.clone())
+            .app_data(storage_config_data.clone())
+            .app_data(retention_config_data.clone())
+            .app_data(pagination_config_data.clone())
+            .app_data(idempotency_config_data.clone())
+            .app_data(principal_config_data.clone())
+            .app_data(event_broadcaster_data.clone())
             .wrap_api()
             .configure(routes)
             .with_json_spec_at(env::var("OPEN_API").unwrap().as_str())
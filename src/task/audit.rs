@@ -0,0 +1,25 @@
+use std::time::Duration;
+
+use actix_web::web::Data;
+
+use yugabyte::db_connection::{CoreDBPool, pgdata_to_pgconnection};
+use yugabyte::engine::audit::purge_audit_logs;
+
+use crate::config::AuditConfig;
+use crate::task::spawn_periodic;
+
+// Drop the entries of the audit log older than the configured period, checked every hour.
+pub fn spawn_audit_task(pool: Data<CoreDBPool>, audit_config: AuditConfig) {
+    let retention_days = match audit_config.retention_days {
+        Some(retention_days) => retention_days,
+        None => return,
+    };
+
+    spawn_periodic(Duration::from_secs(3600), move || {
+        let connection = pgdata_to_pgconnection(pool.clone());
+        match purge_audit_logs(retention_days, &connection) {
+            Ok(purged_entries) => tracing::info!(purged_entries, "purged the audit log"),
+            Err(e) => tracing::error!("purging the audit log failed: {}", e),
+        }
+    });
+}
@@ -0,0 +1,50 @@
+use std::time::Duration;
+
+use actix_web::web::Data;
+
+use yugabyte::db_connection::{CoreDBPool, pgdata_to_pgconnection};
+use yugabyte::engine::email::{purge_email_notifications, send_due_digests, send_due_emails};
+
+use crate::config::EmailConfig;
+use crate::task::spawn_periodic;
+
+// Send the due emails every few seconds, and the digests once their hour passed. The old emails are
+// purged every hour. Nothing runs without SMTP.
+pub fn spawn_email_task(pool: Data<CoreDBPool>, email_config: EmailConfig) {
+    let smtp_settings = match email_config.smtp.clone() {
+        Some(smtp_settings) => smtp_settings,
+        None => return,
+    };
+
+    let send_pool = pool.clone();
+    let retention_days = email_config.retention_days;
+    spawn_periodic(Duration::from_secs(email_config.poll_seconds), move || {
+        let connection = pgdata_to_pgconnection(send_pool.clone());
+        match send_due_emails(&smtp_settings, email_config.max_attempts, &connection) {
+            Ok((0, 0)) => {}
+            Ok((sent, failed)) => tracing::info!(sent, failed, "sent the emails"),
+            Err(e) => tracing::error!("sending the emails failed: {}", e),
+        }
+        match send_due_digests(
+            &smtp_settings,
+            &email_config.digest_subject,
+            email_config.digest_hour,
+            email_config.max_attempts,
+            &connection,
+        ) {
+            Ok((0, 0)) => {}
+            Ok((sent, failed)) => tracing::info!(sent, failed, "sent the email digests"),
+            Err(e) => tracing::error!("sending the email digests failed: {}", e),
+        }
+    });
+
+    if let Some(retention_days) = retention_days {
+        spawn_periodic(Duration::from_secs(3600), move || {
+            let connection = pgdata_to_pgconnection(pool.clone());
+            match purge_email_notifications(retention_days, &connection) {
+                Ok(purged_emails) => tracing::info!(purged_emails, "purged the emails"),
+                Err(e) => tracing::error!("purging the emails failed: {}", e),
+            }
+        });
+    }
+}
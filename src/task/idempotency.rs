@@ -0,0 +1,20 @@
+use std::time::Duration;
+
+use actix_web::web::Data;
+
+use yugabyte::db_connection::{CoreDBPool, pgdata_to_pgconnection};
+use yugabyte::engine::idempotency::purge_idempotency_keys;
+
+use crate::config::IdempotencyConfig;
+use crate::task::spawn_periodic;
+
+// Drop the expired Idempotency-Keys every hour, a retry after the window is a new request anyway.
+pub fn spawn_idempotency_task(pool: Data<CoreDBPool>, idempotency_config: Data<IdempotencyConfig>) {
+    spawn_periodic(Duration::from_secs(3600), move || {
+        let connection = pgdata_to_pgconnection(pool.clone());
+        match purge_idempotency_keys(idempotency_config.window_seconds, &connection) {
+            Ok(purged_keys) => tracing::info!(purged_keys, "purged the expired idempotency keys"),
+            Err(e) => tracing::error!("purging the idempotency keys failed: {}", e),
+        }
+    });
+}
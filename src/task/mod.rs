@@ -0,0 +1,37 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::rt::time::{interval_at, Instant};
+use actix_web::web;
+
+pub mod audit;
+pub mod email;
+pub mod idempotency;
+pub mod notify;
+pub mod outbox;
+pub mod progress;
+pub mod reconcile;
+pub mod retention;
+pub mod stall;
+pub mod trash;
+pub mod webhook;
+
+// Run the task every `period` on the blocking thread pool, the first run is one period after start up.
+pub fn spawn_periodic<F>(period: Duration, task: F)
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    let task = Arc::new(task);
+    actix_web::rt::spawn(async move {
+        let mut ticker = interval_at(Instant::now() + period, period);
+        loop {
+            ticker.tick().await;
+            let task = task.clone();
+            let _ = web::block(move || {
+                task();
+                Ok::<(), ()>(())
+            })
+            .await;
+        }
+    });
+}
@@ -0,0 +1,45 @@
+use std::thread;
+use std::time::Duration;
+
+use actix_web::web::Data;
+use serde_json::Value;
+
+use yugabyte::engine::notify::{listen, JOB_CHANGES_CHANNEL, JOB_EVENTS_CHANNEL};
+
+use crate::broadcast::{EventBroadcaster, CHANGES_TOPIC, EVENTS_TOPIC};
+use crate::config::ChangeFeedConfig;
+
+const RECONNECT_SECONDS: u64 = 5;
+
+// The SSE event of a notification: the `op` of a row change, the `name` of a domain event.
+fn event_name(payload: &str, field: &str) -> Option<String> {
+    serde_json::from_str::<Value>(payload)
+        .ok()?
+        .get(field)?
+        .as_str()
+        .map(str::to_string)
+}
+
+// Listen to the notifications of the database on a thread of its own and stream them to the clients
+// of this server, whichever server made the change. A lost connection is opened again after a while.
+pub fn spawn_change_feed_task(change_feed_config: ChangeFeedConfig, broadcaster: Data<EventBroadcaster>) {
+    let database_url = match change_feed_config.database_url {
+        Some(database_url) => database_url,
+        None => return,
+    };
+
+    thread::spawn(move || loop {
+        let listened = listen(&database_url, &[JOB_CHANGES_CHANNEL, JOB_EVENTS_CHANNEL], |channel, payload| {
+            if channel == JOB_CHANGES_CHANNEL {
+                let op = event_name(payload, "op").unwrap_or_else(|| "update".to_string());
+                broadcaster.broadcast(CHANGES_TOPIC, &op, payload);
+            } else if let Some(name) = event_name(payload, "name") {
+                broadcaster.broadcast(EVENTS_TOPIC, &name, payload);
+            }
+        });
+        if let Err(e) = listened {
+            tracing::error!("the change feed stopped, it reconnects in {} seconds: {}", RECONNECT_SECONDS, e);
+        }
+        thread::sleep(Duration::from_secs(RECONNECT_SECONDS));
+    });
+}
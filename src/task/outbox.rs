@@ -0,0 +1,100 @@
+use std::time::Duration;
+
+use actix_web::web::Data;
+
+use yugabyte::db_connection::{CoreDBPool, pgdata_to_pgconnection};
+use yugabyte::engine::email::enqueue_job_emails;
+use yugabyte::engine::notify::notify_outbox_event;
+use yugabyte::engine::outbox::{purge_outbox_events, relay_outbox_events, RelayOutcome};
+use yugabyte::errors::Error;
+use yugabyte::engine::webhook::enqueue_webhook_deliveries;
+use yugabyte::model::alert::Alert;
+use yugabyte::model::outbox::{SINK_ALERTS, SINK_ALERT_WEBHOOK, SINK_EMAILS, SINK_EVENTS, SINK_WEBHOOKS};
+
+use crate::alert::{post_alert_webhook, send_alert};
+use crate::broadcast::{EventBroadcaster, EVENTS_TOPIC};
+use crate::config::{AlertConfig, ChangeFeedConfig, EmailConfig, OutboxConfig};
+use crate::task::spawn_periodic;
+
+// Log the events a sink failed on, they are published again on the next runs until they are dead.
+fn log_relay(sink: &str, relayed: Result<RelayOutcome, Error>) {
+    match relayed {
+        Ok(outcome) => {
+            for (event_id, e) in outcome.failures {
+                tracing::error!(sink, event_id, "publishing the event failed, it is tried again later: {}", e);
+            }
+        }
+        Err(e) => tracing::error!(sink, "relaying the outbox failed: {}", e),
+    }
+}
+
+// Relay the outbox to its sinks every few seconds: the webhook deliveries are queued in the transaction
+// recording the publication, the events are notified to every server for GET /api/v1/events, or streamed
+// by this one without the change feed, the stall watchdog events raise the alerts, posted to the alert
+// webhook until it takes them, and the completed and failed jobs queue their emails, none without SMTP.
+// The old events every sink is done with are purged every hour.
+pub fn spawn_outbox_task(
+    pool: Data<CoreDBPool>,
+    outbox_config: OutboxConfig,
+    change_feed_config: ChangeFeedConfig,
+    alert_config: AlertConfig,
+    email_config: EmailConfig,
+    broadcaster: Data<EventBroadcaster>,
+) {
+    let change_feed = change_feed_config.database_url.is_some();
+    let max_attempts = outbox_config.max_attempts;
+    let relay_pool = pool.clone();
+    spawn_periodic(Duration::from_secs(outbox_config.poll_seconds), move || {
+        let connection = pgdata_to_pgconnection(relay_pool.clone());
+        let relayed = relay_outbox_events(SINK_WEBHOOKS, max_attempts, &connection, |outbox_event| {
+            enqueue_webhook_deliveries(outbox_event, &connection).map(|_| ())
+        });
+        log_relay(SINK_WEBHOOKS, relayed);
+
+        let relayed = relay_outbox_events(SINK_EVENTS, max_attempts, &connection, |outbox_event| {
+            if change_feed {
+                return notify_outbox_event(outbox_event, &connection);
+            }
+            match serde_json::to_string(outbox_event) {
+                Ok(data) => broadcaster.broadcast(EVENTS_TOPIC, &outbox_event.name, &data),
+                Err(e) => tracing::error!(event_id = outbox_event.id, "the event cannot be serialized: {}", e),
+            }
+            Ok(())
+        });
+        log_relay(SINK_EVENTS, relayed);
+
+        let relayed = relay_outbox_events(SINK_ALERTS, max_attempts, &connection, |outbox_event| {
+            if let Some(alert) = Alert::from_outbox_event(outbox_event) {
+                send_alert(&alert, &alert_config, &broadcaster);
+            }
+            Ok(())
+        });
+        log_relay(SINK_ALERTS, relayed);
+
+        let relayed = relay_outbox_events(SINK_ALERT_WEBHOOK, max_attempts, &connection, |outbox_event| {
+            match Alert::from_outbox_event(outbox_event) {
+                Some(alert) => post_alert_webhook(&alert, &alert_config),
+                None => Ok(()),
+            }
+        });
+        log_relay(SINK_ALERT_WEBHOOK, relayed);
+
+        let relayed = relay_outbox_events(SINK_EMAILS, max_attempts, &connection, |outbox_event| {
+            if email_config.smtp.is_none() {
+                return Ok(());
+            }
+            enqueue_job_emails(outbox_event, &email_config.templates, &connection).map(|_| ())
+        });
+        log_relay(SINK_EMAILS, relayed);
+    });
+
+    if let Some(retention_days) = outbox_config.retention_days {
+        spawn_periodic(Duration::from_secs(3600), move || {
+            let connection = pgdata_to_pgconnection(pool.clone());
+            match purge_outbox_events(retention_days, &connection) {
+                Ok(purged_events) => tracing::info!(purged_events, "purged the outbox"),
+                Err(e) => tracing::error!("purging the outbox failed: {}", e),
+            }
+        });
+    }
+}
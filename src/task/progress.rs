@@ -0,0 +1,24 @@
+use std::time::Duration;
+
+use actix_web::web::Data;
+
+use yugabyte::db_connection::{CoreDBPool, pgdata_to_pgconnection};
+use yugabyte::engine::progress::downsample_progress;
+
+use crate::config::ProgressConfig;
+use crate::task::spawn_periodic;
+
+// Downsample the progress samples older than the configured period, checked every hour.
+pub fn spawn_progress_task(pool: Data<CoreDBPool>, progress_config: ProgressConfig) {
+    spawn_periodic(Duration::from_secs(3600), move || {
+        let connection = pgdata_to_pgconnection(pool.clone());
+        match downsample_progress(
+            progress_config.downsample_after_hours,
+            progress_config.downsample_step_seconds,
+            &connection,
+        ) {
+            Ok(dropped_samples) => tracing::info!(dropped_samples, "downsampled the progress samples"),
+            Err(e) => tracing::error!("downsampling the progress samples failed: {}", e),
+        }
+    });
+}
@@ -0,0 +1,56 @@
+use std::io;
+use std::time::Instant;
+
+use actix_web::web::Data;
+
+use yugabyte::db_connection::{CoreDBPool, pgdata_to_pgconnection};
+use yugabyte::engine::reconcile::reconcile_jobs;
+use yugabyte::storage::StorageConfig;
+
+use crate::config::ReconcileConfig;
+use crate::task::spawn_periodic;
+
+// `exam reconcile [--requeue]` checks the files once and prints the report.
+pub fn run_reconcile_command(
+    pool: Data<CoreDBPool>,
+    storage_config: &StorageConfig,
+    requeue: bool,
+) -> io::Result<()> {
+    let connection = pgdata_to_pgconnection(pool);
+    match reconcile_jobs(storage_config, requeue, &connection) {
+        Ok(report) => {
+            println!("{}", serde_json::to_string_pretty(&report).expect("The report is serializable"));
+            Ok(())
+        }
+        Err(e) => Err(io::Error::other(e.to_string())),
+    }
+}
+
+// Check the files of the completed jobs every configured interval.
+pub fn spawn_reconcile_task(
+    pool: Data<CoreDBPool>,
+    storage_config: Data<StorageConfig>,
+    reconcile_config: ReconcileConfig,
+) {
+    let period = match reconcile_config.interval {
+        Some(period) => period,
+        None => return,
+    };
+
+    spawn_periodic(period, move || {
+        let started = Instant::now();
+        let connection = pgdata_to_pgconnection(pool.clone());
+        match reconcile_jobs(&storage_config, reconcile_config.requeue, &connection) {
+            Ok(report) => tracing::info!(
+                checked = report.checked,
+                missing = report.missing.len(),
+                corrupted = report.corrupted.len(),
+                requeued = report.requeued.len(),
+                orphaned_files = report.orphaned_files.len(),
+                elapsed_ms = started.elapsed().as_millis() as u64,
+                "reconciled the job files"
+            ),
+            Err(e) => tracing::error!("reconciling the job files failed: {}", e),
+        }
+    });
+}
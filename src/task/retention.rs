@@ -0,0 +1,32 @@
+use actix_web::web::Data;
+
+use yugabyte::db_connection::{CoreDBPool, pgdata_to_pgconnection};
+use yugabyte::engine::retention::apply_retention;
+use yugabyte::storage::StorageConfig;
+
+use crate::config::RetentionConfig;
+use crate::task::spawn_periodic;
+
+// Apply the retention rules every configured interval.
+pub fn spawn_retention_task(
+    pool: Data<CoreDBPool>,
+    storage_config: Data<StorageConfig>,
+    retention_config: RetentionConfig,
+) {
+    let period = match retention_config.interval {
+        Some(period) => period,
+        None => return,
+    };
+
+    spawn_periodic(period, move || {
+        let connection = pgdata_to_pgconnection(pool.clone());
+        match apply_retention(&retention_config.policy, &storage_config, false, &connection) {
+            Ok(report) => tracing::info!(
+                expired_files = report.expired_files.len(),
+                purged_jobs = report.purged_jobs.len(),
+                "applied the retention rules"
+            ),
+            Err(e) => tracing::error!("applying the retention rules failed: {}", e),
+        }
+    });
+}
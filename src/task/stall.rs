@@ -0,0 +1,38 @@
+use std::time::Duration;
+
+use actix_web::web::Data;
+
+use yugabyte::db_connection::{CoreDBPool, pgdata_to_pgconnection};
+use yugabyte::engine::stall::{find_stalled_jobs, handle_stalled_job};
+
+use crate::config::StallConfig;
+use crate::task::spawn_periodic;
+
+// Look for the downloads with no progress every minute, or more often for a shorter stall.
+// The alerts are raised by the outbox relay from the events of the recovered jobs.
+pub fn spawn_stall_task(pool: Data<CoreDBPool>, stall_config: StallConfig) {
+    let stall_after_seconds = match stall_config.stall_after_seconds {
+        Some(stall_after_seconds) => stall_after_seconds,
+        None => return,
+    };
+
+    let period = Duration::from_secs(60.min(stall_after_seconds as u64));
+    spawn_periodic(period, move || {
+        let connection = pgdata_to_pgconnection(pool.clone());
+        let stalled_jobs = match find_stalled_jobs(stall_after_seconds, &connection) {
+            Ok(stalled_jobs) => stalled_jobs,
+            Err(e) => return tracing::error!("looking for the stalled jobs failed: {}", e),
+        };
+        for stalled_job in stalled_jobs {
+            if let Err(e) = handle_stalled_job(
+                &stalled_job.id,
+                stall_after_seconds,
+                &stall_config.mirrors,
+                stall_config.max_attempts,
+                &connection,
+            ) {
+                tracing::error!(job_id = %stalled_job.id, "recovering the stalled job failed: {}", e);
+            }
+        }
+    });
+}
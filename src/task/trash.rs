@@ -0,0 +1,35 @@
+use std::time::Duration;
+
+use actix_web::web::Data;
+
+use yugabyte::db_connection::{CoreDBPool, pgdata_to_pgconnection};
+use yugabyte::engine::trash::purge_trash;
+use yugabyte::storage::StorageConfig;
+
+use crate::config::TrashConfig;
+use crate::task::spawn_periodic;
+
+// Purge the jobs kept in the trash longer than the configured period, checked every hour.
+pub fn spawn_trash_task(pool: Data<CoreDBPool>, storage_config: Data<StorageConfig>, trash_config: TrashConfig) {
+    let retention_days = match trash_config.retention_days {
+        Some(retention_days) => retention_days,
+        None => return,
+    };
+
+    spawn_periodic(Duration::from_secs(3600), move || {
+        let connection = pgdata_to_pgconnection(pool.clone());
+        match purge_trash(retention_days, &storage_config, &connection) {
+            Ok(trash_purge) => {
+                for (job_id, e) in &trash_purge.failures {
+                    tracing::error!(job_id = %job_id, "purging the job from the trash failed: {}", e);
+                }
+                tracing::info!(
+                    purged_jobs = trash_purge.purged_ids.len(),
+                    failed_jobs = trash_purge.failures.len(),
+                    "purged the trash"
+                )
+            }
+            Err(e) => tracing::error!("purging the trash failed: {}", e),
+        }
+    });
+}
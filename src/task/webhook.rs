@@ -0,0 +1,21 @@
+use std::time::Duration;
+
+use actix_web::web::Data;
+
+use yugabyte::db_connection::{CoreDBPool, pgdata_to_pgconnection};
+use yugabyte::engine::webhook::deliver_due_webhooks;
+
+use crate::config::WebhookConfig;
+use crate::task::spawn_periodic;
+
+// Post the due webhook deliveries every few seconds.
+pub fn spawn_webhook_task(pool: Data<CoreDBPool>, webhook_config: WebhookConfig) {
+    spawn_periodic(Duration::from_secs(webhook_config.poll_seconds), move || {
+        let connection = pgdata_to_pgconnection(pool.clone());
+        match deliver_due_webhooks(webhook_config.max_attempts, &connection) {
+            Ok((0, 0)) => {}
+            Ok((delivered, failed)) => tracing::info!(delivered, failed, "posted the webhook deliveries"),
+            Err(e) => tracing::error!("posting the webhook deliveries failed: {}", e),
+        }
+    });
+}
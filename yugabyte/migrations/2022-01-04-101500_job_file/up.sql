@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE job
+    ADD COLUMN file_path VARCHAR;
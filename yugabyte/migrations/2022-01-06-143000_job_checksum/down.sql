@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX job_checksum_idx;
+
+ALTER TABLE job
+    DROP COLUMN expected_checksum,
+    DROP COLUMN checksum,
+    DROP COLUMN file_name;
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+ALTER TABLE job
+    ADD COLUMN expected_checksum VARCHAR,
+    ADD COLUMN checksum          VARCHAR,
+    ADD COLUMN file_name         VARCHAR;
+
+CREATE INDEX job_checksum_idx ON job (checksum);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE job
+    DROP COLUMN storage_backend,
+    DROP COLUMN bucket;
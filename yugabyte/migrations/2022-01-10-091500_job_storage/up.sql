@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE job
+    ADD COLUMN storage_backend VARCHAR NOT NULL DEFAULT 'local',
+    ADD COLUMN bucket          VARCHAR;
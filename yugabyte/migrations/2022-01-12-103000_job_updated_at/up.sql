@@ -0,0 +1,5 @@
+-- Your SQL goes here
+ALTER TABLE job
+    ADD COLUMN updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP;
+
+SELECT diesel_manage_updated_at('job');
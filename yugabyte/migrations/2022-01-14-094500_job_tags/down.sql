@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX job_creation_date_idx;
+DROP INDEX job_tags_idx;
+
+ALTER TABLE job
+    DROP COLUMN tags;
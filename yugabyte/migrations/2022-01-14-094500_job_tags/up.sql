@@ -0,0 +1,6 @@
+-- Your SQL goes here
+ALTER TABLE job
+    ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}';
+
+CREATE INDEX job_tags_idx ON job USING GIN (tags);
+CREATE INDEX job_creation_date_idx ON job (creation_date);
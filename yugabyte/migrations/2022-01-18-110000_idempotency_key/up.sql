@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE idempotency_key
+(
+    key           VARCHAR   NOT NULL,
+    endpoint      VARCHAR   NOT NULL,
+    request_hash  VARCHAR   NOT NULL,
+    response      TEXT,
+    creation_date TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (key, endpoint)
+);
+
+CREATE INDEX idempotency_key_creation_date_idx ON idempotency_key (creation_date);
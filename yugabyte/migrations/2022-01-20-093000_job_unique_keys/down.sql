@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX job_name_lower_key;
+
+ALTER TABLE job
+    DROP CONSTRAINT job_external_ref_key;
+
+ALTER TABLE job
+    DROP COLUMN external_ref;
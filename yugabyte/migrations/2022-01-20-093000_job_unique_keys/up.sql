@@ -0,0 +1,32 @@
+-- Your SQL goes here
+ALTER TABLE job
+    ADD COLUMN external_ref VARCHAR;
+
+ALTER TABLE job
+    ADD CONSTRAINT job_external_ref_key UNIQUE (external_ref);
+
+-- Names are unique whatever their case among the jobs that are running, stalled or queued only. A finished job
+-- (Completed, Failed, Expired, Missing, Corrupted) leaves its name to the next download of it, so that the same
+-- file is downloaded again under its name and the retention keeps the last jobs of every name. The jobs sharing
+-- a name are never renamed here, the migration stops with the list of them so that they are renamed by hand first.
+DO
+$$
+    DECLARE
+        duplicates TEXT;
+    BEGIN
+        SELECT STRING_AGG(duplicate.names, '; ')
+        INTO duplicates
+        FROM (
+            SELECT STRING_AGG(name || ' (' || id || ')', ', ' ORDER BY creation_date, id) AS names
+            FROM job
+            WHERE status IN ('Active', 'Queued', 'Stalled')
+            GROUP BY LOWER(name)
+            HAVING COUNT(*) > 1
+        ) AS duplicate;
+        IF duplicates IS NOT NULL THEN
+            RAISE EXCEPTION 'Running and queued job names must be unique whatever their case, rename these jobs first: %', duplicates;
+        END IF;
+    END
+$$;
+
+CREATE UNIQUE INDEX job_name_lower_key ON job (LOWER(name)) WHERE status IN ('Active', 'Queued', 'Stalled');
@@ -0,0 +1,14 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX job_external_ref_key;
+
+ALTER TABLE job
+    ADD CONSTRAINT job_external_ref_key UNIQUE (external_ref);
+
+DROP INDEX job_name_lower_key;
+
+CREATE UNIQUE INDEX job_name_lower_key ON job (LOWER(name)) WHERE status IN ('Active', 'Queued', 'Stalled');
+
+DROP INDEX job_deleted_at_idx;
+
+ALTER TABLE job
+    DROP COLUMN deleted_at;
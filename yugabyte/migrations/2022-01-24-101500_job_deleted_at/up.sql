@@ -0,0 +1,16 @@
+-- Your SQL goes here
+ALTER TABLE job
+    ADD COLUMN deleted_at TIMESTAMP;
+
+CREATE INDEX job_deleted_at_idx ON job (deleted_at) WHERE deleted_at IS NOT NULL;
+
+-- A job in the trash leaves its name and reference to the others, restoring it checks them again.
+DROP INDEX job_name_lower_key;
+
+CREATE UNIQUE INDEX job_name_lower_key ON job (LOWER(name))
+    WHERE status IN ('Active', 'Queued', 'Stalled') AND deleted_at IS NULL;
+
+ALTER TABLE job
+    DROP CONSTRAINT job_external_ref_key;
+
+CREATE UNIQUE INDEX job_external_ref_key ON job (external_ref) WHERE deleted_at IS NULL;
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE job_event
+(
+    id            BIGSERIAL PRIMARY KEY,
+    job_id        UUID      NOT NULL REFERENCES job (id) ON DELETE CASCADE,
+    kind          VARCHAR   NOT NULL,
+    actor         VARCHAR   NOT NULL,
+    details       JSONB     NOT NULL DEFAULT '{}',
+    creation_date TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX job_event_job_id_idx ON job_event (job_id, id);
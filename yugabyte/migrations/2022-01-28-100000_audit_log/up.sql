@@ -0,0 +1,61 @@
+-- Your SQL goes here
+CREATE TABLE audit_log
+(
+    id            BIGSERIAL PRIMARY KEY,
+    creation_date TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    request_id    VARCHAR   NOT NULL,
+    principal     VARCHAR   NOT NULL,
+    source_ip     VARCHAR,
+    method        VARCHAR   NOT NULL,
+    path          VARCHAR   NOT NULL,
+    action        VARCHAR   NOT NULL,
+    job_id        UUID,
+    before        JSONB,
+    after         JSONB,
+    status_code   INT4      NOT NULL,
+    result        VARCHAR   NOT NULL
+);
+
+CREATE INDEX audit_log_creation_date_idx ON audit_log (creation_date);
+CREATE INDEX audit_log_job_id_idx ON audit_log (job_id);
+CREATE INDEX audit_log_principal_idx ON audit_log (principal);
+
+-- The log is append-only, rows are only removed by the retention once they are old enough.
+CREATE FUNCTION audit_log_append_only() RETURNS TRIGGER AS
+$$
+BEGIN
+    RAISE EXCEPTION 'the audit log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_log_append_only
+    BEFORE UPDATE
+    ON audit_log
+    FOR EACH ROW
+EXECUTE PROCEDURE audit_log_append_only();
+
+CREATE TRIGGER audit_log_no_truncate
+    BEFORE TRUNCATE
+    ON audit_log
+    FOR EACH STATEMENT
+EXECUTE PROCEDURE audit_log_append_only();
+
+-- The retention tells its period with `audit_log.retention_days` in its transaction, any other delete is refused.
+CREATE FUNCTION audit_log_retention_only() RETURNS TRIGGER AS
+$$
+DECLARE
+    retention_days TEXT := current_setting('audit_log.retention_days', true);
+BEGIN
+    IF retention_days IS NULL OR retention_days = ''
+        OR OLD.creation_date >= LOCALTIMESTAMP - make_interval(days => retention_days::INT) THEN
+        RAISE EXCEPTION 'audit log entries are only removed once older than the retention period';
+    END IF;
+    RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_log_retention_only
+    BEFORE DELETE
+    ON audit_log
+    FOR EACH ROW
+EXECUTE PROCEDURE audit_log_retention_only();
@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE job_progress;
+
+ALTER TABLE job
+    DROP COLUMN attempts,
+    DROP COLUMN mirror,
+    DROP COLUMN last_error,
+    DROP COLUMN started_at,
+    DROP COLUMN finished_at;
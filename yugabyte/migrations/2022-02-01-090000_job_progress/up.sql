@@ -0,0 +1,19 @@
+-- Your SQL goes here
+ALTER TABLE job
+    ADD COLUMN attempts    INT4 NOT NULL DEFAULT 0,
+    ADD COLUMN mirror      VARCHAR,
+    ADD COLUMN last_error  TEXT,
+    ADD COLUMN started_at  TIMESTAMP,
+    ADD COLUMN finished_at TIMESTAMP;
+
+CREATE TABLE job_progress
+(
+    id              BIGSERIAL PRIMARY KEY,
+    job_id          UUID      NOT NULL REFERENCES job (id) ON DELETE CASCADE,
+    attempt         INT4      NOT NULL,
+    downloaded_size INT4      NOT NULL,
+    mirror          VARCHAR,
+    sampled_at      TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX job_progress_job_id_idx ON job_progress (job_id, attempt, sampled_at);
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX job_progress_sampled_at_idx;
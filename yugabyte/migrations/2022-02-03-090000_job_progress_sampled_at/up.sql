@@ -0,0 +1,2 @@
+-- Your SQL goes here
+CREATE INDEX job_progress_sampled_at_idx ON job_progress (sampled_at);
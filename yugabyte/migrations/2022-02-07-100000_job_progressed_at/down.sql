@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX job_progressed_at_idx;
+
+ALTER TABLE job
+    DROP COLUMN progressed_at;
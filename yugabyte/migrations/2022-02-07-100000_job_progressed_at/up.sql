@@ -0,0 +1,9 @@
+-- Your SQL goes here
+ALTER TABLE job
+    ADD COLUMN progressed_at TIMESTAMP;
+
+UPDATE job
+SET progressed_at = started_at
+WHERE started_at IS NOT NULL;
+
+CREATE INDEX job_progressed_at_idx ON job (progressed_at) WHERE status IN ('Active', 'Stalled');
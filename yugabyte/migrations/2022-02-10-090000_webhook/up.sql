@@ -0,0 +1,28 @@
+-- Your SQL goes here
+CREATE TABLE webhook_subscription
+(
+    id            UUID PRIMARY KEY,
+    url           VARCHAR   NOT NULL,
+    event_types   TEXT[]    NOT NULL,
+    secret        VARCHAR   NOT NULL,
+    creation_date TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE webhook_delivery
+(
+    id               UUID PRIMARY KEY,
+    subscription_id  UUID      NOT NULL REFERENCES webhook_subscription (id) ON DELETE CASCADE,
+    event_type       VARCHAR   NOT NULL,
+    job_id           UUID,
+    payload          JSONB     NOT NULL,
+    status           VARCHAR   NOT NULL DEFAULT 'pending',
+    attempts         INT4      NOT NULL DEFAULT 0,
+    next_attempt_at  TIMESTAMP NOT NULL,
+    last_status_code INT4,
+    last_error       TEXT,
+    creation_date    TIMESTAMP NOT NULL,
+    delivered_at     TIMESTAMP
+);
+
+CREATE INDEX webhook_delivery_subscription_id_idx ON webhook_delivery (subscription_id, creation_date);
+CREATE INDEX webhook_delivery_due_idx ON webhook_delivery (next_attempt_at) WHERE status = 'pending';
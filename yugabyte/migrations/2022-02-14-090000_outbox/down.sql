@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE outbox_publication;
+DROP TABLE outbox_event;
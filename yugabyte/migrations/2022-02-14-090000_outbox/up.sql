@@ -0,0 +1,25 @@
+-- Your SQL goes here
+CREATE TABLE outbox_event
+(
+    id            BIGSERIAL PRIMARY KEY,
+    name          VARCHAR   NOT NULL,
+    job_id        UUID,
+    payload       JSONB     NOT NULL,
+    creation_date TIMESTAMP NOT NULL
+);
+
+CREATE INDEX outbox_event_creation_date_idx ON outbox_event (creation_date);
+
+-- One row per event and sink once the sink was tried. An event is `published` at least once, a `failed` one is
+-- published again after `next_attempt_at` until it is `dead` after its last attempt.
+CREATE TABLE outbox_publication
+(
+    event_id        BIGINT    NOT NULL REFERENCES outbox_event (id) ON DELETE CASCADE,
+    sink            VARCHAR   NOT NULL,
+    status          VARCHAR   NOT NULL,
+    attempts        INT4      NOT NULL,
+    last_error      VARCHAR,
+    next_attempt_at TIMESTAMP,
+    published_at    TIMESTAMP,
+    PRIMARY KEY (event_id, sink)
+);
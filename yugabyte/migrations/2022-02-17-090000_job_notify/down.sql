@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER job_notify_change ON job;
+DROP FUNCTION notify_job_change();
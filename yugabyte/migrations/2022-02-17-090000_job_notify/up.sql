@@ -0,0 +1,36 @@
+-- Your SQL goes here
+-- Every change of a job is notified on `job_changes` so that each server can stream it to its own clients.
+-- The payload stays small, NOTIFY refuses payloads of 8000 bytes or more.
+CREATE FUNCTION notify_job_change() RETURNS TRIGGER AS
+$$
+DECLARE
+    changed_job job;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        changed_job := OLD;
+    ELSE
+        changed_job := NEW;
+    END IF;
+    PERFORM pg_notify('job_changes', json_build_object(
+        'op', lower(TG_OP),
+        'id', changed_job.id,
+        'status', changed_job.status,
+        'is_active', changed_job.is_active,
+        'downloaded_size', changed_job.downloaded_size,
+        'percent_downloaded', changed_job.percent_downloaded,
+        'deleted', changed_job.deleted_at IS NOT NULL,
+        'updated_at', changed_job.updated_at
+    )::text);
+    RETURN NULL;
+EXCEPTION
+    -- The databases without NOTIFY keep working, only without the change feed.
+    WHEN feature_not_supported THEN
+        RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER job_notify_change
+    AFTER INSERT OR UPDATE OR DELETE
+    ON job
+    FOR EACH ROW
+EXECUTE PROCEDURE notify_job_change();
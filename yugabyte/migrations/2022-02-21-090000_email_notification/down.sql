@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE email_notification;
+ALTER TABLE job DROP COLUMN email_digest;
+ALTER TABLE job DROP COLUMN notify_emails;
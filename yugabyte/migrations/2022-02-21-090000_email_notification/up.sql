@@ -0,0 +1,24 @@
+-- Your SQL goes here
+-- Who is told by email when the job completes or fails, and whether in the daily digest.
+ALTER TABLE job ADD COLUMN notify_emails TEXT[] NOT NULL DEFAULT '{}';
+ALTER TABLE job ADD COLUMN email_digest BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE email_notification
+(
+    id              UUID PRIMARY KEY,
+    job_id          UUID      REFERENCES job (id) ON DELETE CASCADE,
+    recipient       VARCHAR   NOT NULL,
+    event_type      VARCHAR   NOT NULL,
+    subject         TEXT      NOT NULL,
+    body            TEXT      NOT NULL,
+    digest          BOOLEAN   NOT NULL,
+    status          VARCHAR   NOT NULL DEFAULT 'pending',
+    attempts        INT4      NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMP NOT NULL,
+    last_error      TEXT,
+    creation_date   TIMESTAMP NOT NULL,
+    sent_at         TIMESTAMP
+);
+
+CREATE INDEX email_notification_due_idx ON email_notification (status, digest, next_attempt_at);
+CREATE INDEX email_notification_creation_date_idx ON email_notification (creation_date);
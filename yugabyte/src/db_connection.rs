@@ -39,6 +39,15 @@ pub fn init_pool(database_url: &str) -> Result<PgPool, PoolError> {
     Pool::builder().build(manager)
 }
 
+// A connection to the database in database_url, for the tests that run in a test transaction.
+#[cfg(test)]
+pub fn test_connection() -> PgConnection {
+    use diesel::Connection;
+
+    let url = std::env::var("DATABASE_URL").unwrap();
+    PgConnection::establish(url.as_str()).expect("Failed to connect to the test DB")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
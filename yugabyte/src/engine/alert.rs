@@ -0,0 +1,20 @@
+use std::time::Duration;
+
+use crate::errors::Error;
+use crate::model::alert::Alert;
+
+const ALERT_TIMEOUT_SECONDS: u64 = 10;
+// The id of the alert, the same for every post of it.
+pub const ALERT_ID: &str = "X-Alert-Id";
+
+// Post the alert as JSON to the webhook of the operators.
+pub fn post_alert(url: &str, alert: &Alert) -> Result<(), Error> {
+    let body = serde_json::to_string(alert).map_err(|e| Error::InternalServerError(e.to_string()))?;
+    ureq::post(url)
+        .timeout(Duration::from_secs(ALERT_TIMEOUT_SECONDS))
+        .set("Content-Type", "application/json")
+        .set(ALERT_ID, &alert.event_id.to_string())
+        .send_string(&body)
+        .map(|_| ())
+        .map_err(|e| Error::HttpRequest(format!("the alert webhook failed: {}", e)))
+}
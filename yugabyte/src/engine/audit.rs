@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+
+use chrono::Duration;
+use diesel::pg::Pg;
+use diesel::sql_types::Text;
+use diesel::{sql_query, Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl};
+use uuid::Uuid;
+
+use crate::errors::Error;
+use crate::model::audit::{AuditFilterDTO, AuditLog, AuditedCall, NewAuditLog};
+use crate::model::general::{PaginatedResponseDTO, PaginationDTO};
+use crate::model::job::Job;
+use crate::schema::{audit_log, job};
+use crate::util::utils::current_timestamp;
+
+pub fn record_audit_logs(new_audit_logs: &[NewAuditLog], connection: &PgConnection) -> Result<usize, Error> {
+    diesel::insert_into(audit_log::table)
+        .values(new_audit_logs)
+        .execute(connection)
+        .map_err(Error::DBError)
+}
+
+fn filtered_audit_logs(filter: &AuditFilterDTO) -> audit_log::BoxedQuery<'static, Pg> {
+    let mut query = audit_log::table.into_boxed();
+    if let Some(principal) = &filter.principal {
+        query = query.filter(audit_log::principal.eq(principal.clone()));
+    }
+    if let Some(action) = &filter.action {
+        query = query.filter(audit_log::action.eq(action.clone()));
+    }
+    if let Some(other_job_id) = filter.job_id {
+        query = query.filter(audit_log::job_id.eq(other_job_id));
+    }
+    if let Some(request_id) = &filter.request_id {
+        query = query.filter(audit_log::request_id.eq(request_id.clone()));
+    }
+    if let Some(result) = &filter.result {
+        query = query.filter(audit_log::result.eq(result.clone()));
+    }
+    if let Some(after) = filter.created_after {
+        query = query.filter(audit_log::creation_date.ge(after));
+    }
+    if let Some(before) = filter.created_before {
+        query = query.filter(audit_log::creation_date.lt(before));
+    }
+    query
+}
+
+// The entries of the audit log matching the filter, the newest first.
+pub fn get_audit_logs(
+    pagination_dto: &PaginationDTO,
+    filter: &AuditFilterDTO,
+    connection: &PgConnection,
+) -> Result<PaginatedResponseDTO<AuditLog>, Error> {
+    let paginated_list = filtered_audit_logs(filter)
+        .order_by(audit_log::id.desc())
+        .limit(pagination_dto.page_size)
+        .offset(pagination_dto.offset)
+        .load::<AuditLog>(connection)
+        .map_err(Error::DBError)?;
+    let count = filtered_audit_logs(filter)
+        .count()
+        .get_result::<i64>(connection)
+        .map_err(Error::DBError)?;
+
+    Ok(PaginatedResponseDTO {
+        paginated_list,
+        count: Some(count),
+        next: None,
+        prev: None,
+    })
+}
+
+// Drop the entries older than `retention_days`. The log refuses any other delete, the period is handed to it
+// for the transaction.
+pub fn purge_audit_logs(retention_days: i64, connection: &PgConnection) -> Result<usize, Error> {
+    let cutoff = current_timestamp() - Duration::days(retention_days);
+    connection.transaction(|| {
+        sql_query("SELECT set_config('audit_log.retention_days', $1, true)")
+            .bind::<Text, _>(retention_days.to_string())
+            .execute(connection)
+            .map_err(Error::DBError)?;
+        diesel::delete(audit_log::table.filter(audit_log::creation_date.lt(cutoff)))
+            .execute(connection)
+            .map_err(Error::DBError)
+    })
+}
+
+// The jobs as they are now, the ones in the trash included, to keep them in the audit log.
+pub fn snapshot_jobs(other_job_ids: &[Uuid], connection: &PgConnection) -> Result<Vec<Job>, Error> {
+    job::table
+        .filter(job::id.eq_any(other_job_ids))
+        .load::<Job>(connection)
+        .map_err(Error::DBError)
+}
+
+// One entry for every job the call changed, as it was before and as it is now, or one for the call when it
+// changed none.
+fn record_audited_call(audited_call: AuditedCall, before: Vec<Job>, connection: &PgConnection) -> Result<usize, Error> {
+    let AuditedCall { audit_log, job_ids } = audited_call;
+    if job_ids.is_empty() {
+        return record_audit_logs(&[audit_log], connection);
+    }
+    let mut before: HashMap<Uuid, Job> = before.into_iter().map(|found_job| (found_job.id, found_job)).collect();
+    let mut after: HashMap<Uuid, Job> = snapshot_jobs(&job_ids, connection)?
+        .into_iter()
+        .map(|found_job| (found_job.id, found_job))
+        .collect();
+    let new_audit_logs: Vec<NewAuditLog> = job_ids
+        .iter()
+        .map(|job_id| NewAuditLog {
+            job_id: Some(*job_id),
+            before: before.remove(job_id).and_then(|found_job| serde_json::to_value(found_job).ok()),
+            after: after.remove(job_id).and_then(|found_job| serde_json::to_value(found_job).ok()),
+            ..audit_log.clone()
+        })
+        .collect();
+    record_audit_logs(&new_audit_logs, connection)
+}
+
+// Make the change with the jobs locked and read as they were before it, and write its audit log in the
+// transaction making it: a change is never kept without its entries. The jobs in the trash are included.
+pub fn audited_change<T, F, A>(other_job_ids: &[Uuid], connection: &PgConnection, change: F, audit: A) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+    A: FnOnce(&T) -> AuditedCall,
+{
+    connection.transaction(|| {
+        let before = job::table
+            .filter(job::id.eq_any(other_job_ids))
+            .order_by(job::id)
+            .for_update()
+            .load::<Job>(connection)
+            .map_err(Error::DBError)?;
+        let changed = change()?;
+        record_audited_call(audit(&changed), before, connection)?;
+        Ok(changed)
+    })
+}
+
+// The same for the live job of the reference, there is none before a change creating it.
+pub fn audited_change_by_external_ref<T, F, A>(
+    other_external_ref: &str,
+    connection: &PgConnection,
+    change: F,
+    audit: A,
+) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+    A: FnOnce(&T) -> AuditedCall,
+{
+    connection.transaction(|| {
+        let before = job::table
+            .filter(job::external_ref.eq(other_external_ref))
+            .filter(job::deleted_at.is_null())
+            .for_update()
+            .get_result::<Job>(connection)
+            .optional()
+            .map_err(Error::DBError)?;
+        let changed = change()?;
+        record_audited_call(audit(&changed), before.into_iter().collect(), connection)?;
+        Ok(changed)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::sql_types::Timestamp;
+
+    use crate::db_connection::test_connection;
+    use crate::engine::job::{add_test_job, set_activate_job};
+    use crate::model::audit::AUDIT_SUCCESS;
+
+    use super::*;
+
+    fn test_audit_log(request_id: &str) -> NewAuditLog {
+        NewAuditLog {
+            request_id: request_id.to_string(),
+            principal: "test".to_string(),
+            source_ip: None,
+            method: "DELETE".to_string(),
+            path: "/api/v1/jobs/1".to_string(),
+            action: "delete".to_string(),
+            job_id: None,
+            before: None,
+            after: None,
+            status_code: 204,
+            result: AUDIT_SUCCESS.to_string(),
+        }
+    }
+
+    fn audit_log_count(connection: &PgConnection) -> Result<i64, Error> {
+        audit_log::table.count().get_result::<i64>(connection).map_err(Error::DBError)
+    }
+
+    #[test]
+    fn audit_log_only_loses_the_entries_past_the_retention() {
+        let connection = test_connection();
+        connection.test_transaction::<_, Error, _>(|| {
+            record_audit_logs(&[test_audit_log("recent request")], &connection)?;
+            sql_query("INSERT INTO audit_log (creation_date, request_id, principal, method, path, action, status_code, result)
+                SELECT $1, 'old request', principal, method, path, action, status_code, result FROM audit_log")
+                .bind::<Timestamp, _>(current_timestamp() - Duration::days(40))
+                .execute(&connection)?;
+            let count = audit_log_count(&connection)?;
+
+            // every statement is tried in its own savepoint, a refused one would abort the test transaction.
+            let refused = |statement: &str| connection.transaction(|| sql_query(statement).execute(&connection)).is_err();
+            assert!(refused("UPDATE audit_log SET principal = 'someone else'"));
+            assert!(refused("DELETE FROM audit_log"));
+            assert!(refused("TRUNCATE audit_log"));
+            assert_eq!(audit_log_count(&connection)?, count);
+
+            assert_eq!(purge_audit_logs(30, &connection)?, 1);
+            assert_eq!(audit_log_count(&connection)?, count - 1);
+            assert!(refused("DELETE FROM audit_log"));
+            Ok(())
+        });
+    }
+
+    fn deactivated_call(job_ids: Vec<Uuid>, request_id: &str) -> AuditedCall {
+        AuditedCall {
+            audit_log: NewAuditLog {
+                method: "PUT".to_string(),
+                action: "activate".to_string(),
+                status_code: 200,
+                ..test_audit_log(request_id)
+            },
+            job_ids,
+        }
+    }
+
+    #[test]
+    fn audited_change_logs_the_jobs_before_and_after_the_change() {
+        let connection = test_connection();
+        connection.test_transaction::<_, Error, _>(|| {
+            let audited_job = add_test_job("audited job", 1000, &connection);
+
+            let changed = audited_change(
+                &[audited_job.id],
+                &connection,
+                || set_activate_job(&audited_job.id, false, "test", &connection),
+                |_| deactivated_call(vec![audited_job.id], "deactivating request"),
+            )?;
+
+            assert_eq!(changed, 1);
+            let audit_logs = audit_log::table
+                .filter(audit_log::request_id.eq("deactivating request"))
+                .load::<AuditLog>(&connection)?;
+            assert_eq!(audit_logs.len(), 1);
+            assert_eq!(audit_logs[0].job_id, Some(audited_job.id));
+            assert_eq!(audit_logs[0].before.as_ref().map(|before| before["is_active"].clone()), Some(true.into()));
+            assert_eq!(audit_logs[0].after.as_ref().map(|after| after["is_active"].clone()), Some(false.into()));
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn a_failed_audit_write_rolls_the_change_back() {
+        let connection = test_connection();
+        connection.test_transaction::<_, Error, _>(|| {
+            let audited_job = add_test_job("audited job", 1000, &connection);
+            let count = audit_log_count(&connection)?;
+
+            // the database refuses the NUL character of the request id.
+            let changed = audited_change(
+                &[audited_job.id],
+                &connection,
+                || set_activate_job(&audited_job.id, false, "test", &connection),
+                |_| deactivated_call(vec![audited_job.id], "broken\0request"),
+            );
+
+            assert!(matches!(changed, Err(Error::DBError(_))));
+            assert!(snapshot_jobs(&[audited_job.id], &connection)?[0].is_active);
+            assert_eq!(audit_log_count(&connection)?, count);
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,325 @@
+use diesel::result::Error as DieselError;
+use diesel::{Connection, PgConnection};
+use uuid::Uuid;
+
+use crate::engine::job::{create_job, delete_job_by_id, find_job_ids, requeue_job, set_activate_job};
+use crate::errors::StateCode::{
+    BulkSelectionError, BulkTooLarge, DeletedDuplicationError, DuplicationError, InternalServerError, NotFound, RolledBack,
+};
+use crate::errors::{Error, ErrorCode};
+use crate::model::bulk::{
+    BulkAction, BulkCreateDTO, BulkItemResult, BulkMode, BulkResultDTO, BulkSelectionDTO, MAX_BULK_JOBS,
+};
+use crate::model::job::Job;
+use crate::storage::StorageConfig;
+
+// The errors of an item as the client sees them.
+fn item_errors(e: Error) -> Vec<ErrorCode> {
+    match e {
+        Error::ValidationError(errors) => errors,
+        Error::DBError(DieselError::NotFound) => vec![NotFound.into()],
+        Error::DuplicationError => vec![DuplicationError.into()],
+        Error::Conflict(state_code) => vec![state_code.into()],
+        Error::DeletedDuplicationError => vec![DeletedDuplicationError.into()],
+        _ => vec![InternalServerError.into()],
+    }
+}
+
+fn item_result(index: usize, id: Option<Uuid>, result: Result<Uuid, Error>) -> BulkItemResult {
+    match result {
+        Ok(other_id) => BulkItemResult {
+            index,
+            id: Some(other_id),
+            success: true,
+            errors: Vec::new(),
+        },
+        Err(e) => BulkItemResult {
+            index,
+            id,
+            success: false,
+            errors: item_errors(e),
+        },
+    }
+}
+
+fn bulk_result(mode: BulkMode, committed: bool, mut results: Vec<BulkItemResult>) -> BulkResultDTO {
+    // the items that went through are undone with the batch.
+    if !committed {
+        for result in results.iter_mut().filter(|result| result.success) {
+            result.success = false;
+            result.errors = vec![RolledBack.into()];
+        }
+    }
+    let succeeded = results.iter().filter(|result| result.success).count();
+    BulkResultDTO {
+        mode,
+        committed,
+        succeeded,
+        failed: results.len() - succeeded,
+        results,
+    }
+}
+
+// A transactional batch with invalid items is never inserted, so none of its items gets an id.
+fn rejected_result(built_ids: Vec<Result<Uuid, Error>>) -> BulkResultDTO {
+    let results = built_ids
+        .into_iter()
+        .enumerate()
+        .map(|(index, built_id)| {
+            let mut result = item_result(index, None, built_id);
+            result.id = None;
+            result
+        })
+        .collect();
+    bulk_result(BulkMode::Transactional, false, results)
+}
+
+// Apply `apply` to every item, each in its own savepoint so a failed item leaves the others untouched.
+// A transactional batch is rolled back as a whole once an item failed.
+fn run_bulk<T, I, F>(
+    mode: BulkMode,
+    items: &[T],
+    item_id: I,
+    apply: F,
+    connection: &PgConnection,
+) -> Result<BulkResultDTO, Error>
+where
+    I: Fn(&T) -> Option<Uuid>,
+    F: Fn(&T) -> Result<Uuid, Error>,
+{
+    let mut results = Vec::with_capacity(items.len());
+    let run_items = |results: &mut Vec<BulkItemResult>| {
+        for (index, item) in items.iter().enumerate() {
+            let result = connection.transaction(|| apply(item));
+            results.push(item_result(index, item_id(item), result));
+        }
+    };
+
+    let committed = match mode {
+        BulkMode::BestEffort => {
+            run_items(&mut results);
+            true
+        }
+        BulkMode::Transactional => {
+            let outcome = connection.transaction::<_, Error, _>(|| {
+                run_items(&mut results);
+                if results.iter().any(|result| !result.success) {
+                    return Err(Error::DBError(DieselError::RollbackTransaction));
+                }
+                Ok(())
+            });
+            match outcome {
+                Ok(()) => true,
+                Err(Error::DBError(DieselError::RollbackTransaction)) => false,
+                Err(e) => return Err(e),
+            }
+        }
+    };
+    Ok(bulk_result(mode, committed, results))
+}
+
+// Create many jobs, a transactional batch is inserted only after every job was checked.
+pub fn bulk_create_jobs(
+    bulk_create_dto: &BulkCreateDTO,
+    storage_config: &StorageConfig,
+    actor: &str,
+    connection: &PgConnection,
+) -> Result<BulkResultDTO, Error> {
+    let new_jobs = &bulk_create_dto.jobs;
+    if new_jobs.len() > MAX_BULK_JOBS {
+        return Err(Error::ValidationError(vec![BulkTooLarge.into()]));
+    }
+
+    if bulk_create_dto.mode == BulkMode::BestEffort {
+        return run_bulk(
+            BulkMode::BestEffort,
+            new_jobs,
+            |_| None,
+            |new_job| new_job.add_job(storage_config, actor, connection).map(|added_job| added_job.id),
+            connection,
+        );
+    }
+
+    // the batch is checked and inserted in one transaction, a failed item undoes the others.
+    connection.transaction(|| {
+        // Step 1: build and check every job, each item reports its own problems.
+        let built_jobs: Vec<Result<Job, Error>> = new_jobs
+            .iter()
+            .map(|new_job| new_job.to_job(storage_config, connection))
+            .collect();
+        if built_jobs.iter().any(Result::is_err) {
+            let built_ids = built_jobs
+                .into_iter()
+                .map(|built_job| built_job.map(|other_job| other_job.id))
+                .collect();
+            return Ok(rejected_result(built_ids));
+        }
+
+        // Step 2: insert each job with its events, so a refused job is told apart from the others.
+        let other_jobs: Vec<Job> = built_jobs.into_iter().filter_map(Result::ok).collect();
+        let mut bulk = run_bulk(
+            BulkMode::Transactional,
+            &other_jobs,
+            |_| None,
+            |other_job| create_job(other_job, actor, connection).map(|created_job| created_job.id),
+            connection,
+        )?;
+        // a rolled back job was never created, so it has no id.
+        if !bulk.committed {
+            for result in bulk.results.iter_mut() {
+                result.id = None;
+            }
+        }
+        Ok(bulk)
+    })
+}
+
+// The ids of the jobs a bulk action applies to.
+pub fn select_bulk_jobs(
+    bulk_selection_dto: &BulkSelectionDTO,
+    connection: &PgConnection,
+) -> Result<Vec<Uuid>, Error> {
+    let ids = match (&bulk_selection_dto.ids, &bulk_selection_dto.filter) {
+        (Some(ids), None) => ids.clone(),
+        (None, Some(filter)) => {
+            filter.check().map_err(Error::ValidationError)?;
+            find_job_ids(filter, MAX_BULK_JOBS as i64 + 1, connection)?
+        }
+        _ => return Err(Error::ValidationError(vec![BulkSelectionError.into()])),
+    };
+    if ids.len() > MAX_BULK_JOBS {
+        return Err(Error::ValidationError(vec![BulkTooLarge.into()]));
+    }
+    Ok(ids)
+}
+
+pub fn bulk_update_jobs(
+    action: BulkAction,
+    ids: &[Uuid],
+    mode: BulkMode,
+    actor: &str,
+    connection: &PgConnection,
+) -> Result<BulkResultDTO, Error> {
+    run_bulk(
+        mode,
+        ids,
+        |other_job_id| Some(*other_job_id),
+        |other_job_id| {
+            let changed = match action {
+                BulkAction::Activate => set_activate_job(other_job_id, true, actor, connection)?,
+                BulkAction::Deactivate => set_activate_job(other_job_id, false, actor, connection)?,
+                BulkAction::Delete => delete_job_by_id(other_job_id, None, actor, connection).map(|_| 1)?,
+                BulkAction::Requeue => requeue_job(other_job_id, actor, connection)?,
+            };
+            if changed == 0 {
+                return Err(Error::DBError(DieselError::NotFound));
+            }
+            Ok(*other_job_id)
+        },
+        connection,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+
+    use crate::db_connection::test_connection;
+    use crate::errors::StateCode::InvalidChecksum;
+    use crate::model::job::NewJob;
+    use crate::schema::job;
+    use crate::storage::local::LocalStorage;
+    use crate::storage::LOCAL_BACKEND;
+
+    use super::*;
+
+    fn error_codes(result: &BulkItemResult) -> Vec<&str> {
+        result.errors.iter().map(|error| error.error_code.as_str()).collect()
+    }
+
+    #[test]
+    fn committed_batch_keeps_the_outcome_of_every_item() {
+        let id = Uuid::new_v4();
+        let results = vec![
+            item_result(0, Some(id), Ok(id)),
+            item_result(1, None, Err(Error::DuplicationError)),
+        ];
+
+        let bulk = bulk_result(BulkMode::BestEffort, true, results);
+
+        assert!(bulk.committed);
+        assert_eq!((bulk.succeeded, bulk.failed), (1, 1));
+        assert!(bulk.results[0].success && bulk.results[0].errors.is_empty());
+        assert_eq!(bulk.results[0].id, Some(id));
+        assert_eq!(error_codes(&bulk.results[1]), vec!["duplication-error"]);
+    }
+
+    #[test]
+    fn rolled_back_batch_undoes_the_items_that_went_through() {
+        let id = Uuid::new_v4();
+        let missing_id = Uuid::new_v4();
+        let results = vec![
+            item_result(0, Some(id), Ok(id)),
+            item_result(1, Some(missing_id), Err(Error::DBError(DieselError::NotFound))),
+        ];
+
+        let bulk = bulk_result(BulkMode::Transactional, false, results);
+
+        assert!(!bulk.committed);
+        assert_eq!((bulk.succeeded, bulk.failed), (0, 2));
+        assert!(!bulk.results[0].success);
+        assert_eq!(error_codes(&bulk.results[0]), vec!["rolled-back"]);
+        assert_eq!(error_codes(&bulk.results[1]), vec!["not-found"]);
+    }
+
+    #[test]
+    fn rejected_batch_gives_no_item_an_id() {
+        let built_ids = vec![
+            Ok(Uuid::new_v4()),
+            Err(Error::ValidationError(vec![InvalidChecksum.into()])),
+        ];
+
+        let bulk = rejected_result(built_ids);
+
+        assert!(!bulk.committed);
+        assert_eq!((bulk.succeeded, bulk.failed), (0, 2));
+        assert!(bulk.results.iter().all(|result| result.id.is_none()));
+        assert_eq!(error_codes(&bulk.results[0]), vec!["rolled-back"]);
+        assert_eq!(error_codes(&bulk.results[1]), vec!["invalid-checksum"]);
+    }
+
+    #[test]
+    fn failed_transactional_batch_inserts_nothing() {
+        let connection = test_connection();
+        let storage_config = StorageConfig {
+            default_backend: LOCAL_BACKEND.to_string(),
+            default_bucket: None,
+            local: LocalStorage::new(std::env::temp_dir()),
+            s3: None,
+        };
+        let new_job = |other_name: &str| NewJob {
+            name: other_name.to_string(),
+            total_size: 10,
+            ..NewJob::default()
+        };
+        let bulk_create_dto = BulkCreateDTO {
+            jobs: vec![new_job("bulk rollback job"), new_job("Bulk Rollback Job")],
+            mode: BulkMode::Transactional,
+        };
+
+        connection.test_transaction::<_, Error, _>(|| {
+            let bulk = bulk_create_jobs(&bulk_create_dto, &storage_config, "test", &connection)?;
+
+            assert!(!bulk.committed);
+            assert!(bulk.results.iter().all(|result| result.id.is_none()));
+            assert_eq!(error_codes(&bulk.results[0]), vec!["rolled-back"]);
+            assert_eq!(error_codes(&bulk.results[1]), vec!["duplicate-name"]);
+            let inserted = job::table
+                .filter(job::name.eq_any(vec!["bulk rollback job", "Bulk Rollback Job"]))
+                .count()
+                .get_result::<i64>(&connection)?;
+            assert_eq!(inserted, 0);
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,180 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+
+use diesel::{ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::engine::event::record_job_event;
+use crate::engine::job::complete_job;
+use crate::errors::Error;
+use crate::errors::StateCode::FileTooLarge;
+use crate::model::event::{JobEventKind, SYSTEM_ACTOR};
+use crate::model::job::Job;
+use crate::schema::job;
+use crate::storage::{StorageBackend, StorageConfig};
+
+const CAS_DIR: &str = "cas";
+
+// Hash the file with SHA-256, returns the lowercase hex digest.
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+// Check that the checksum is a SHA-256 hex digest, returns it in lowercase.
+pub fn normalize_checksum(other_checksum: &str) -> Result<String, Error> {
+    let normalized = other_checksum.trim().to_lowercase();
+    if normalized.len() == 64 && normalized.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(normalized)
+    } else {
+        Err(Error::BadRequest(format!("{} is not a SHA-256 checksum.", other_checksum)))
+    }
+}
+
+// Key of the blob in the storage backend, e.g. `cas/ab/ab12...`.
+pub fn cas_key(other_checksum: &str) -> String {
+    format!("{}/{}/{}", CAS_DIR, &other_checksum[..2], other_checksum)
+}
+
+// Returns the key and the size of the blob if the backend already holds it.
+pub fn find_blob(
+    backend: &dyn StorageBackend,
+    bucket: Option<&str>,
+    other_checksum: &str,
+) -> Result<Option<(String, u64)>, Error> {
+    let key = cas_key(other_checksum);
+    Ok(backend.object_size(bucket, &key)?.map(|size| (key, size)))
+}
+
+// The size of a blob as the job records it, a blob too large for the job is refused.
+pub fn job_size(blob_size: u64) -> Result<i32, Error> {
+    i32::try_from(blob_size).map_err(|_| Error::ValidationError(vec![FileTooLarge.into()]))
+}
+
+// Move the file into the store, the file is dropped if the store already holds the same content.
+// Returns the checksum of the file and the key of its blob.
+pub fn store_file(
+    backend: &dyn StorageBackend,
+    bucket: Option<&str>,
+    source: &Path,
+) -> Result<(String, String), Error> {
+    // Step 1: hash the downloaded file.
+    let other_checksum = sha256_file(source).map_err(|e| Error::InternalServerError(e.to_string()))?;
+    let key = cas_key(&other_checksum);
+
+    // Step 2: keep one copy of the content.
+    if backend.object_size(bucket, &key)?.is_some() {
+        fs::remove_file(source).map_err(|e| Error::InternalServerError(e.to_string()))?;
+    } else {
+        backend.put_file(bucket, &key, source)?;
+    }
+
+    Ok((other_checksum, key))
+}
+
+// Find the name a blob was first downloaded under, so deduplicated jobs keep a useful file name.
+pub fn find_blob_file_name(
+    other_checksum: &str,
+    connection: &PgConnection,
+) -> Result<Option<String>, Error> {
+    job::table
+        .filter(job::checksum.eq(other_checksum))
+        .filter(job::file_name.is_not_null())
+        .select(job::file_name)
+        .first::<Option<String>>(connection)
+        .optional()
+        .map(Option::flatten)
+        .map_err(Error::DBError)
+}
+
+// Store the downloaded file of the job in the content-addressed store, then complete the job.
+pub fn complete_job_with_file(
+    other_job_id: &Uuid,
+    source: &Path,
+    storage_config: &StorageConfig,
+    connection: &PgConnection,
+) -> Result<Job, Error> {
+    // Step 1: a job that expects a checksum must get exactly that content.
+    let found_job = job::table
+        .find(other_job_id)
+        .get_result::<Job>(connection)
+        .map_err(Error::DBError)?;
+    if let Some(expected) = &found_job.expected_checksum {
+        let actual = sha256_file(source).map_err(|e| Error::InternalServerError(e.to_string()))?;
+        if &actual != expected {
+            let message = format!(
+                "The file of the job {} has the checksum {}, expected {}.",
+                other_job_id, actual, expected
+            );
+            let details = json!({ "message": message, "checksum": actual, "expected_checksum": expected });
+            record_job_event(other_job_id, JobEventKind::Error, SYSTEM_ACTOR, details, connection)?;
+            return Err(Error::BadRequest(message));
+        }
+    }
+
+    // Step 2: move the file into the backend of the job and reference it from the job.
+    let backend = storage_config.backend(&found_job.storage_backend)?;
+    let source_file_name = source.file_name().map(|name| name.to_string_lossy().to_string());
+    let source_size = fs::metadata(source).map_err(|e| Error::InternalServerError(e.to_string()))?.len();
+    let other_size = job_size(source_size)?;
+    let (other_checksum, key) = store_file(backend, found_job.bucket.as_deref(), source)?;
+    complete_job(
+        other_job_id,
+        &key,
+        &other_checksum,
+        source_file_name.as_deref(),
+        other_size,
+        SYSTEM_ACTOR,
+        connection,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::local::LocalStorage;
+
+    use super::*;
+
+    #[test]
+    fn same_content_is_stored_once() {
+        let download_dir = std::env::temp_dir().join(format!("cas-{}", Uuid::new_v4()));
+        fs::create_dir_all(&download_dir).unwrap();
+        let backend = LocalStorage::new(download_dir.clone());
+        let first = download_dir.join("first.bin");
+        let second = download_dir.join("second.bin");
+        fs::write(&first, b"same content").unwrap();
+        fs::write(&second, b"same content").unwrap();
+
+        let (first_checksum, first_key) = store_file(&backend, None, &first).unwrap();
+        let (second_checksum, second_key) = store_file(&backend, None, &second).unwrap();
+
+        assert_eq!(first_checksum, second_checksum);
+        assert_eq!(first_key, second_key);
+        assert_eq!(find_blob(&backend, None, &first_checksum).unwrap(), Some((first_key.clone(), 12)));
+        assert!(!first.exists() && !second.exists());
+        assert_eq!(job_size(12).unwrap(), 12);
+        assert!(job_size(2 * 1024 * 1024 * 1024).is_err());
+        assert_eq!(fs::read(download_dir.join(&first_key)).unwrap(), b"same content");
+        fs::remove_dir_all(&download_dir).unwrap();
+    }
+
+    #[test]
+    fn checksums_are_normalized() {
+        let upper = "A".repeat(64);
+        assert_eq!(normalize_checksum(&upper).unwrap(), "a".repeat(64));
+        assert!(normalize_checksum("abc").is_err());
+        assert!(normalize_checksum(&"g".repeat(64)).is_err());
+    }
+}
@@ -0,0 +1,419 @@
+use std::time::Duration as StdDuration;
+
+use chrono::{Duration, NaiveDateTime, NaiveTime};
+use diesel::{Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl};
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::engine::webhook::retry_delay;
+use crate::errors::Error;
+use crate::model::email::{
+    render_template, EmailNotification, EmailTemplates, NewEmailNotification, SmtpSettings, SmtpTls, EMAIL_FAILED,
+    EMAIL_PENDING, EMAIL_SENT,
+};
+use crate::model::job::Job;
+use crate::model::outbox::OutboxEvent;
+use crate::schema::email_notification;
+use crate::util::utils::current_timestamp;
+
+const SMTP_TIMEOUT_SECONDS: u64 = 10;
+// A claimed email is tried again after this lease when its server died while sending it,
+// it outlasts the timeouts of one email.
+const EMAIL_LEASE_SECONDS: i64 = 60;
+// The most emails, or digests, a server sends in one run and the most emails in a digest.
+const EMAIL_BATCH_SIZE: i64 = 100;
+
+// Queue the emails of a completed or failed job for its recipients, the relay calls it in the transaction
+// recording the publication so that an event is emailed once.
+pub fn enqueue_job_emails(
+    outbox_event: &OutboxEvent,
+    templates: &EmailTemplates,
+    connection: &PgConnection,
+) -> Result<usize, Error> {
+    let template = match outbox_event.name.as_str() {
+        "JobCompleted" => &templates.completed,
+        "JobFailed" => &templates.failed,
+        _ => return Ok(0),
+    };
+    let job_value = match outbox_event.payload.get("job") {
+        Some(job_value) => job_value,
+        None => return Ok(0),
+    };
+    let notified_job: Job = match serde_json::from_value(job_value.clone()) {
+        Ok(notified_job) => notified_job,
+        Err(_) => return Ok(0),
+    };
+    if notified_job.notify_emails.is_empty() {
+        return Ok(0);
+    }
+
+    let now = current_timestamp();
+    let subject = render_template(&template.subject, job_value);
+    let body = render_template(&template.body, job_value);
+    let new_emails: Vec<NewEmailNotification> = notified_job
+        .notify_emails
+        .iter()
+        .map(|recipient| NewEmailNotification {
+            id: Uuid::new_v4(),
+            job_id: Some(notified_job.id),
+            recipient: recipient.clone(),
+            event_type: outbox_event.event_type().unwrap_or_default().to_string(),
+            subject: subject.clone(),
+            body: body.clone(),
+            digest: notified_job.email_digest,
+            next_attempt_at: now,
+            creation_date: now,
+        })
+        .collect();
+    diesel::insert_into(email_notification::table)
+        .values(&new_emails)
+        .execute(connection)
+        .map_err(Error::DBError)
+}
+
+// The last time the daily digest was due, today at `digest_hour` UTC or yesterday before that hour.
+pub fn last_digest_time(now: NaiveDateTime, digest_hour: u32) -> NaiveDateTime {
+    let today = now.date().and_time(NaiveTime::from_hms(digest_hour.min(23), 0, 0));
+    if today <= now {
+        today
+    } else {
+        today - Duration::days(1)
+    }
+}
+
+// Lease the next due emails so that no other server sends them at the same time. Without `digest_cutoff`
+// this is the next email sent at once, with it the digest emails of the next recipient queued before the
+// cutoff. The emails are claimed right before they are sent, so the lease only has to outlast one email.
+fn claim_due_emails(
+    digest_cutoff: Option<NaiveDateTime>,
+    connection: &PgConnection,
+) -> Result<Vec<EmailNotification>, Error> {
+    connection.transaction(|| {
+        let now = current_timestamp();
+        let next_email = email_notification::table
+            .filter(email_notification::status.eq(EMAIL_PENDING))
+            .filter(email_notification::next_attempt_at.le(now))
+            .filter(email_notification::digest.eq(digest_cutoff.is_some()))
+            .filter(email_notification::creation_date.le(digest_cutoff.unwrap_or(now)))
+            .order_by(email_notification::creation_date.asc())
+            .limit(1)
+            .for_update()
+            .skip_locked()
+            .get_result::<EmailNotification>(connection)
+            .optional()
+            .map_err(Error::DBError)?;
+        let next_email = match next_email {
+            Some(next_email) => next_email,
+            None => return Ok(Vec::new()),
+        };
+        let due_emails = match digest_cutoff {
+            None => vec![next_email],
+            Some(digest_cutoff) => email_notification::table
+                .filter(email_notification::status.eq(EMAIL_PENDING))
+                .filter(email_notification::next_attempt_at.le(now))
+                .filter(email_notification::digest.eq(true))
+                .filter(email_notification::creation_date.le(digest_cutoff))
+                .filter(email_notification::recipient.eq(&next_email.recipient))
+                .order_by(email_notification::creation_date.asc())
+                .limit(EMAIL_BATCH_SIZE)
+                .for_update()
+                .skip_locked()
+                .load::<EmailNotification>(connection)
+                .map_err(Error::DBError)?,
+        };
+        let email_ids: Vec<Uuid> = due_emails.iter().map(|email| email.id).collect();
+        diesel::update(email_notification::table.filter(email_notification::id.eq_any(&email_ids)))
+            .set(email_notification::next_attempt_at.eq(now + Duration::seconds(EMAIL_LEASE_SECONDS)))
+            .execute(connection)
+            .map_err(Error::DBError)?;
+        Ok(due_emails)
+    })
+}
+
+fn smtp_transport(smtp_settings: &SmtpSettings) -> Result<SmtpTransport, String> {
+    let builder = match smtp_settings.tls {
+        SmtpTls::None => SmtpTransport::builder_dangerous(&smtp_settings.host),
+        SmtpTls::StartTls => SmtpTransport::starttls_relay(&smtp_settings.host).map_err(|e| e.to_string())?,
+        SmtpTls::Tls => SmtpTransport::relay(&smtp_settings.host).map_err(|e| e.to_string())?,
+    };
+    let mut builder = builder
+        .port(smtp_settings.port)
+        .timeout(Some(StdDuration::from_secs(SMTP_TIMEOUT_SECONDS)));
+    if let (Some(username), Some(password)) = (&smtp_settings.username, &smtp_settings.password) {
+        builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+    Ok(builder.build())
+}
+
+fn send_email(smtp_settings: &SmtpSettings, recipient: &str, subject: &str, body: &str) -> Result<(), String> {
+    let from = smtp_settings.from.parse::<Mailbox>().map_err(|e| format!("the sender is not valid: {}", e))?;
+    let to = recipient.parse::<Mailbox>().map_err(|e| format!("the recipient is not valid: {}", e))?;
+    let message = Message::builder()
+        .from(from)
+        .to(to)
+        .subject(subject)
+        .header(ContentType::TEXT_PLAIN)
+        .body(body.to_string())
+        .map_err(|e| e.to_string())?;
+    smtp_transport(smtp_settings)?
+        .send(&message)
+        .map(|_| ())
+        .map_err(|e| format!("the SMTP server refused the email: {}", e))
+}
+
+// Record how sending went, a failed email is tried again later until it used `max_attempts`.
+fn record_outcome(
+    email: &EmailNotification,
+    sent: &Result<(), String>,
+    max_attempts: i32,
+    connection: &PgConnection,
+) -> Result<(), Error> {
+    let attempts = email.attempts + 1;
+    let now = current_timestamp();
+    let updated = match sent {
+        Ok(()) => diesel::update(email_notification::table.find(email.id))
+            .set((
+                email_notification::status.eq(EMAIL_SENT),
+                email_notification::attempts.eq(attempts),
+                email_notification::last_error.eq(Option::<String>::None),
+                email_notification::sent_at.eq(Some(now)),
+            ))
+            .execute(connection),
+        Err(message) => {
+            let status = if attempts >= max_attempts { EMAIL_FAILED } else { EMAIL_PENDING };
+            diesel::update(email_notification::table.find(email.id))
+                .set((
+                    email_notification::status.eq(status),
+                    email_notification::attempts.eq(attempts),
+                    email_notification::next_attempt_at.eq(now + retry_delay(attempts)),
+                    email_notification::last_error.eq(Some(message)),
+                ))
+                .execute(connection)
+        }
+    };
+    updated.map(|_| ()).map_err(Error::DBError)
+}
+
+// Send the due emails that do not wait for the digest. The number of sent and of failed emails is returned.
+pub fn send_due_emails(
+    smtp_settings: &SmtpSettings,
+    max_attempts: i32,
+    connection: &PgConnection,
+) -> Result<(usize, usize), Error> {
+    let mut outcome = (0, 0);
+    for _ in 0..EMAIL_BATCH_SIZE {
+        let email = match claim_due_emails(None, connection)?.pop() {
+            Some(email) => email,
+            None => break,
+        };
+        let sent = send_email(smtp_settings, &email.recipient, &email.subject, &email.body);
+        if sent.is_ok() {
+            outcome.0 += 1;
+        } else {
+            outcome.1 += 1;
+        }
+        record_outcome(&email, &sent, max_attempts, connection)?;
+    }
+    Ok(outcome)
+}
+
+// The digest of a recipient: one line per email, the subject of the digest may use `{{count}}` and `{{date}}`.
+pub fn digest_email(subject_template: &str, digest_time: NaiveDateTime, emails: &[&EmailNotification]) -> (String, String) {
+    let subject = render_template(
+        subject_template,
+        &json!({ "count": emails.len(), "date": digest_time.date().to_string() }),
+    );
+    let mut body = String::from("Hello,\n\nWhat happened to your downloads since the last digest:\n\n");
+    for email in emails {
+        body.push_str(&format!("- {} ({})\n", email.subject, email.creation_date.format("%Y-%m-%d %H:%M UTC")));
+    }
+    (subject, body)
+}
+
+// Send the digest emails queued before the last digest time, one email per recipient at a time.
+// The number of sent and of failed digests is returned.
+pub fn send_due_digests(
+    smtp_settings: &SmtpSettings,
+    subject_template: &str,
+    digest_hour: u32,
+    max_attempts: i32,
+    connection: &PgConnection,
+) -> Result<(usize, usize), Error> {
+    let digest_time = last_digest_time(current_timestamp(), digest_hour);
+    let mut outcome = (0, 0);
+    for _ in 0..EMAIL_BATCH_SIZE {
+        let due_emails = claim_due_emails(Some(digest_time), connection)?;
+        let recipient = match due_emails.first() {
+            Some(email) => email.recipient.clone(),
+            None => break,
+        };
+        let emails: Vec<&EmailNotification> = due_emails.iter().collect();
+        let (subject, body) = digest_email(subject_template, digest_time, &emails);
+        let sent = send_email(smtp_settings, &recipient, &subject, &body);
+        if sent.is_ok() {
+            outcome.0 += 1;
+        } else {
+            outcome.1 += 1;
+        }
+        for email in emails {
+            record_outcome(email, &sent, max_attempts, connection)?;
+        }
+    }
+    Ok(outcome)
+}
+
+// Drop the emails queued before the retention period, whatever became of them.
+pub fn purge_email_notifications(retention_days: i64, connection: &PgConnection) -> Result<usize, Error> {
+    let cutoff = current_timestamp() - Duration::days(retention_days);
+    diesel::delete(email_notification::table.filter(email_notification::creation_date.lt(cutoff)))
+        .execute(connection)
+        .map_err(Error::DBError)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use crate::db_connection::test_connection;
+
+    use super::*;
+
+    #[derive(Clone, Debug, Default)]
+    struct ReceivedEmail {
+        recipients: Vec<String>,
+        data: String,
+    }
+
+    // Just enough of SMTP to take the emails of one connection, without TLS nor authentication.
+    fn sink_session(stream: TcpStream, emails: &Mutex<Vec<ReceivedEmail>>) -> std::io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+        let mut email = ReceivedEmail::default();
+        writer.write_all(b"220 sink ESMTP\r\n")?;
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 0 {
+            let command = line.trim_end().to_string();
+            line.clear();
+            let verb = command.split(' ').next().unwrap_or_default().to_uppercase();
+            match verb.as_str() {
+                "RCPT" => {
+                    let recipient = command.trim_start_matches("RCPT TO:");
+                    email.recipients.push(recipient.trim_matches(|c| c == '<' || c == '>').to_string());
+                    writer.write_all(b"250 OK\r\n")?;
+                }
+                "DATA" => {
+                    writer.write_all(b"354 go ahead\r\n")?;
+                    while reader.read_line(&mut line)? > 0 && line.trim_end() != "." {
+                        email.data.push_str(&line);
+                        line.clear();
+                    }
+                    line.clear();
+                    emails.lock().unwrap().push(std::mem::take(&mut email));
+                    writer.write_all(b"250 queued\r\n")?;
+                }
+                "QUIT" => {
+                    writer.write_all(b"221 bye\r\n")?;
+                    break;
+                }
+                _ => writer.write_all(b"250 OK\r\n")?,
+            }
+        }
+        Ok(())
+    }
+
+    fn start_sink() -> (SmtpSettings, Arc<Mutex<Vec<ReceivedEmail>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let emails = Arc::new(Mutex::new(Vec::new()));
+        let sink_emails = emails.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let _ = sink_session(stream, &sink_emails);
+            }
+        });
+        let smtp_settings = SmtpSettings {
+            host: "127.0.0.1".to_string(),
+            port,
+            tls: SmtpTls::None,
+            username: None,
+            password: None,
+            from: "downloads@example.com".to_string(),
+        };
+        (smtp_settings, emails)
+    }
+
+    fn queue_test_email(
+        recipient: &str,
+        subject: &str,
+        digest: bool,
+        connection: &PgConnection,
+    ) -> Result<EmailNotification, Error> {
+        let queued_at = current_timestamp() - Duration::days(2);
+        diesel::insert_into(email_notification::table)
+            .values(&NewEmailNotification {
+                id: Uuid::new_v4(),
+                job_id: None,
+                recipient: recipient.to_string(),
+                event_type: "job.completed".to_string(),
+                subject: subject.to_string(),
+                body: format!("{} body", subject),
+                digest,
+                next_attempt_at: queued_at,
+                creation_date: queued_at,
+            })
+            .get_result::<EmailNotification>(connection)
+            .map_err(Error::DBError)
+    }
+
+    #[test]
+    fn emails_and_digests_reach_the_smtp_server() {
+        let (smtp_settings, emails) = start_sink();
+        let connection = test_connection();
+        connection.test_transaction::<_, Error, _>(|| {
+            let immediate_email = queue_test_email("alice@example.com", "alpha is done", false, &connection)?;
+            let digest_emails = [
+                queue_test_email("bob@example.com", "beta is done", true, &connection)?,
+                queue_test_email("bob@example.com", "gamma failed", true, &connection)?,
+                queue_test_email("carol@example.com", "delta is done", true, &connection)?,
+            ];
+
+            assert_eq!(send_due_emails(&smtp_settings, 3, &connection)?, (1, 0));
+            assert_eq!(send_due_digests(&smtp_settings, "{{count}} downloads", 0, 3, &connection)?, (2, 0));
+
+            let received = emails.lock().unwrap().clone();
+            assert_eq!(received.len(), 3);
+            assert_eq!(received[0].recipients, vec!["alice@example.com"]);
+            assert!(received[0].data.contains("Subject: alpha is done"));
+            assert!(received[0].data.contains("alpha is done body"));
+            let bob_digest = received.iter().find(|email| email.recipients == ["bob@example.com"]).unwrap();
+            assert!(bob_digest.data.contains("Subject: 2 downloads"));
+            assert!(bob_digest.data.contains("- beta is done (") && bob_digest.data.contains("- gamma failed ("));
+            let carol_digest = received.iter().find(|email| email.recipients == ["carol@example.com"]).unwrap();
+            assert!(carol_digest.data.contains("Subject: 1 downloads") && carol_digest.data.contains("- delta is done ("));
+
+            for email in digest_emails.iter().chain(std::iter::once(&immediate_email)) {
+                let sent_email = email_notification::table.find(email.id).get_result::<EmailNotification>(&connection)?;
+                assert_eq!((sent_email.status.as_str(), sent_email.attempts), (EMAIL_SENT, 1));
+                assert!(sent_email.sent_at.is_some());
+            }
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn last_digest_time_is_today_once_the_hour_passed() {
+        let morning = NaiveDateTime::parse_from_str("2022-02-21 07:59:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        let noon = NaiveDateTime::parse_from_str("2022-02-21 12:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+
+        assert_eq!(last_digest_time(morning, 8).to_string(), "2022-02-20 08:00:00");
+        assert_eq!(last_digest_time(noon, 8).to_string(), "2022-02-21 08:00:00");
+        assert_eq!(last_digest_time(noon, 12).to_string(), "2022-02-21 12:00:00");
+    }
+}
@@ -0,0 +1,125 @@
+use diesel::{ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl};
+use serde_json::{json, Map, Value};
+use uuid::Uuid;
+
+use crate::engine::outbox::record_outbox_event;
+use crate::errors::Error;
+use crate::model::event::{JobEvent, JobEventKind, NewJobEvent};
+use crate::model::general::{PaginatedResponseDTO, PaginationDTO};
+use crate::model::job::Job;
+use crate::schema::{job, job_event};
+
+// Fields that change with every write, they tell nothing about what the write did.
+const UNTRACKED_FIELDS: [&str; 1] = ["updated_at"];
+
+pub fn record_job_event(
+    other_job_id: &Uuid,
+    kind: JobEventKind,
+    actor: &str,
+    details: Value,
+    connection: &PgConnection,
+) -> Result<(), Error> {
+    diesel::insert_into(job_event::table)
+        .values(&NewJobEvent {
+            job_id: *other_job_id,
+            kind: kind.get_name().to_string(),
+            actor: actor.to_string(),
+            details: details.clone(),
+        })
+        .execute(connection)
+        .map_err(Error::DBError)?;
+    record_outbox_event(other_job_id, kind, &details, connection)
+}
+
+// The fields that differ between two versions of a job, as `{"field": {"from": .., "to": ..}}`.
+pub fn job_changes(before: &Job, after: &Job) -> Map<String, Value> {
+    let (before, after) = match (serde_json::to_value(before), serde_json::to_value(after)) {
+        (Ok(Value::Object(before)), Ok(Value::Object(after))) => (before, after),
+        _ => return Map::new(),
+    };
+    after
+        .into_iter()
+        .filter(|(field, _)| !UNTRACKED_FIELDS.contains(&field.as_str()))
+        .filter_map(|(field, to)| {
+            let from = before.get(&field).cloned().unwrap_or(Value::Null);
+            if from == to {
+                None
+            } else {
+                Some((field, json!({ "from": from, "to": to })))
+            }
+        })
+        .collect()
+}
+
+// The history of a job oldest first, the jobs in the trash keep theirs until they are purged.
+pub fn get_job_events(
+    other_job_id: &Uuid,
+    pagination_dto: &PaginationDTO,
+    connection: &PgConnection,
+) -> Result<PaginatedResponseDTO<JobEvent>, Error> {
+    // Step 1: the job must exist, a job without events is only a new one.
+    job::table
+        .find(other_job_id)
+        .select(job::id)
+        .get_result::<Uuid>(connection)
+        .map_err(Error::DBError)?;
+
+    // Step 2: read the page and count every event of the job.
+    let paginated_list = job_event::table
+        .filter(job_event::job_id.eq(other_job_id))
+        .order_by(job_event::id.asc())
+        .limit(pagination_dto.page_size)
+        .offset(pagination_dto.offset)
+        .load::<JobEvent>(connection)
+        .map_err(Error::DBError)?;
+    let count = job_event::table
+        .filter(job_event::job_id.eq(other_job_id))
+        .count()
+        .get_result::<i64>(connection)
+        .map_err(Error::DBError)?;
+
+    Ok(PaginatedResponseDTO {
+        paginated_list,
+        count: Some(count),
+        next: None,
+        prev: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+
+    use super::*;
+
+    #[test]
+    fn job_changes_lists_the_changed_fields() {
+        let before: Job = serde_json::from_value(json!({
+            "id": "5a4bd4b4-5c4f-4f4c-9d45-7d5d1a2b3c4d",
+            "name": "nightly backup",
+            "total_size": 10,
+            "downloaded_size": 0,
+            "percent_downloaded": 0,
+            "status": "Active",
+            "is_active": true,
+            "creation_date": "2022-01-01T00:00:00",
+            "expiration_date": null,
+            "file_path": null,
+            "expected_checksum": null,
+            "checksum": null,
+            "file_name": null,
+            "storage_backend": "local",
+            "bucket": null,
+            "updated_at": "2022-01-01T00:00:00",
+        }))
+        .unwrap();
+        let mut after = before.clone();
+        after.is_active = false;
+        after.updated_at = NaiveDateTime::from_timestamp(1_641_980_000, 0);
+
+        let changes = job_changes(&before, &after);
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes["is_active"], json!({ "from": true, "to": false }));
+    }
+}
@@ -0,0 +1,53 @@
+use std::path::PathBuf;
+
+use diesel::PgConnection;
+use uuid::Uuid;
+
+use crate::engine::job::find_job_by_id;
+use crate::errors::Error;
+use crate::model::job::{Job, JobStatus};
+use crate::storage::StorageConfig;
+
+// Where the file of a job can be fetched from.
+pub enum JobFile {
+    Local(PathBuf),
+    Remote(String),
+}
+
+// Find the file of a completed job in the storage backend of the job.
+// Remote backends hand out a presigned URL valid for `ttl_seconds`.
+pub fn find_job_file(
+    other_job_id: &Uuid,
+    storage_config: &StorageConfig,
+    ttl_seconds: i64,
+    connection: &PgConnection,
+) -> Result<(Job, JobFile), Error> {
+    // Step 1: search in the database for the required job.
+    let found_job = find_job_by_id(other_job_id, connection)?;
+
+    // Step 2: only completed jobs have a file to serve.
+    if found_job.status != JobStatus::Completed.get_name() {
+        return Err(Error::BadRequest(format!("The job {} is not completed.", other_job_id)));
+    }
+    let key = match &found_job.file_path {
+        Some(key) => key.clone(),
+        None => return Err(Error::NotFound(format!("The job {} has no file.", other_job_id))),
+    };
+
+    // Step 3: make sure the backend can still hand out the file.
+    let backend = storage_config
+        .backend(&found_job.storage_backend)
+        .map_err(|e| Error::InternalServerError(e.to_string()))?;
+    let bucket = found_job.bucket.as_deref();
+    if let Some(local_path) = backend.local_path(bucket, &key) {
+        return if local_path.is_file() {
+            Ok((found_job, JobFile::Local(local_path)))
+        } else {
+            Err(Error::NotFound(format!("The file of the job {} is missing.", other_job_id)))
+        };
+    }
+    match backend.presigned_url(bucket, &key, ttl_seconds) {
+        Some(url) => Ok((found_job, JobFile::Remote(url))),
+        None => Err(Error::NotFound(format!("The file of the job {} is missing.", other_job_id))),
+    }
+}
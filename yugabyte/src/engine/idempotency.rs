@@ -0,0 +1,115 @@
+use chrono::Duration;
+use diesel::{Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::errors::Error;
+use crate::errors::StateCode::{IdempotencyKeyInProgress, IdempotencyKeyReused};
+use crate::model::idempotency::{IdempotencyKey, StoredResponse};
+use crate::schema::idempotency_key;
+use crate::util::utils::current_timestamp;
+
+// The hash of a request body, equal bodies have equal hashes whatever their formatting.
+pub fn request_hash<T: Serialize>(request: &T) -> String {
+    let body = serde_json::to_vec(request).unwrap_or_default();
+    hex::encode(Sha256::digest(&body))
+}
+
+// Run `respond` once per key and endpoint within `window_seconds`, a retry with the same key
+// replays the stored response instead. Returns the response and whether it was replayed.
+// The key is claimed in the transaction of the request, a failed request leaves no key behind.
+pub fn with_idempotency_key<F>(
+    key: &str,
+    endpoint: &str,
+    other_request_hash: &str,
+    window_seconds: i64,
+    connection: &PgConnection,
+    respond: F,
+) -> Result<(StoredResponse, bool), Error>
+where
+    F: FnOnce() -> Result<StoredResponse, Error>,
+{
+    connection.transaction(|| {
+        // Step 1: a key older than the window is free again.
+        let cutoff = current_timestamp() - Duration::seconds(window_seconds);
+        diesel::delete(
+            idempotency_key::table
+                .find((key, endpoint))
+                .filter(idempotency_key::creation_date.lt(cutoff)),
+        )
+        .execute(connection)
+        .map_err(Error::DBError)?;
+
+        // Step 2: claim the key, a concurrent request with the same key waits here until this one ends.
+        let claimed = diesel::insert_into(idempotency_key::table)
+            .values(&IdempotencyKey {
+                key: key.to_string(),
+                endpoint: endpoint.to_string(),
+                request_hash: other_request_hash.to_string(),
+                response: None,
+                creation_date: current_timestamp(),
+            })
+            .on_conflict_do_nothing()
+            .execute(connection)
+            .map_err(Error::DBError)?;
+
+        // Step 3: the key was used before, replay its response if the request is the same.
+        if claimed == 0 {
+            let stored_key = idempotency_key::table
+                .find((key, endpoint))
+                .get_result::<IdempotencyKey>(connection)
+                .optional()
+                .map_err(Error::DBError)?
+                .ok_or(Error::Conflict(IdempotencyKeyInProgress))?;
+            if stored_key.request_hash != other_request_hash {
+                return Err(Error::Conflict(IdempotencyKeyReused));
+            }
+            return match stored_key.response.and_then(|response| serde_json::from_str(&response).ok()) {
+                Some(stored_response) => Ok((stored_response, true)),
+                None => Err(Error::Conflict(IdempotencyKeyInProgress)),
+            };
+        }
+
+        // Step 4: answer the request and keep the response with the key.
+        let stored_response = respond()?;
+        let response = serde_json::to_string(&stored_response)
+            .map_err(|e| Error::InternalServerError(e.to_string()))?;
+        diesel::update(idempotency_key::table.find((key, endpoint)))
+            .set(idempotency_key::response.eq(response))
+            .execute(connection)
+            .map_err(Error::DBError)?;
+        Ok((stored_response, false))
+    })
+}
+
+// Drop the keys older than the window.
+pub fn purge_idempotency_keys(window_seconds: i64, connection: &PgConnection) -> Result<usize, Error> {
+    let cutoff = current_timestamp() - Duration::seconds(window_seconds);
+    diesel::delete(idempotency_key::table.filter(idempotency_key::creation_date.lt(cutoff)))
+        .execute(connection)
+        .map_err(Error::DBError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::job::NewJob;
+
+    #[test]
+    fn request_hash_follows_the_content() {
+        let new_job = NewJob {
+            name: "nightly backup".to_string(),
+            total_size: 10,
+            ..Default::default()
+        };
+        let same_job: NewJob =
+            serde_json::from_str(r#"{ "total_size": 10, "is_active": false,  "name": "nightly backup" }"#).unwrap();
+        let other_job = NewJob {
+            total_size: 11,
+            ..Default::default()
+        };
+
+        assert_eq!(request_hash(&new_job), request_hash(&same_job));
+        assert_ne!(request_hash(&new_job), request_hash(&other_job));
+    }
+}
@@ -1,98 +1,468 @@
-use diesel::{ExpressionMethods, PgSortExpressionMethods};
+use diesel::{BoolExpressionMethods, ExpressionMethods, PgSortExpressionMethods};
 use diesel::{associations::HasTable, RunQueryDsl};
-use diesel::{PgConnection, QueryResult};
+use diesel::pg::Pg;
+use diesel::{Connection, OptionalExtension, PgArrayExpressionMethods, PgConnection, PgTextExpressionMethods, QueryResult};
 use diesel::QueryDsl;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use serde_json::{json, Value};
 use uuid::Uuid;
 
 use crate::{errors::Error, model::job::NewJob};
-use crate::model::general::PaginationDTO;
-use crate::model::job::{Job, JobInfo};
+use crate::errors::ErrorCode;
+use crate::errors::StateCode::{DuplicateExternalRef, DuplicateName, InvalidChecksum, InvalidStorage};
+use crate::engine::cas::{find_blob, find_blob_file_name, job_size, normalize_checksum};
+use crate::engine::event::{job_changes, record_job_event};
+use crate::engine::progress::{download_rates, eta_seconds, get_progress_samples};
+use crate::model::event::JobEventKind;
+use crate::model::filter::{JobFilterDTO, JobSortField};
+use crate::model::general::{PageCursor, PaginatedResponseDTO, PaginationDTO};
+use crate::model::job::{Job, JobInfo, JobPatch, JobStatus};
 use crate::schema::job::dsl::*;
 use crate::schema::job::dsl::id as job_primary_id;
+use crate::storage::{StorageConfig, LOCAL_BACKEND};
+use crate::util::utils::current_timestamp;
+
+// A write refused by a unique index is told apart from the other failures of the db.
+pub(crate) fn write_failed(e: DieselError) -> Error {
+    match &e {
+        DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, info) => match info.constraint_name() {
+            Some("job_name_lower_key") => Error::Conflict(DuplicateName),
+            Some("job_external_ref_key") => Error::Conflict(DuplicateExternalRef),
+            _ => Error::DuplicationError,
+        },
+        _ => Error::DBError(e),
+    }
+}
 
 impl NewJob {
-    pub fn add_job(&self, connection: &PgConnection) -> Result<Job, Error> {
-        // add the new job to the db.
-        let new_job = Job {
+    pub fn add_job(
+        &self,
+        storage_config: &StorageConfig,
+        actor: &str,
+        connection: &PgConnection,
+    ) -> Result<Job, Error> {
+        let new_job = self.to_job(storage_config, connection)?;
+
+        // the job is added with its events, or not at all.
+        create_job(&new_job, actor, connection)
+    }
+
+    // The job to insert for this request, checked and completed at once when its content is already stored.
+    pub fn to_job(&self, storage_config: &StorageConfig, connection: &PgConnection) -> Result<Job, Error> {
+        self.check().map_err(Error::ValidationError)?;
+
+        // the backend and bucket are fixed when the job is created.
+        let other_storage_backend = self
+            .storage_backend
+            .clone()
+            .unwrap_or_else(|| storage_config.default_backend.clone());
+        let backend = storage_config
+            .backend(&other_storage_backend)
+            .map_err(|_| Error::ValidationError(vec![InvalidStorage.into()]))?;
+        let other_bucket = storage_config
+            .bucket(&other_storage_backend, self.bucket.as_deref())
+            .map(str::to_string);
+        if other_storage_backend != LOCAL_BACKEND && other_bucket.is_none() {
+            return Err(Error::ValidationError(vec![InvalidStorage.into()]));
+        }
+
+        let mut new_job = Job {
             id: Uuid::new_v4(),
             name: self.name.clone(),
             total_size: self.total_size,
             downloaded_size: 0,
             percent_downloaded: 0,
-            status: "Active".to_string(),
+            status: JobStatus::Active.get_name().to_string(),
             is_active: self.is_active,
             creation_date: chrono::offset::Utc::now().naive_local(),
             expiration_date: Option::None,
+            file_path: Option::None,
+            expected_checksum: Option::None,
+            checksum: Option::None,
+            file_name: Option::None,
+            storage_backend: other_storage_backend,
+            bucket: other_bucket,
+            updated_at: chrono::offset::Utc::now().naive_local(),
+            tags: self.tags.clone(),
+            external_ref: self.external_ref.clone(),
+            deleted_at: Option::None,
+            attempts: 0,
+            mirror: Option::None,
+            last_error: Option::None,
+            started_at: Option::None,
+            finished_at: Option::None,
+            progressed_at: Option::None,
+            notify_emails: self.notify_emails.clone(),
+            email_digest: self.email_digest,
         };
 
-        diesel::insert_into(job::table())
-            .values(&new_job)
-            .get_result::<Job>(connection)
-            .map_err(|_err| Error::DuplicationError)
+        // a job whose content is already in the store completes without downloading it again.
+        if let Some(other_expected_checksum) = &self.expected_checksum {
+            let other_expected_checksum = normalize_checksum(other_expected_checksum)
+                .map_err(|_| Error::ValidationError(vec![InvalidChecksum.into()]))?;
+            if let Some((blob_key, blob_size)) = find_blob(backend, new_job.bucket.as_deref(), &other_expected_checksum)? {
+                new_job.total_size = job_size(blob_size)?;
+                new_job.downloaded_size = new_job.total_size;
+                new_job.percent_downloaded = 100;
+                new_job.status = JobStatus::Completed.get_name().to_string();
+                new_job.file_path = Some(blob_key);
+                new_job.checksum = Some(other_expected_checksum.clone());
+                new_job.file_name = find_blob_file_name(&other_expected_checksum, connection)?;
+                new_job.finished_at = Some(new_job.creation_date);
+            }
+            new_job.expected_checksum = Some(other_expected_checksum);
+        }
+        Ok(new_job)
     }
 }
 
+fn record_created(created_job: &Job, actor: &str, connection: &PgConnection) -> Result<(), Error> {
+    let details = json!({ "status": created_job.status, "is_active": created_job.is_active });
+    record_job_event(&created_job.id, JobEventKind::Created, actor, details, connection)
+}
+
+// Insert a built job with its created events, in a savepoint so a failed insert leaves the
+// transaction of the caller usable.
+pub fn create_job(new_job: &Job, actor: &str, connection: &PgConnection) -> Result<Job, Error> {
+    connection.transaction(|| {
+        let created_job = diesel::insert_into(job::table())
+            .values(new_job)
+            .get_result::<Job>(connection)
+            .map_err(write_failed)?;
+        record_created(&created_job, actor, connection)?;
+        Ok(created_job)
+    })
+}
+
+// An active job added the way a client adds one, for the tests that need a job in the db.
+#[cfg(test)]
+pub(crate) fn add_test_job(other_name: &str, other_total_size: i32, connection: &PgConnection) -> Job {
+    let new_job = NewJob {
+        name: other_name.to_string(),
+        total_size: other_total_size,
+        is_active: true,
+        ..NewJob::default()
+    };
+    new_job.add_job(&StorageConfig::default(), "test", connection).unwrap()
+}
+
 pub fn create_bulk_jobs(
     other_jobs: &Vec<Job>,
+    actor: &str,
     connection: &PgConnection,
 ) -> Result<Vec<Job>, Error> {
-    diesel::insert_into(job::table())
-        .values(other_jobs)
-        .get_results::<Job>(connection)
-        .map_err(|_| Error::DuplicationError)
+    // every job is checked before any is inserted, the errors tell which job broke which rule.
+    let errors: Vec<ErrorCode> = other_jobs
+        .iter()
+        .enumerate()
+        .filter_map(|(index, other_job)| other_job.check().err().map(|errors| (index, errors)))
+        .flat_map(|(index, errors)| {
+            errors.into_iter().map(move |error| ErrorCode {
+                error_code: error.error_code,
+                message: format!("Job {}: {}", index, error.message),
+            })
+        })
+        .collect();
+    if !errors.is_empty() {
+        return Err(Error::ValidationError(errors));
+    }
+
+    // the jobs are added with their events, or none of them is.
+    connection.transaction(|| {
+        other_jobs
+            .iter()
+            .map(|other_job| create_job(other_job, actor, connection))
+            .collect()
+    })
 }
 
 pub fn get_all_jobs(connection: &PgConnection) -> QueryResult<Vec<Job>> {
     job::table().load::<Job>(connection)
 }
 
+// Escape the wildcards of LIKE so the text is matched as it is.
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+// The jobs matching the filter, the list and its count are built from the same query.
+// The jobs in the trash are left out.
+fn filtered_jobs(filter: &JobFilterDTO) -> Result<crate::schema::job::BoxedQuery<'static, Pg>, Error> {
+    let mut query = job::table().filter(deleted_at.is_null()).into_boxed();
+
+    let statuses = filter.statuses().map_err(|e| Error::BadRequest(e.message))?;
+    if !statuses.is_empty() {
+        query = query.filter(status.eq_any(statuses));
+    }
+    if let Some(other_is_active) = filter.is_active {
+        query = query.filter(is_active.eq(other_is_active));
+    }
+    if let Some(text) = &filter.name_contains {
+        query = query.filter(name.ilike(format!("%{}%", escape_like(text))));
+    }
+    if let Some(prefix) = &filter.name_prefix {
+        query = query.filter(name.ilike(format!("{}%", escape_like(prefix))));
+    }
+    if let Some(after) = filter.created_after {
+        query = query.filter(creation_date.ge(after));
+    }
+    if let Some(before) = filter.created_before {
+        query = query.filter(creation_date.lt(before));
+    }
+    if let Some(after) = filter.expires_after {
+        query = query.filter(expiration_date.ge(after));
+    }
+    if let Some(before) = filter.expires_before {
+        query = query.filter(expiration_date.lt(before));
+    }
+    if let Some(min) = filter.min_total_size {
+        query = query.filter(total_size.ge(min));
+    }
+    if let Some(max) = filter.max_total_size {
+        query = query.filter(total_size.le(max));
+    }
+    let other_tags = filter.tag_list();
+    if !other_tags.is_empty() {
+        query = query.filter(tags.contains(other_tags));
+    }
+    Ok(query)
+}
+
+// The ids of the jobs matching the filter, at most `max_jobs` of them.
+pub fn find_job_ids(filter: &JobFilterDTO, max_jobs: i64, connection: &PgConnection) -> Result<Vec<Uuid>, Error> {
+    filtered_jobs(filter)?
+        .select(job_primary_id)
+        .order_by(creation_date.desc())
+        .limit(max_jobs)
+        .load::<Uuid>(connection)
+        .map_err(Error::DBError)
+}
+
+macro_rules! sort_by {
+    ($query:expr, $column:expr, $descending:expr) => {
+        if $descending {
+            $query.then_order_by($column.desc().nulls_last())
+        } else {
+            $query.then_order_by($column.asc().nulls_last())
+        }
+    };
+}
+
 pub fn get_all_paginated_jobs(
     pagination_dto: &PaginationDTO,
+    filter: &JobFilterDTO,
     connection: &PgConnection,
 ) -> Result<Vec<Job>, Error> {
-    job::table()
-        .order_by(creation_date.desc().nulls_last())
+    let sort_keys = filter.sort_keys().map_err(|e| Error::BadRequest(e.message))?;
+    let mut query = filtered_jobs(filter)?;
+
+    // the newest jobs come first unless the client asks for another order.
+    if sort_keys.is_empty() {
+        query = query.order_by(creation_date.desc().nulls_last());
+    }
+    for key in sort_keys {
+        query = match key.field {
+            JobSortField::Name => sort_by!(query, name, key.descending),
+            JobSortField::Status => sort_by!(query, status, key.descending),
+            JobSortField::TotalSize => sort_by!(query, total_size, key.descending),
+            JobSortField::DownloadedSize => sort_by!(query, downloaded_size, key.descending),
+            JobSortField::PercentDownloaded => sort_by!(query, percent_downloaded, key.descending),
+            JobSortField::CreationDate => sort_by!(query, creation_date, key.descending),
+            JobSortField::ExpirationDate => sort_by!(query, expiration_date, key.descending),
+            JobSortField::UpdatedAt => sort_by!(query, updated_at, key.descending),
+        };
+    }
+
+    // the id keeps the order of equal rows the same from one page to the next.
+    query
+        .then_order_by(job_primary_id.asc())
         .limit(pagination_dto.page_size)
         .offset(pagination_dto.offset)
         .load::<Job>(connection)
         .map_err(|err| Error::DBError(err))
 }
 
-pub fn count_jobs(connection: &PgConnection) -> Result<i64, Error> {
-    job::table()
+// A page of the jobs newest first, with the cursors of the pages around it.
+// After a cursor the page is found from the position of the cursor, not by skipping rows.
+pub fn get_job_page(
+    pagination_dto: &PaginationDTO,
+    filter: &JobFilterDTO,
+    connection: &PgConnection,
+) -> Result<PaginatedResponseDTO<Job>, Error> {
+    let page_size = pagination_dto.page_size.max(0);
+    let cursor = match &pagination_dto.cursor {
+        Some(cursor) => Some(
+            PageCursor::decode(cursor)
+                .ok_or_else(|| Error::BadRequest(format!("The cursor {} is not valid.", cursor)))?,
+        ),
+        None => None,
+    };
+    let backward = cursor.as_ref().is_some_and(|cursor| cursor.backward);
+
+    // Step 1: start from the cursor, or from the offset on the first request.
+    let mut query = filtered_jobs(filter)?;
+    match &cursor {
+        Some(cursor) if cursor.backward => {
+            query = query.filter(
+                creation_date.gt(cursor.creation_date).or(creation_date
+                    .eq(cursor.creation_date)
+                    .and(job_primary_id.gt(cursor.id))),
+            );
+        }
+        Some(cursor) => {
+            query = query.filter(
+                creation_date.lt(cursor.creation_date).or(creation_date
+                    .eq(cursor.creation_date)
+                    .and(job_primary_id.lt(cursor.id))),
+            );
+        }
+        None => query = query.offset(pagination_dto.offset),
+    }
+
+    // Step 2: walk towards older jobs, or back towards newer ones, one row more tells if the list goes on.
+    query = if backward {
+        query.order_by((creation_date.asc(), job_primary_id.asc()))
+    } else {
+        query.order_by((creation_date.desc(), job_primary_id.desc()))
+    };
+    let mut paginated_list = query
+        .limit(page_size + 1)
+        .load::<Job>(connection)
+        .map_err(Error::DBError)?;
+    let has_more = paginated_list.len() as i64 > page_size;
+    paginated_list.truncate(page_size as usize);
+    if backward {
+        paginated_list.reverse();
+    }
+
+    // Step 3: point the cursors at both ends of the page.
+    let (has_next, has_prev) = if backward {
+        (true, has_more)
+    } else {
+        (has_more, cursor.is_some() || pagination_dto.offset > 0)
+    };
+    let next = paginated_list.last().filter(|_| has_next).map(|last_job| {
+        PageCursor {
+            creation_date: last_job.creation_date,
+            id: last_job.id,
+            backward: false,
+        }
+        .encode()
+    });
+    let prev = paginated_list.first().filter(|_| has_prev).map(|first_job| {
+        PageCursor {
+            creation_date: first_job.creation_date,
+            id: first_job.id,
+            backward: true,
+        }
+        .encode()
+    });
+
+    Ok(PaginatedResponseDTO {
+        paginated_list,
+        count: None,
+        next,
+        prev,
+    })
+}
+
+#[derive(QueryableByName)]
+struct TableEstimate {
+    #[sql_type = "diesel::sql_types::BigInt"]
+    estimate: i64,
+}
+
+// The number of jobs from the statistics of the planner, it is only counted when a filter is set
+// or the table was never analyzed.
+pub fn estimate_jobs(filter: &JobFilterDTO, connection: &PgConnection) -> Result<i64, Error> {
+    if !filter.has_filters() {
+        let table_estimate = diesel::sql_query(
+            "SELECT reltuples::BIGINT AS estimate FROM pg_class WHERE oid = 'job'::regclass",
+        )
+        .get_result::<TableEstimate>(connection)
+        .map_err(Error::DBError)?;
+        if table_estimate.estimate >= 0 {
+            return Ok(table_estimate.estimate);
+        }
+    }
+    count_jobs(filter, connection)
+}
+
+pub fn count_jobs(filter: &JobFilterDTO, connection: &PgConnection) -> Result<i64, Error> {
+    filtered_jobs(filter)?
         .count()
         .get_result(connection)
         .map_err(|e| Error::DBError(e))
 }
 
+// Lock the job for the rest of the transaction, it must still match `if_match` when one is given.
+pub(crate) fn lock_job(
+    other_job_id: &Uuid,
+    if_match: Option<&str>,
+    connection: &PgConnection,
+) -> Result<Job, Error> {
+    let found_job = job
+        .find(other_job_id)
+        .filter(deleted_at.is_null())
+        .for_update()
+        .get_result::<Job>(connection)
+        .map_err(Error::DBError)?;
+    match if_match {
+        Some(if_match) if !found_job.matches_etag(if_match) => Err(Error::PreconditionFailed),
+        _ => Ok(found_job),
+    }
+}
+
+// The job locked for an update, `None` when there is no such job or it is in the trash.
+fn lock_existing_job(other_job_id: &Uuid, connection: &PgConnection) -> Result<Option<Job>, Error> {
+    match lock_job(other_job_id, None, connection) {
+        Ok(found_job) => Ok(Some(found_job)),
+        Err(Error::DBError(DieselError::NotFound)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
 pub fn delete_job_by_id(
     other_job_id: &Uuid,
+    if_match: Option<&str>,
+    actor: &str,
     connection: &PgConnection,
 ) -> Result<Job, Error> {
-    // Step 1: delete the required job from the db.
-    let deleted_job =
-        diesel::delete(job::table().filter(job_primary_id.eq(other_job_id)))
-            .load::<Job>(connection)
-            .map_err(|e| Error::DBError(e))?
-            .pop()
-            .ok_or(Error::DBError(diesel::result::Error::NotFound))?;
+    connection.transaction(|| {
+        // Step 1: make sure the job was not changed since the client read it.
+        lock_job(other_job_id, if_match, connection)?;
 
-    Ok(deleted_job)
+        // Step 2: move the job to the trash, it is purged for good later.
+        let deleted_job = diesel::update(job.find(other_job_id))
+            .set(deleted_at.eq(current_timestamp()))
+            .get_result::<Job>(connection)
+            .map_err(Error::DBError)?;
+        record_job_event(other_job_id, JobEventKind::Deleted, actor, json!({}), connection)?;
+        Ok(deleted_job)
+    })
 }
 
 // Activate/Deactivate the job.
 pub fn set_activate_job(
     other_job_id: &Uuid,
     activate: bool,
+    actor: &str,
     connection: &PgConnection,
 ) -> Result<usize, Error> {
-    let res = diesel::update(job::table().filter(job_primary_id.eq(other_job_id)))
-        .set((
-            is_active.eq(activate),
-        ))
-        .execute(connection)
-        .map_err(|e| Error::DBError(e));
-    res
+    connection.transaction(|| {
+        let found_job = match lock_existing_job(other_job_id, connection)? {
+            Some(found_job) => found_job,
+            None => return Ok(0),
+        };
+        let changed = diesel::update(job.find(other_job_id))
+            .set(is_active.eq(activate))
+            .execute(connection)
+            .map_err(Error::DBError)?;
+        if found_job.is_active != activate {
+            record_job_event(other_job_id, JobEventKind::activation(activate), actor, json!({}), connection)?;
+        }
+        Ok(changed)
+    })
 }
 
 pub fn delete_all_jobs(connection: &PgConnection) -> Result<usize, Error> {
@@ -104,6 +474,7 @@ pub fn delete_all_jobs(connection: &PgConnection) -> Result<usize, Error> {
 pub fn find_job_by_id(other_job_id: &Uuid, connection: &PgConnection) -> Result<Job, Error> {
     job::table()
         .find(other_job_id)
+        .filter(deleted_at.is_null())
         .get_result::<Job>(connection)
         .map_err(|err| Error::DBError(err))
 }
@@ -117,18 +488,285 @@ pub fn update_job(
             name.eq(&incoming_job.name),
             total_size.eq(&incoming_job.total_size),
             is_active.eq(&incoming_job.is_active),
+            expiration_date.eq(&incoming_job.expiration_date),
+            tags.eq(&incoming_job.tags),
+            external_ref.eq(&incoming_job.external_ref),
+            notify_emails.eq(&incoming_job.notify_emails),
+            email_digest.eq(&incoming_job.email_digest),
         ))
         .get_result::<Job>(connection)
-        .map_err(|e| Error::DBError(e))
+        .map_err(write_failed)
+}
+
+// Apply a merge patch to the job, the job is locked so concurrent patches are not lost.
+// With `if_match` the patch is refused when the job changed since the client read it.
+pub fn patch_job(
+    other_job_id: &Uuid,
+    job_patch: JobPatch,
+    if_match: Option<&str>,
+    actor: &str,
+    connection: &PgConnection,
+) -> Result<Job, Error> {
+    connection.transaction(|| {
+        let found_job = lock_job(other_job_id, if_match, connection)?;
+        let mut patched_job = found_job.clone();
+        job_patch.apply(&mut patched_job);
+        patched_job.check().map_err(Error::ValidationError)?;
+        let updated_job = update_job(&patched_job, connection)?;
+
+        // the activation is told apart from the other changes.
+        let mut changes = job_changes(&found_job, &updated_job);
+        if changes.remove("is_active").is_some() {
+            let kind = JobEventKind::activation(updated_job.is_active);
+            record_job_event(other_job_id, kind, actor, json!({}), connection)?;
+        }
+        if !changes.is_empty() {
+            let details = Value::Object(changes);
+            record_job_event(other_job_id, JobEventKind::Updated, actor, details, connection)?;
+        }
+        Ok(updated_job)
+    })
+}
+
+// Create the job of an external reference, or bring the one already there in line with the request.
+// Returns the job and whether it was created. With `if_match` only the job of that version is updated.
+pub fn upsert_job(
+    other_external_ref: &str,
+    new_job: &NewJob,
+    if_match: Option<&str>,
+    storage_config: &StorageConfig,
+    actor: &str,
+    connection: &PgConnection,
+) -> Result<(Job, bool), Error> {
+    connection.transaction(|| {
+        // Step 1: the job is inserted unless a job out of the trash holds its reference or its name,
+        // a concurrent upsert waits here.
+        let mut upserted_job = new_job.to_job(storage_config, connection)?;
+        upserted_job.external_ref = Some(other_external_ref.to_string());
+        let created_job = diesel::insert_into(job::table())
+            .values(&upserted_job)
+            .on_conflict_do_nothing()
+            .get_result::<Job>(connection)
+            .optional()
+            .map_err(write_failed)?;
+        if let Some(created_job) = created_job {
+            // there was no job to match the version against.
+            if if_match.is_some() {
+                return Err(Error::PreconditionFailed);
+            }
+            record_created(&created_job, actor, connection)?;
+            return Ok((created_job, true));
+        }
+
+        // Step 2: update the job of the reference, without one the name was the conflict.
+        let other_job_id = job
+            .filter(external_ref.eq(other_external_ref))
+            .filter(deleted_at.is_null())
+            .select(job_primary_id)
+            .get_result::<Uuid>(connection)
+            .optional()
+            .map_err(Error::DBError)?
+            .ok_or(Error::Conflict(DuplicateName))?;
+        patch_job(&other_job_id, JobPatch::upserting(new_job), if_match, actor, connection)
+            .map(|updated_job| (updated_job, false))
+    })
+}
+
+// Mark the job as completed and attach the downloaded file of `other_size` bytes to it.
+pub fn complete_job(
+    other_job_id: &Uuid,
+    other_file_path: &str,
+    other_checksum: &str,
+    other_file_name: Option<&str>,
+    other_size: i32,
+    actor: &str,
+    connection: &PgConnection,
+) -> Result<Job, Error> {
+    connection.transaction(|| {
+        let found_job = lock_job(other_job_id, None, connection)?;
+        // the stored file has the final say on the size, whatever the client declared.
+        let completed_job = diesel::update(job.find(other_job_id))
+            .set((
+                total_size.eq(other_size),
+                downloaded_size.eq(other_size),
+                percent_downloaded.eq(100),
+                status.eq(JobStatus::Completed.get_name()),
+                file_path.eq(other_file_path),
+                checksum.eq(other_checksum),
+                file_name.eq(other_file_name),
+                finished_at.eq(current_timestamp()),
+            ))
+            .get_result::<Job>(connection)
+            .map_err(Error::DBError)?;
+        record_status_change(&found_job, &completed_job.status, actor, connection)?;
+        Ok(completed_job)
+    })
+}
+
+pub fn record_status_change(
+    found_job: &Job,
+    new_status: &str,
+    actor: &str,
+    connection: &PgConnection,
+) -> Result<(), Error> {
+    if found_job.status == new_status {
+        return Ok(());
+    }
+    let details = json!({ "from": found_job.status, "to": new_status });
+    record_job_event(&found_job.id, JobEventKind::StatusChanged, actor, details, connection)
+}
+
+pub fn set_job_status(
+    other_job_id: &Uuid,
+    new_status: JobStatus,
+    actor: &str,
+    connection: &PgConnection,
+) -> Result<usize, Error> {
+    connection.transaction(|| {
+        let found_job = match lock_existing_job(other_job_id, connection)? {
+            Some(found_job) => found_job,
+            None => return Ok(0),
+        };
+        change_job_status(&found_job, new_status, actor, connection)
+    })
+}
+
+// Send the job back to the queue, its progress and file are dropped.
+pub fn requeue_job(other_job_id: &Uuid, actor: &str, connection: &PgConnection) -> Result<usize, Error> {
+    connection.transaction(|| {
+        let found_job = match lock_existing_job(other_job_id, connection)? {
+            Some(found_job) => found_job,
+            None => return Ok(0),
+        };
+        requeue_locked_job(&found_job, actor, connection)
+    })
+}
+
+// Flag the job whose file failed a check with `new_status`, or send it back to the queue when `requeue`
+// is set. The job is left alone unless it is still completed with the file that was checked.
+pub fn flag_checked_job(
+    checked_job: &Job,
+    new_status: JobStatus,
+    requeue: bool,
+    actor: &str,
+    connection: &PgConnection,
+) -> Result<usize, Error> {
+    connection.transaction(|| {
+        let found_job = match lock_existing_job(&checked_job.id, connection)? {
+            Some(found_job)
+                if found_job.status == JobStatus::Completed.get_name()
+                    && found_job.storage_backend == checked_job.storage_backend
+                    && found_job.bucket == checked_job.bucket
+                    && found_job.file_path == checked_job.file_path =>
+            {
+                found_job
+            }
+            _ => return Ok(0),
+        };
+        if requeue {
+            requeue_locked_job(&found_job, actor, connection)
+        } else {
+            change_job_status(&found_job, new_status, actor, connection)
+        }
+    })
+}
+
+fn change_job_status(
+    found_job: &Job,
+    new_status: JobStatus,
+    actor: &str,
+    connection: &PgConnection,
+) -> Result<usize, Error> {
+    let changed = diesel::update(job.find(found_job.id))
+        .set(status.eq(new_status.get_name()))
+        .execute(connection)
+        .map_err(Error::DBError)?;
+    record_status_change(found_job, new_status.get_name(), actor, connection)?;
+    Ok(changed)
+}
+
+fn requeue_locked_job(found_job: &Job, actor: &str, connection: &PgConnection) -> Result<usize, Error> {
+    let changed = diesel::update(job.find(found_job.id))
+        .set((
+            downloaded_size.eq(0),
+            percent_downloaded.eq(0),
+            status.eq(JobStatus::Queued.get_name()),
+            file_path.eq(Option::<String>::None),
+            checksum.eq(Option::<String>::None),
+            file_name.eq(Option::<String>::None),
+            mirror.eq(Option::<String>::None),
+            finished_at.eq(Option::<chrono::NaiveDateTime>::None),
+        ))
+        .execute(connection)
+        .map_err(write_failed)?;
+    let details = json!({ "from": found_job.status, "to": JobStatus::Queued.get_name() });
+    record_job_event(&found_job.id, JobEventKind::Retried, actor, details, connection)?;
+    Ok(changed)
 }
 
 pub fn get_job_info(other_job_id: &Uuid, connection: &PgConnection) -> Result<JobInfo, Error> {
-    match find_job_by_id(&other_job_id, connection) {
-        Ok(found_job) => Ok(JobInfo {
-            name: found_job.name,
-            downloaded_size: found_job.downloaded_size,
-            remaining_size: found_job.total_size - found_job.downloaded_size,
-        }),
-        Err(e) => Err(e),
+    let found_job = find_job_by_id(other_job_id, connection)?;
+    let rates = download_rates(&get_progress_samples(other_job_id, connection)?);
+    let other_eta_seconds = eta_seconds(&found_job, &rates);
+
+    // a job that is not downloading has no current speed.
+    let downloading = found_job.status == JobStatus::Active.get_name() && found_job.is_active;
+    Ok(JobInfo {
+        remaining_size: found_job.total_size - found_job.downloaded_size,
+        name: found_job.name,
+        status: found_job.status,
+        downloaded_size: found_job.downloaded_size,
+        current_speed: rates.current_speed.filter(|_| downloading),
+        average_speed: rates.average_speed,
+        eta_seconds: other_eta_seconds,
+        started_at: found_job.started_at,
+        finished_at: found_job.finished_at,
+        active_seconds: rates.active_seconds,
+        attempts: found_job.attempts,
+        mirror: found_job.mirror,
+        last_error: found_job.last_error,
+    })
+}
+#[cfg(test)]
+mod tests {
+    use crate::db_connection::test_connection;
+
+    use super::*;
+
+    fn try_add_job(other_name: &str, connection: &PgConnection) -> Result<Job, Error> {
+        let new_job = NewJob {
+            name: other_name.to_string(),
+            total_size: 1000,
+            is_active: true,
+            ..NewJob::default()
+        };
+        new_job.add_job(&StorageConfig::default(), "test", connection)
+    }
+
+    #[test]
+    fn names_are_unique_among_the_running_jobs_only() {
+        let connection = test_connection();
+        connection.test_transaction::<_, Error, _>(|| {
+            let finished_job = add_test_job("shared name job", 1000, &connection);
+            set_job_status(&finished_job.id, JobStatus::Completed, "test", &connection)?;
+            let other_finished_job = add_test_job("Shared Name Job", 1000, &connection);
+            set_job_status(&other_finished_job.id, JobStatus::Failed, "test", &connection)?;
+
+            // two finished jobs share the name, one running job may take it too.
+            let running_job = add_test_job("SHARED NAME JOB", 1000, &connection);
+            assert!(matches!(try_add_job("shared NAME job", &connection), Err(Error::Conflict(DuplicateName))));
+            for running_status in [JobStatus::Queued, JobStatus::Stalled, JobStatus::Active] {
+                set_job_status(&running_job.id, running_status, "test", &connection)?;
+                assert!(matches!(try_add_job("shared name job", &connection), Err(Error::Conflict(DuplicateName))));
+                assert!(matches!(
+                    requeue_job(&finished_job.id, "test", &connection),
+                    Err(Error::Conflict(DuplicateName))
+                ));
+            }
+
+            set_job_status(&running_job.id, JobStatus::Completed, "test", &connection)?;
+            assert_eq!(requeue_job(&finished_job.id, "test", &connection)?, 1);
+            Ok(())
+        });
     }
-}
\ No newline at end of file
+}
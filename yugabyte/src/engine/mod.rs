@@ -1 +1,17 @@
-pub mod job;
\ No newline at end of file
+pub mod alert;
+pub mod audit;
+pub mod bulk;
+pub mod cas;
+pub mod email;
+pub mod event;
+pub mod file;
+pub mod idempotency;
+pub mod job;
+pub mod notify;
+pub mod outbox;
+pub mod progress;
+pub mod reconcile;
+pub mod retention;
+pub mod stall;
+pub mod trash;
+pub mod webhook;
@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use diesel::sql_types::Text;
+use diesel::{PgConnection, RunQueryDsl};
+use postgres::fallible_iterator::FallibleIterator;
+use postgres::{Client, NoTls};
+use serde_json::json;
+
+use crate::errors::Error;
+use crate::model::outbox::OutboxEvent;
+
+// The row changes of the jobs, notified by the `job_notify_change` trigger.
+pub const JOB_CHANGES_CHANNEL: &str = "job_changes";
+// The domain events of the outbox, notified by the relay once per event.
+pub const JOB_EVENTS_CHANNEL: &str = "job_events";
+
+// NOTIFY refuses payloads of 8000 bytes or more.
+const MAX_PAYLOAD_SIZE: usize = 7999;
+// How long the listener waits for a notification before it checks that the connection still works.
+const LISTEN_TIMEOUT_SECONDS: u64 = 30;
+
+// Notify the outbox event to the listening servers, sent when the transaction commits.
+// An event too large for NOTIFY goes without its payload, the clients read the job again.
+pub fn notify_outbox_event(outbox_event: &OutboxEvent, connection: &PgConnection) -> Result<(), Error> {
+    let mut payload = serde_json::to_string(outbox_event).map_err(|e| Error::InternalServerError(e.to_string()))?;
+    if payload.len() > MAX_PAYLOAD_SIZE {
+        payload = json!({
+            "id": outbox_event.id,
+            "name": outbox_event.name,
+            "job_id": outbox_event.job_id,
+            "payload": null,
+            "creation_date": outbox_event.creation_date,
+        })
+        .to_string();
+    }
+    diesel::sql_query("SELECT pg_notify($1, $2)")
+        .bind::<Text, _>(JOB_EVENTS_CHANNEL)
+        .bind::<Text, _>(payload)
+        .execute(connection)
+        .map(|_| ())
+        .map_err(Error::DBError)
+}
+
+// Listen to the channels on a connection of its own and hand every notification to `on_notification`
+// with its channel and payload. It only returns when the connection is lost.
+pub fn listen<F>(database_url: &str, channels: &[&str], mut on_notification: F) -> Result<(), Error>
+where
+    F: FnMut(&str, &str),
+{
+    let listen_error = |e: postgres::Error| Error::InternalServerError(format!("listening to the database failed: {}", e));
+    let mut client = Client::connect(database_url, NoTls).map_err(listen_error)?;
+    for channel in channels {
+        client.batch_execute(&format!("LISTEN {}", channel)).map_err(listen_error)?;
+    }
+    loop {
+        {
+            let mut notifications = client.notifications();
+            let mut received = notifications.timeout_iter(Duration::from_secs(LISTEN_TIMEOUT_SECONDS));
+            while let Some(notification) = received.next().map_err(listen_error)? {
+                on_notification(notification.channel(), notification.payload());
+            }
+        }
+        client.is_valid(Duration::from_secs(LISTEN_TIMEOUT_SECONDS)).map_err(listen_error)?;
+    }
+}
@@ -0,0 +1,284 @@
+use chrono::Duration;
+use diesel::dsl::{exists, not};
+use diesel::sql_types::{Array, BigInt, Text, Timestamp};
+use diesel::{
+    sql_query, BoolExpressionMethods, Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl,
+    RunQueryDsl,
+};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::errors::Error;
+use crate::model::event::JobEventKind;
+use crate::model::job::Job;
+use crate::engine::webhook::retry_delay;
+use crate::model::outbox::{
+    domain_event_name, NewOutboxEvent, NewOutboxPublication, OutboxEvent, OUTBOX_SINKS, PUBLICATION_DEAD,
+    PUBLICATION_FAILED, PUBLICATION_PUBLISHED,
+};
+use crate::model::webhook::webhook_event_type;
+use crate::schema::{job, outbox_event, outbox_publication};
+use crate::util::utils::current_timestamp;
+
+const RELAY_BATCH_SIZE: i64 = 100;
+
+// Write the domain event of a job event to the outbox, in the transaction of the change so that
+// the event is published if and only if the change is committed.
+pub fn record_outbox_event(
+    other_job_id: &Uuid,
+    kind: JobEventKind,
+    details: &Value,
+    connection: &PgConnection,
+) -> Result<(), Error> {
+    let now = current_timestamp();
+    let found_job = job::table
+        .find(other_job_id)
+        .get_result::<Job>(connection)
+        .optional()
+        .map_err(Error::DBError)?;
+    diesel::insert_into(outbox_event::table)
+        .values(&NewOutboxEvent {
+            name: domain_event_name(kind, details),
+            job_id: Some(*other_job_id),
+            payload: json!({
+                "event_type": webhook_event_type(kind, details),
+                "occurred_at": now,
+                "job": found_job,
+                "details": details,
+            }),
+            creation_date: now,
+        })
+        .execute(connection)
+        .map_err(Error::DBError)?;
+    Ok(())
+}
+
+// What a relay run did: the number of published events and the events the sink failed on, left to the next runs.
+#[derive(Debug, Default)]
+pub struct RelayOutcome {
+    pub published: usize,
+    pub failures: Vec<(i64, Error)>,
+}
+
+// Lock the oldest event the sink is due to get, so that no other server publishes it meanwhile, and the
+// number of the attempt.
+fn claim_pending_event(sink: &str, connection: &PgConnection) -> Result<Option<(OutboxEvent, i32)>, Error> {
+    let now = current_timestamp();
+    let pending_event = outbox_event::table
+        .filter(not(exists(
+            outbox_publication::table
+                .filter(outbox_publication::event_id.eq(outbox_event::id))
+                .filter(outbox_publication::sink.eq(sink))
+                .filter(
+                    outbox_publication::status
+                        .ne(PUBLICATION_FAILED)
+                        .or(outbox_publication::next_attempt_at.gt(now)),
+                ),
+        )))
+        .order_by(outbox_event::id.asc())
+        .limit(1)
+        .for_update()
+        .skip_locked()
+        .get_result::<OutboxEvent>(connection)
+        .optional()
+        .map_err(Error::DBError)?;
+    let pending_event = match pending_event {
+        Some(pending_event) => pending_event,
+        None => return Ok(None),
+    };
+    let attempts = outbox_publication::table
+        .find((pending_event.id, sink))
+        .select(outbox_publication::attempts)
+        .get_result::<i32>(connection)
+        .optional()
+        .map_err(Error::DBError)?
+        .unwrap_or_default();
+    Ok(Some((pending_event, attempts + 1)))
+}
+
+// Publish the events the sink is due to get, oldest first, each in a transaction of its own that records
+// how it went. An event is locked while it is published so that two servers never relay it to the same sink
+// at once, but a server dying before its commit leaves it to be published again: a sink gets an event at
+// least once and tells the copies apart by the event id. A failed event is tried again later, after a wait
+// doubled every time, until it is dead after `max_attempts`. The events behind it are published meanwhile.
+pub fn relay_outbox_events<F>(
+    sink: &str,
+    max_attempts: i32,
+    connection: &PgConnection,
+    mut publish: F,
+) -> Result<RelayOutcome, Error>
+where
+    F: FnMut(&OutboxEvent) -> Result<(), Error>,
+{
+    let mut outcome = RelayOutcome::default();
+    for _ in 0..RELAY_BATCH_SIZE {
+        let relayed = connection.transaction::<_, Error, _>(|| {
+            let (pending_event, attempts) = match claim_pending_event(sink, connection)? {
+                Some(claimed) => claimed,
+                None => return Ok(None),
+            };
+            // what a failed publication wrote is rolled back, its failure is recorded instead.
+            let published = connection.transaction(|| publish(&pending_event));
+            let now = current_timestamp();
+            let publication = match &published {
+                Ok(()) => NewOutboxPublication {
+                    event_id: pending_event.id,
+                    sink: sink.to_string(),
+                    status: PUBLICATION_PUBLISHED.to_string(),
+                    attempts,
+                    last_error: None,
+                    next_attempt_at: None,
+                    published_at: Some(now),
+                },
+                Err(e) => NewOutboxPublication {
+                    event_id: pending_event.id,
+                    sink: sink.to_string(),
+                    status: if attempts >= max_attempts { PUBLICATION_DEAD } else { PUBLICATION_FAILED }.to_string(),
+                    attempts,
+                    last_error: Some(e.to_string()),
+                    next_attempt_at: Some(now + retry_delay(attempts)),
+                    published_at: None,
+                },
+            };
+            diesel::insert_into(outbox_publication::table)
+                .values(&publication)
+                .on_conflict((outbox_publication::event_id, outbox_publication::sink))
+                .do_update()
+                .set(&publication)
+                .execute(connection)
+                .map_err(Error::DBError)?;
+            Ok(Some((pending_event.id, published)))
+        })?;
+        match relayed {
+            Some((_, Ok(()))) => outcome.published += 1,
+            Some((event_id, Err(e))) => outcome.failures.push((event_id, e)),
+            None => break,
+        }
+    }
+    Ok(outcome)
+}
+
+// Drop the events older than the retention period that every sink published or gave up on, with their
+// publications. An event a sink still has to get is kept whatever its age.
+pub fn purge_outbox_events(retention_days: i64, connection: &PgConnection) -> Result<usize, Error> {
+    let cutoff = current_timestamp() - Duration::days(retention_days);
+    sql_query(
+        "DELETE FROM outbox_event
+        WHERE creation_date < $1
+          AND (SELECT COUNT(*)
+               FROM outbox_publication
+               WHERE outbox_publication.event_id = outbox_event.id
+                 AND sink = ANY ($2)
+                 AND status IN ($3, $4)) = $5",
+    )
+    .bind::<Timestamp, _>(cutoff)
+    .bind::<Array<Text>, _>(OUTBOX_SINKS.to_vec())
+    .bind::<Text, _>(PUBLICATION_PUBLISHED)
+    .bind::<Text, _>(PUBLICATION_DEAD)
+    .bind::<BigInt, _>(OUTBOX_SINKS.len() as i64)
+    .execute(connection)
+    .map_err(Error::DBError)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db_connection::test_connection;
+    use crate::model::outbox::PUBLICATION_DEAD;
+
+    use super::*;
+
+    fn add_test_event(event_name: &str, connection: &PgConnection) -> Result<OutboxEvent, Error> {
+        diesel::insert_into(outbox_event::table)
+            .values(&NewOutboxEvent {
+                name: event_name.to_string(),
+                job_id: None,
+                payload: json!({}),
+                creation_date: current_timestamp(),
+            })
+            .get_result::<OutboxEvent>(connection)
+            .map_err(Error::DBError)
+    }
+
+    fn publication_of(event_id: i64, sink: &str, connection: &PgConnection) -> Result<(String, i32), Error> {
+        outbox_publication::table
+            .find((event_id, sink))
+            .select((outbox_publication::status, outbox_publication::attempts))
+            .get_result::<(String, i32)>(connection)
+            .map_err(Error::DBError)
+    }
+
+    fn make_due(connection: &PgConnection) -> Result<(), Error> {
+        diesel::update(outbox_publication::table)
+            .set(outbox_publication::next_attempt_at.eq(current_timestamp() - Duration::seconds(1)))
+            .execute(connection)
+            .map_err(Error::DBError)?;
+        Ok(())
+    }
+
+    #[test]
+    fn a_failed_event_is_retried_alone_until_it_is_dead() {
+        let connection = test_connection();
+        connection.test_transaction::<_, Error, _>(|| {
+            let failing_event = add_test_event("JobFailing", &connection)?;
+            let other_event = add_test_event("JobCreated", &connection)?;
+            let mut published_ids = Vec::new();
+            let mut publish = |outbox_event: &OutboxEvent| {
+                if outbox_event.id != failing_event.id {
+                    published_ids.push(outbox_event.id);
+                    return Ok(());
+                }
+                // what the failed publication wrote goes away with it.
+                add_test_event("JobSideEffect", &connection)?;
+                Err(Error::HttpRequest("the sink is down".to_string()))
+            };
+
+            let outcome = relay_outbox_events("test", 2, &connection, &mut publish)?;
+            assert_eq!(outcome.published, 1);
+            assert_eq!(outcome.failures.iter().map(|(event_id, _)| *event_id).collect::<Vec<_>>(), vec![failing_event.id]);
+            assert_eq!(publication_of(failing_event.id, "test", &connection)?, (PUBLICATION_FAILED.to_string(), 1));
+            assert_eq!(publication_of(other_event.id, "test", &connection)?, (PUBLICATION_PUBLISHED.to_string(), 1));
+            let side_effects = outbox_event::table
+                .filter(outbox_event::name.eq("JobSideEffect"))
+                .count()
+                .get_result::<i64>(&connection)?;
+            assert_eq!(side_effects, 0);
+
+            // the failed event waits for its next attempt, then is given up after the last one.
+            assert_eq!(relay_outbox_events("test", 2, &connection, &mut publish)?.failures.len(), 0);
+            make_due(&connection)?;
+            assert_eq!(relay_outbox_events("test", 2, &connection, &mut publish)?.failures.len(), 1);
+            assert_eq!(publication_of(failing_event.id, "test", &connection)?, (PUBLICATION_DEAD.to_string(), 2));
+            make_due(&connection)?;
+            let outcome = relay_outbox_events("test", 2, &connection, &mut publish)?;
+            assert_eq!((outcome.published, outcome.failures.len()), (0, 0));
+            assert_eq!(published_ids, vec![other_event.id]);
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn only_the_events_every_sink_is_done_with_are_purged() {
+        let connection = test_connection();
+        connection.test_transaction::<_, Error, _>(|| {
+            let published_event = add_test_event("JobCreated", &connection)?;
+            let pending_event = add_test_event("JobUpdated", &connection)?;
+            for sink in OUTBOX_SINKS {
+                relay_outbox_events(sink, 8, &connection, |outbox_event| {
+                    if sink == OUTBOX_SINKS[0] && outbox_event.id == pending_event.id {
+                        Err(Error::HttpRequest("the sink is down".to_string()))
+                    } else {
+                        Ok(())
+                    }
+                })?;
+            }
+            diesel::update(outbox_event::table)
+                .set(outbox_event::creation_date.eq(current_timestamp() - Duration::days(40)))
+                .execute(&connection)?;
+
+            assert_eq!(purge_outbox_events(7, &connection)?, 1);
+            let left_ids = outbox_event::table.select(outbox_event::id).load::<i64>(&connection)?;
+            assert!(left_ids.contains(&pending_event.id) && !left_ids.contains(&published_event.id));
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,471 @@
+use chrono::{Duration, NaiveDateTime};
+use diesel::sql_types::{BigInt, Timestamp};
+use diesel::{Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::engine::event::record_job_event;
+use crate::engine::job::{lock_job, record_status_change, write_failed};
+use crate::errors::Error;
+use crate::errors::StateCode::JobFinished;
+use crate::model::event::{JobEventKind, SYSTEM_ACTOR};
+use crate::model::job::{Job, JobStatus};
+use crate::model::progress::{
+    DownloadRates, JobProgress, NewJobProgress, ProgressPoint, ProgressReportDTO, ProgressSeriesDTO,
+    ProgressSeriesQuery,
+};
+use crate::schema::{job, job_progress};
+use crate::util::utils::current_timestamp;
+
+// The current speed is read over the samples of the last seconds.
+const CURRENT_SPEED_WINDOW_SECONDS: i64 = 30;
+// A progress is sampled at most once in this period, unless the download moved to another mirror or ended.
+const SAMPLE_INTERVAL_SECONDS: i64 = 5;
+// A longer series is downsampled to fit.
+const MAX_SERIES_POINTS: i64 = 1000;
+
+fn percent_of(downloaded_size: i32, total_size: i32) -> i32 {
+    if total_size <= 0 {
+        return 100;
+    }
+    (i64::from(downloaded_size) * 100 / i64::from(total_size)) as i32
+}
+
+// Whether the progress of the job is worth a new sample, the samples of an attempt are kept periodic.
+fn sample_due(sampled_job: &Job, connection: &PgConnection) -> Result<bool, Error> {
+    let last_sample = job_progress::table
+        .filter(job_progress::job_id.eq(sampled_job.id))
+        .filter(job_progress::attempt.eq(sampled_job.attempts))
+        .order_by((job_progress::sampled_at.desc(), job_progress::id.desc()))
+        .select((job_progress::sampled_at, job_progress::mirror))
+        .first::<(NaiveDateTime, Option<String>)>(connection)
+        .optional()
+        .map_err(Error::DBError)?;
+    Ok(match last_sample {
+        Some((last_sampled_at, last_mirror)) => {
+            current_timestamp() - last_sampled_at >= Duration::seconds(SAMPLE_INTERVAL_SECONDS)
+                || last_mirror != sampled_job.mirror
+                || sampled_job.downloaded_size == sampled_job.total_size
+        }
+        None => true,
+    })
+}
+
+pub(crate) fn record_sample(sampled_job: &Job, connection: &PgConnection) -> Result<(), Error> {
+    diesel::insert_into(job_progress::table)
+        .values(&NewJobProgress {
+            job_id: sampled_job.id,
+            attempt: sampled_job.attempts,
+            downloaded_size: sampled_job.downloaded_size,
+            mirror: sampled_job.mirror.clone(),
+            sampled_at: current_timestamp(),
+        })
+        .execute(connection)
+        .map_err(Error::DBError)?;
+    Ok(())
+}
+
+// A completed or expired job keeps its progress, a download of it is refused.
+fn check_unfinished(found_job: &Job) -> Result<(), Error> {
+    if found_job.status == JobStatus::Completed.get_name() || found_job.status == JobStatus::Expired.get_name() {
+        return Err(Error::Conflict(JobFinished));
+    }
+    Ok(())
+}
+
+fn is_running(found_job: &Job) -> bool {
+    found_job.status == JobStatus::Active.get_name() || found_job.status == JobStatus::Stalled.get_name()
+}
+
+// Start a new download attempt of the job from the mirror, its first sample is taken at once.
+pub fn start_download(other_job_id: &Uuid, mirror: Option<&str>, connection: &PgConnection) -> Result<Job, Error> {
+    connection.transaction(|| {
+        let found_job = lock_job(other_job_id, None, connection)?;
+        check_unfinished(&found_job)?;
+        let started_job = diesel::update(job::table.find(other_job_id))
+            .set((
+                job::status.eq(JobStatus::Active.get_name()),
+                job::attempts.eq(found_job.attempts + 1),
+                job::mirror.eq(mirror),
+                job::started_at.eq(found_job.started_at.unwrap_or_else(current_timestamp)),
+                job::finished_at.eq(Option::<NaiveDateTime>::None),
+                job::progressed_at.eq(current_timestamp()),
+            ))
+            .get_result::<Job>(connection)
+            .map_err(write_failed)?;
+        record_sample(&started_job, connection)?;
+        record_status_change(&found_job, &started_job.status, SYSTEM_ACTOR, connection)?;
+        Ok(started_job)
+    })
+}
+
+// Record how much of the job is downloaded, a mirror given means the download moved to it.
+// A stalled job that moves forward again is active, a job that is not running starts a new attempt.
+pub fn record_progress(
+    other_job_id: &Uuid,
+    other_downloaded_size: i32,
+    mirror: Option<&str>,
+    connection: &PgConnection,
+) -> Result<Job, Error> {
+    connection.transaction(|| {
+        let mut found_job = lock_job(other_job_id, None, connection)?;
+        check_unfinished(&found_job)?;
+        if found_job.attempts == 0 || !is_running(&found_job) {
+            found_job = start_download(other_job_id, mirror, connection)?;
+        }
+        let moved_forward = other_downloaded_size > found_job.downloaded_size;
+        let new_status = if moved_forward && found_job.status == JobStatus::Stalled.get_name() {
+            JobStatus::Active.get_name()
+        } else {
+            found_job.status.as_str()
+        }
+        .to_string();
+        let other_progressed_at = if moved_forward {
+            Some(current_timestamp())
+        } else {
+            found_job.progressed_at
+        };
+        let previous_job = found_job.clone();
+        found_job.downloaded_size = other_downloaded_size;
+        found_job.percent_downloaded = percent_of(other_downloaded_size, found_job.total_size);
+        found_job.check().map_err(Error::ValidationError)?;
+
+        let sampled_job = diesel::update(job::table.find(other_job_id))
+            .set((
+                job::downloaded_size.eq(found_job.downloaded_size),
+                job::percent_downloaded.eq(found_job.percent_downloaded),
+                job::mirror.eq(mirror.or(found_job.mirror.as_deref())),
+                job::status.eq(&new_status),
+                job::progressed_at.eq(other_progressed_at),
+            ))
+            .get_result::<Job>(connection)
+            .map_err(Error::DBError)?;
+        record_status_change(&previous_job, &sampled_job.status, SYSTEM_ACTOR, connection)?;
+        if sample_due(&sampled_job, connection)? {
+            record_sample(&sampled_job, connection)?;
+        }
+        Ok(sampled_job)
+    })
+}
+
+// End the current attempt of the job on an error, it is kept to be shown with the job.
+pub fn fail_download(other_job_id: &Uuid, message: &str, connection: &PgConnection) -> Result<Job, Error> {
+    connection.transaction(|| {
+        let found_job = lock_job(other_job_id, None, connection)?;
+        let failed_job = diesel::update(job::table.find(other_job_id))
+            .set((
+                job::status.eq(JobStatus::Failed.get_name()),
+                job::last_error.eq(message),
+                job::finished_at.eq(current_timestamp()),
+            ))
+            .get_result::<Job>(connection)
+            .map_err(Error::DBError)?;
+        let details = json!({ "message": message, "attempt": failed_job.attempts, "mirror": failed_job.mirror });
+        record_job_event(other_job_id, JobEventKind::Error, SYSTEM_ACTOR, details, connection)?;
+        record_status_change(&found_job, &failed_job.status, SYSTEM_ACTOR, connection)?;
+        Ok(failed_job)
+    })
+}
+
+// Apply what the downloader reports of the job: a new attempt, the downloaded size, then the error
+// that ended the attempt, all at once.
+pub fn report_progress(
+    other_job_id: &Uuid,
+    progress_report: &ProgressReportDTO,
+    connection: &PgConnection,
+) -> Result<Job, Error> {
+    connection.transaction(|| {
+        let mirror = progress_report.mirror.as_deref();
+        if progress_report.new_attempt {
+            start_download(other_job_id, mirror, connection)?;
+        }
+        let reported_job = record_progress(other_job_id, progress_report.downloaded_size, mirror, connection)?;
+        match &progress_report.error {
+            Some(message) => fail_download(other_job_id, message, connection),
+            None => Ok(reported_job),
+        }
+    })
+}
+
+// The samples of every attempt of the job, in the order they were taken.
+pub fn get_progress_samples(other_job_id: &Uuid, connection: &PgConnection) -> Result<Vec<JobProgress>, Error> {
+    job_progress::table
+        .filter(job_progress::job_id.eq(other_job_id))
+        .order_by((job_progress::attempt.asc(), job_progress::sampled_at.asc(), job_progress::id.asc()))
+        .load::<JobProgress>(connection)
+        .map_err(Error::DBError)
+}
+
+// The points of the progress of samples ordered by attempt then time, one per step when a step is given:
+// the last sample of the step, and the first one of each attempt to keep where it started.
+pub fn progress_series(samples: &[JobProgress], step_seconds: Option<i64>) -> Vec<ProgressPoint> {
+    let bucket = |sample: &JobProgress| step_seconds.map(|step| sample.sampled_at.timestamp().div_euclid(step));
+    let mut points: Vec<ProgressPoint> = Vec::new();
+    let mut previous: Option<&JobProgress> = None;
+    for (index, sample) in samples.iter().enumerate() {
+        let next = samples.get(index + 1);
+        let first_of_attempt = index == 0 || samples[index - 1].attempt != sample.attempt;
+        let last_of_step = match next {
+            Some(next) => step_seconds.is_none() || next.attempt != sample.attempt || bucket(next) != bucket(sample),
+            None => true,
+        };
+        if !first_of_attempt && !last_of_step {
+            continue;
+        }
+        let speed = previous.filter(|previous| previous.attempt == sample.attempt).and_then(|previous| speed(previous, sample));
+        points.push(ProgressPoint {
+            sampled_at: sample.sampled_at,
+            attempt: sample.attempt,
+            downloaded_size: sample.downloaded_size,
+            mirror: sample.mirror.clone(),
+            speed,
+        });
+        previous = Some(sample);
+    }
+    points
+}
+
+// The progress series of the job between two times, a flat speed shows when it stalled.
+pub fn get_progress_series(
+    other_job_id: &Uuid,
+    series_query: &ProgressSeriesQuery,
+    connection: &PgConnection,
+) -> Result<ProgressSeriesDTO, Error> {
+    // Step 1: the job must exist, the jobs in the trash keep their samples until they are purged.
+    job::table
+        .find(other_job_id)
+        .select(job::id)
+        .get_result::<Uuid>(connection)
+        .map_err(Error::DBError)?;
+
+    // Step 2: read the samples of the period.
+    let mut query = job_progress::table.filter(job_progress::job_id.eq(other_job_id)).into_boxed();
+    if let Some(since) = series_query.since {
+        query = query.filter(job_progress::sampled_at.ge(since));
+    }
+    if let Some(until) = series_query.until {
+        query = query.filter(job_progress::sampled_at.lt(until));
+    }
+    let samples = query
+        .order_by((job_progress::attempt.asc(), job_progress::sampled_at.asc(), job_progress::id.asc()))
+        .load::<JobProgress>(connection)
+        .map_err(Error::DBError)?;
+
+    // Step 3: a series too long for a chart gets a step that fits it.
+    let step_seconds = series_query.step_seconds.or_else(|| {
+        if samples.len() as i64 <= MAX_SERIES_POINTS {
+            return None;
+        }
+        let first = samples.iter().map(|sample| sample.sampled_at).min()?;
+        let last = samples.iter().map(|sample| sample.sampled_at).max()?;
+        Some(((last - first).num_seconds() / MAX_SERIES_POINTS).max(1))
+    });
+    Ok(ProgressSeriesDTO {
+        job_id: *other_job_id,
+        step_seconds,
+        points: progress_series(&samples, step_seconds),
+    })
+}
+
+// Keep one sample per step, the last of it, of the samples older than `older_than_hours`. The first
+// sample of each attempt is kept too, the average speed and the active time are read from it.
+pub fn downsample_progress(older_than_hours: i64, step_seconds: i64, connection: &PgConnection) -> Result<usize, Error> {
+    let cutoff = current_timestamp() - Duration::hours(older_than_hours);
+    diesel::sql_query(
+        "DELETE FROM job_progress WHERE id IN ( \
+            SELECT id FROM ( \
+                SELECT id, \
+                    ROW_NUMBER() OVER (PARTITION BY job_id, attempt, FLOOR(EXTRACT(EPOCH FROM sampled_at) / $2) \
+                        ORDER BY sampled_at DESC, id DESC) AS rank_in_step, \
+                    ROW_NUMBER() OVER (PARTITION BY job_id, attempt ORDER BY sampled_at, id) AS rank_in_attempt \
+                FROM job_progress WHERE sampled_at < $1 \
+            ) ranked WHERE rank_in_step > 1 AND rank_in_attempt > 1)",
+    )
+    .bind::<Timestamp, _>(cutoff)
+    .bind::<BigInt, _>(step_seconds)
+    .execute(connection)
+    .map_err(Error::DBError)
+}
+
+fn seconds_between(from: NaiveDateTime, to: NaiveDateTime) -> f64 {
+    (to - from).num_milliseconds() as f64 / 1000.0
+}
+
+fn speed(from: &JobProgress, to: &JobProgress) -> Option<f64> {
+    let seconds = seconds_between(from.sampled_at, to.sampled_at);
+    if seconds <= 0.0 {
+        return None;
+    }
+    Some(f64::from((to.downloaded_size - from.downloaded_size).max(0)) / seconds)
+}
+
+// The speeds of a download from its samples ordered by attempt then time. The active time is the time
+// between the first and the last sample of each attempt, the waits in the queue between them are left out.
+pub fn download_rates(samples: &[JobProgress]) -> DownloadRates {
+    let mut attempts: Vec<&[JobProgress]> = Vec::new();
+    let mut start = 0;
+    for index in 1..=samples.len() {
+        if index == samples.len() || samples[index].attempt != samples[start].attempt {
+            attempts.push(&samples[start..index]);
+            start = index;
+        }
+    }
+
+    let mut active_milliseconds = 0;
+    let mut downloaded_size = 0;
+    for attempt in &attempts {
+        let (first, last) = (&attempt[0], &attempt[attempt.len() - 1]);
+        active_milliseconds += (last.sampled_at - first.sampled_at).num_milliseconds();
+        downloaded_size += i64::from((last.downloaded_size - first.downloaded_size).max(0));
+    }
+    let average_speed = if active_milliseconds > 0 {
+        Some(downloaded_size as f64 * 1000.0 / active_milliseconds as f64)
+    } else {
+        None
+    };
+
+    // the current speed is read from the last attempt only, over the window or the last two samples.
+    let current_speed = attempts.last().and_then(|attempt| {
+        let last = &attempt[attempt.len() - 1];
+        let window_start = last.sampled_at - Duration::seconds(CURRENT_SPEED_WINDOW_SECONDS);
+        let first = attempt
+            .iter()
+            .take(attempt.len() - 1)
+            .find(|sample| sample.sampled_at >= window_start)
+            .or_else(|| attempt.len().checked_sub(2).map(|index| &attempt[index]))?;
+        speed(first, last)
+    });
+
+    DownloadRates {
+        current_speed,
+        average_speed,
+        active_seconds: active_milliseconds / 1000,
+    }
+}
+
+// The seconds left to download the job at its current speed, or at its average one when it has none.
+pub fn eta_seconds(found_job: &Job, rates: &DownloadRates) -> Option<i64> {
+    if found_job.status != JobStatus::Active.get_name() || !found_job.is_active {
+        return None;
+    }
+    let remaining_size = f64::from((found_job.total_size - found_job.downloaded_size).max(0));
+    let speed = rates
+        .current_speed
+        .filter(|speed| *speed > 0.0)
+        .or(rates.average_speed)
+        .filter(|speed| *speed > 0.0)?;
+    Some((remaining_size / speed).ceil() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db_connection::test_connection;
+    use crate::engine::job::add_test_job;
+
+    use super::*;
+
+    fn sample(attempt: i32, seconds: i64, downloaded_size: i32) -> JobProgress {
+        JobProgress {
+            id: 0,
+            job_id: Uuid::nil(),
+            attempt,
+            downloaded_size,
+            mirror: None,
+            sampled_at: NaiveDateTime::from_timestamp(1_641_980_000 + seconds, 0),
+        }
+    }
+
+    #[test]
+    fn download_rates_leave_out_the_time_between_attempts() {
+        let samples = vec![
+            sample(1, 0, 0),
+            sample(1, 10, 1000),
+            sample(2, 100, 0),
+            sample(2, 110, 500),
+            sample(2, 150, 900),
+            sample(2, 160, 1500),
+        ];
+
+        let rates = download_rates(&samples);
+
+        assert_eq!(rates.active_seconds, 70);
+        assert_eq!(rates.average_speed, Some(2500.0 / 70.0));
+        assert_eq!(rates.current_speed, Some(60.0));
+    }
+
+    #[test]
+    fn progress_series_keep_the_last_sample_of_each_step() {
+        let samples = vec![
+            sample(1, 0, 0),
+            sample(1, 20, 300),
+            sample(1, 39, 600),
+            sample(1, 70, 600),
+            sample(2, 100, 0),
+            sample(2, 130, 900),
+        ];
+
+        let points = progress_series(&samples, Some(60));
+        let kept: Vec<(i32, i32, Option<f64>)> =
+            points.iter().map(|point| (point.attempt, point.downloaded_size, point.speed)).collect();
+
+        assert_eq!(
+            kept,
+            vec![(1, 0, None), (1, 600, Some(600.0 / 39.0)), (1, 600, Some(0.0)), (2, 0, None), (2, 900, Some(30.0))]
+        );
+        assert_eq!(progress_series(&samples, None).len(), samples.len());
+    }
+
+    #[test]
+    fn download_rates_need_two_samples() {
+        assert_eq!(download_rates(&[]), DownloadRates::default());
+        assert_eq!(download_rates(&[sample(1, 0, 0)]).current_speed, None);
+        assert_eq!(download_rates(&[sample(1, 0, 0), sample(1, 90, 900)]).current_speed, Some(10.0));
+    }
+
+    #[test]
+    fn reported_progress_is_sampled_along_each_attempt() {
+        let connection = test_connection();
+        connection.test_transaction::<_, Error, _>(|| {
+            let added_job = add_test_job("progress report job", 1000, &connection);
+            let report = |downloaded_size: i32, mirror: Option<&str>, new_attempt: bool, error: Option<&str>| {
+                let progress_report = ProgressReportDTO {
+                    downloaded_size,
+                    mirror: mirror.map(str::to_string),
+                    new_attempt,
+                    error: error.map(str::to_string),
+                };
+                report_progress(&added_job.id, &progress_report, &connection)
+            };
+
+            // the first report starts the first attempt, the reports within 5 seconds are not sampled
+            // unless the download moved to another mirror.
+            let started_job = report(100, Some("http://a"), false, None)?;
+            assert_eq!((started_job.attempts, started_job.mirror.as_deref()), (1, Some("http://a")));
+            assert!(started_job.started_at.is_some() && started_job.progressed_at.is_some());
+            report(300, None, false, None)?;
+            report(400, Some("http://b"), false, None)?;
+            let failed_job = report(400, None, false, Some("connection reset"))?;
+            assert_eq!(failed_job.status, JobStatus::Failed.get_name());
+            assert_eq!(failed_job.last_error.as_deref(), Some("connection reset"));
+            assert!(failed_job.finished_at.is_some());
+
+            // the next report of the failed job starts the second attempt.
+            let restarted_job = report(0, None, false, None)?;
+            assert_eq!((restarted_job.attempts, restarted_job.status.as_str()), (2, "Active"));
+            assert_eq!(restarted_job.finished_at, None);
+            let downloaded_job = report(1000, None, false, None)?;
+            assert_eq!(downloaded_job.percent_downloaded, 100);
+
+            let samples: Vec<(i32, i32, Option<String>)> = get_progress_samples(&added_job.id, &connection)?
+                .into_iter()
+                .map(|sample| (sample.attempt, sample.downloaded_size, sample.mirror))
+                .collect();
+            let mirror = |mirror: &str| Some(mirror.to_string());
+            assert_eq!(
+                samples,
+                vec![(1, 0, mirror("http://a")), (1, 400, mirror("http://b")), (2, 400, None), (2, 1000, None)]
+            );
+            Ok(())
+        });
+    }
+}
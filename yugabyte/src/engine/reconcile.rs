@@ -0,0 +1,338 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use diesel::{ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl};
+use uuid::Uuid;
+
+use crate::engine::cas::sha256_file;
+use crate::engine::job::flag_checked_job;
+use crate::errors::Error;
+use crate::errors::StateCode::DuplicateName;
+use crate::model::event::SYSTEM_ACTOR;
+use crate::model::job::{Job, JobStatus};
+use crate::model::reconcile::ReconcileReport;
+use crate::schema::job;
+use crate::storage::{StorageBackend, StorageConfig, LOCAL_BACKEND};
+
+const BATCH_SIZE: i64 = 500;
+
+enum FileState {
+    Healthy,
+    Missing,
+    Corrupted,
+    Unreachable,
+}
+
+fn check_job_file(found_job: &Job, storage_config: &StorageConfig) -> FileState {
+    let key = match &found_job.file_path {
+        Some(key) => key,
+        None => return FileState::Missing,
+    };
+    let backend = match storage_config.backend(&found_job.storage_backend) {
+        Ok(backend) => backend,
+        Err(_) => return FileState::Unreachable,
+    };
+    let bucket = found_job.bucket.as_deref();
+
+    match backend.object_size(bucket, key) {
+        Ok(None) => FileState::Missing,
+        Ok(Some(size)) if size != found_job.total_size as u64 => FileState::Corrupted,
+        // Only local files are hashed, remote objects would have to be downloaded first.
+        Ok(Some(_)) => match (backend.local_path(bucket, key), &found_job.checksum) {
+            (Some(path), Some(expected)) => match sha256_file(&path) {
+                Ok(actual) if &actual == expected => FileState::Healthy,
+                Ok(_) => FileState::Corrupted,
+                Err(_) => FileState::Unreachable,
+            },
+            _ => FileState::Healthy,
+        },
+        Err(_) => FileState::Unreachable,
+    }
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                collect_files(&path, files);
+            } else if path.extension().is_some_and(|extension| extension == "partial") {
+                // a copy still being moved into place, not an orphan.
+                continue;
+            } else {
+                files.push(path);
+            }
+        }
+    }
+}
+
+// Files under the local storage root that no job references.
+pub fn find_orphaned_files(
+    storage_config: &StorageConfig,
+    connection: &PgConnection,
+) -> Result<Vec<String>, Error> {
+    // Step 1: collect the paths of every file a job points to.
+    let referenced: HashSet<PathBuf> = job::table
+        .filter(job::storage_backend.eq(LOCAL_BACKEND))
+        .filter(job::file_path.is_not_null())
+        .select((job::bucket, job::file_path))
+        .load::<(Option<String>, Option<String>)>(connection)
+        .map_err(Error::DBError)?
+        .into_iter()
+        .filter_map(|(other_bucket, key)| {
+            storage_config.local.local_path(other_bucket.as_deref(), &key?)
+        })
+        .collect();
+
+    // Step 2: walk the storage root.
+    let mut files = Vec::new();
+    collect_files(&storage_config.local.root, &mut files);
+    let mut orphaned_files: Vec<String> = files
+        .into_iter()
+        .filter(|path| !referenced.contains(path))
+        .map(|path| {
+            path.strip_prefix(&storage_config.local.root)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .to_string()
+        })
+        .collect();
+    orphaned_files.sort();
+    Ok(orphaned_files)
+}
+
+// Check that every completed job still has its file with the right size and hash.
+// Jobs whose file is gone are marked Missing, the ones with a different file Corrupted,
+// both are queued again to be downloaded when `requeue` is set.
+pub fn reconcile_jobs(
+    storage_config: &StorageConfig,
+    requeue: bool,
+    connection: &PgConnection,
+) -> Result<ReconcileReport, Error> {
+    let mut report = ReconcileReport::default();
+    let mut last_job_id: Option<Uuid> = None;
+
+    // Step 1: check the completed jobs batch by batch.
+    loop {
+        let mut query = job::table
+            .filter(job::status.eq(JobStatus::Completed.get_name()))
+            .order_by(job::id)
+            .limit(BATCH_SIZE)
+            .into_boxed();
+        if let Some(last_job_id) = last_job_id {
+            query = query.filter(job::id.gt(last_job_id));
+        }
+        let batch = query.load::<Job>(connection).map_err(Error::DBError)?;
+
+        for found_job in &batch {
+            report.checked += 1;
+            let new_status = match check_job_file(found_job, storage_config) {
+                FileState::Healthy => {
+                    report.healthy += 1;
+                    continue;
+                }
+                FileState::Unreachable => {
+                    report.skipped.push(found_job.id);
+                    continue;
+                }
+                FileState::Missing => JobStatus::Missing,
+                FileState::Corrupted => JobStatus::Corrupted,
+            };
+
+            // Step 2: flag the job, or send it back to the queue. A job restarted or given another file
+            // since it was checked is skipped. A job whose name a running job took meanwhile cannot be queued
+            // again, it is only flagged so that the next runs do not stumble on it.
+            let missing = new_status == JobStatus::Missing;
+            let (flagged, requeued) =
+                match flag_checked_job(found_job, new_status.clone(), requeue, SYSTEM_ACTOR, connection) {
+                    Err(Error::Conflict(DuplicateName)) if requeue => {
+                        (flag_checked_job(found_job, new_status, false, SYSTEM_ACTOR, connection)?, false)
+                    }
+                    flagged => (flagged?, requeue),
+                };
+            if flagged == 0 {
+                report.skipped.push(found_job.id);
+                continue;
+            }
+            if missing {
+                report.missing.push(found_job.id);
+            } else {
+                report.corrupted.push(found_job.id);
+            }
+            if requeued {
+                report.requeued.push(found_job.id);
+            }
+        }
+
+        last_job_id = batch.last().map(|found_job| found_job.id);
+        if (batch.len() as i64) < BATCH_SIZE {
+            break;
+        }
+    }
+
+    // Step 3: report the files no job references.
+    report.orphaned_files = find_orphaned_files(storage_config, connection)?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::Connection;
+
+    use crate::db_connection::test_connection;
+    use crate::engine::job::add_test_job;
+    use crate::storage::local::LocalStorage;
+
+    use super::*;
+
+    fn test_storage() -> StorageConfig {
+        StorageConfig {
+            default_backend: LOCAL_BACKEND.to_string(),
+            default_bucket: None,
+            local: LocalStorage::new(std::env::temp_dir().join(format!("reconcile-{}", Uuid::new_v4()))),
+            s3: None,
+        }
+    }
+
+    fn write_test_file(storage_config: &StorageConfig, key: &str, content: &str) -> PathBuf {
+        let path = storage_config.local.root.join(key);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn complete_test_job(
+        other_name: &str,
+        key: &str,
+        other_checksum: Option<String>,
+        connection: &PgConnection,
+    ) -> Result<Job, Error> {
+        let found_job = add_test_job(other_name, 10, connection);
+        let completed_job = diesel::update(job::table.find(found_job.id))
+            .set((
+                job::status.eq(JobStatus::Completed.get_name()),
+                job::storage_backend.eq(LOCAL_BACKEND),
+                job::bucket.eq(Option::<String>::None),
+                job::file_path.eq(key),
+                job::checksum.eq(other_checksum),
+            ))
+            .get_result::<Job>(connection)?;
+        Ok(completed_job)
+    }
+
+    #[test]
+    fn job_files_are_checked_by_size_and_hash() {
+        let connection = test_connection();
+        let storage_config = test_storage();
+        let path = write_test_file(&storage_config, "cas/healthy", "0123456789");
+        let expected_checksum = sha256_file(&path).unwrap();
+        connection.test_transaction::<_, Error, _>(|| {
+            let checked_job = complete_test_job("checked file job", "cas/healthy", Some(expected_checksum), &connection)?;
+            assert!(matches!(check_job_file(&checked_job, &storage_config), FileState::Healthy));
+
+            let other_hash_job = Job {
+                checksum: Some("0".repeat(64)),
+                ..checked_job.clone()
+            };
+            assert!(matches!(check_job_file(&other_hash_job, &storage_config), FileState::Corrupted));
+            let other_size_job = Job {
+                total_size: 11,
+                ..checked_job.clone()
+            };
+            assert!(matches!(check_job_file(&other_size_job, &storage_config), FileState::Corrupted));
+            let gone_job = Job {
+                file_path: Some("cas/gone".to_string()),
+                ..checked_job.clone()
+            };
+            assert!(matches!(check_job_file(&gone_job, &storage_config), FileState::Missing));
+            let remote_job = Job {
+                storage_backend: "s3".to_string(),
+                ..checked_job
+            };
+            assert!(matches!(check_job_file(&remote_job, &storage_config), FileState::Unreachable));
+            Ok(())
+        });
+        fs::remove_dir_all(&storage_config.local.root).unwrap();
+    }
+
+    #[test]
+    fn orphan_scan_skips_the_referenced_and_partial_files() {
+        let connection = test_connection();
+        let storage_config = test_storage();
+        write_test_file(&storage_config, "cas/referenced", "0123456789");
+        write_test_file(&storage_config, "cas/orphan", "0123456789");
+        write_test_file(&storage_config, "cas/moving.partial", "01234");
+        connection.test_transaction::<_, Error, _>(|| {
+            complete_test_job("referenced file job", "cas/referenced", None, &connection)?;
+
+            let orphaned_files = find_orphaned_files(&storage_config, &connection)?;
+
+            assert_eq!(orphaned_files, vec!["cas/orphan".to_string()]);
+            Ok(())
+        });
+        fs::remove_dir_all(&storage_config.local.root).unwrap();
+    }
+
+    #[test]
+    fn broken_jobs_are_requeued_unless_they_changed() {
+        let connection = test_connection();
+        let storage_config = test_storage();
+        write_test_file(&storage_config, "cas/healthy", "0123456789");
+        write_test_file(&storage_config, "cas/short", "01234");
+        connection.test_transaction::<_, Error, _>(|| {
+            let healthy_job = complete_test_job("healthy file job", "cas/healthy", None, &connection)?;
+            let missing_job = complete_test_job("missing file job", "cas/missing", None, &connection)?;
+            let corrupted_job = complete_test_job("corrupted file job", "cas/short", None, &connection)?;
+
+            // the job got another file after it was checked, it is not flagged.
+            let stale_job = Job {
+                file_path: Some("cas/before".to_string()),
+                ..missing_job.clone()
+            };
+            assert_eq!(flag_checked_job(&stale_job, JobStatus::Missing, true, SYSTEM_ACTOR, &connection)?, 0);
+            let unchanged_job = job::table.find(missing_job.id).get_result::<Job>(&connection)?;
+            assert_eq!(unchanged_job.status, JobStatus::Completed.get_name());
+
+            let report = reconcile_jobs(&storage_config, true, &connection)?;
+
+            assert_eq!(report.missing, vec![missing_job.id]);
+            assert_eq!(report.corrupted, vec![corrupted_job.id]);
+            let mut requeued_ids = vec![missing_job.id, corrupted_job.id];
+            requeued_ids.sort();
+            assert_eq!(report.requeued, requeued_ids);
+            for requeued_id in requeued_ids {
+                let requeued_job = job::table.find(requeued_id).get_result::<Job>(&connection)?;
+                assert_eq!(requeued_job.status, JobStatus::Queued.get_name());
+                assert_eq!(requeued_job.file_path, None);
+            }
+            let kept_job = job::table.find(healthy_job.id).get_result::<Job>(&connection)?;
+            assert_eq!(kept_job.status, JobStatus::Completed.get_name());
+            Ok(())
+        });
+        fs::remove_dir_all(&storage_config.local.root).unwrap();
+    }
+
+    #[test]
+    fn a_job_whose_name_was_taken_is_flagged_without_stopping_the_run() {
+        let connection = test_connection();
+        let storage_config = test_storage();
+        write_test_file(&storage_config, "cas/orphan", "0123456789");
+        connection.test_transaction::<_, Error, _>(|| {
+            let missing_job = complete_test_job("taken name job", "cas/missing", None, &connection)?;
+            let queued_job = add_test_job("Taken Name Job", 10, &connection);
+
+            let report = reconcile_jobs(&storage_config, true, &connection)?;
+
+            assert_eq!(report.missing, vec![missing_job.id]);
+            assert!(report.requeued.is_empty());
+            assert_eq!(report.orphaned_files, vec!["cas/orphan".to_string()]);
+            let flagged_job = job::table.find(missing_job.id).get_result::<Job>(&connection)?;
+            assert_eq!(flagged_job.status, JobStatus::Missing.get_name());
+            let running_job = job::table.find(queued_job.id).get_result::<Job>(&connection)?;
+            assert_eq!(running_job.status, queued_job.status);
+            Ok(())
+        });
+        fs::remove_dir_all(&storage_config.local.root).unwrap();
+    }
+}
@@ -0,0 +1,384 @@
+use std::collections::HashSet;
+
+use chrono::{Duration, NaiveDateTime};
+use diesel::sql_types::{BigInt, Nullable, Text, Timestamp};
+use diesel::{sql_query, Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl};
+use uuid::Uuid;
+
+use crate::engine::job::record_status_change;
+use crate::errors::Error;
+use crate::model::event::SYSTEM_ACTOR;
+use crate::model::job::{Job, JobStatus};
+use crate::model::retention::{RetainedJob, RetentionPolicy, RetentionReport};
+use crate::schema::job;
+use crate::storage::StorageConfig;
+use crate::util::utils::current_timestamp;
+
+#[derive(QueryableByName)]
+struct RankedJob {
+    #[sql_type = "diesel::sql_types::Uuid"]
+    id: Uuid,
+}
+
+sql_function!(fn coalesce(x: Nullable<Timestamp>, y: Timestamp) -> Timestamp);
+
+fn cutoff(days: i64) -> NaiveDateTime {
+    current_timestamp() - Duration::days(days)
+}
+
+// The jobs finished before the retention rules were tracking it count from their last update.
+fn finished_time(found_job: &Job) -> NaiveDateTime {
+    found_job.finished_at.unwrap_or(found_job.updated_at)
+}
+
+fn is_running(found_job: &Job) -> bool {
+    [JobStatus::Active, JobStatus::Queued, JobStatus::Stalled]
+        .iter()
+        .any(|running_status| found_job.status == running_status.get_name())
+}
+
+fn retained(found_job: &Job, rule: &str) -> RetainedJob {
+    RetainedJob {
+        id: found_job.id,
+        name: found_job.name.clone(),
+        file_path: found_job.file_path.clone(),
+        rule: rule.to_string(),
+    }
+}
+
+// Lock the jobs sharing the object of the job until the transaction ends, so that none of them takes
+// or drops it meanwhile. Returns the key of the object when no other job references it.
+fn lock_unshared_key(found_job: &Job, connection: &PgConnection) -> Result<Option<String>, Error> {
+    let key = match &found_job.file_path {
+        Some(key) => key,
+        None => return Ok(None),
+    };
+
+    let sharing_jobs = job::table
+        .filter(job::storage_backend.eq(&found_job.storage_backend))
+        .filter(job::file_path.eq(key))
+        .select(job::id);
+    let sharing_ids: Vec<Uuid> = match &found_job.bucket {
+        Some(other_bucket) => sharing_jobs
+            .filter(job::bucket.eq(other_bucket))
+            .for_update()
+            .load(connection),
+        None => sharing_jobs.filter(job::bucket.is_null()).for_update().load(connection),
+    }
+    .map_err(Error::DBError)?;
+
+    if sharing_ids.iter().any(|sharing_id| *sharing_id != found_job.id) {
+        Ok(None)
+    } else {
+        Ok(Some(key.clone()))
+    }
+}
+
+// Delete the object the job let go of, once the transaction dropping the reference committed.
+pub fn release_job_file(
+    found_job: &Job,
+    unshared_key: Option<String>,
+    storage_config: &StorageConfig,
+) -> Result<(), Error> {
+    match unshared_key {
+        Some(key) => storage_config
+            .backend(&found_job.storage_backend)?
+            .delete_object(found_job.bucket.as_deref(), &key),
+        None => Ok(()),
+    }
+}
+
+// Delete the job for good when it still passes `purgeable` once locked, it may have changed since it was
+// picked. Returns the deleted job and the key of its file when no other job shares it, for the caller to
+// release once the deletion is committed.
+pub(crate) fn delete_purgeable_job<P>(
+    other_job_id: &Uuid,
+    purgeable: P,
+    connection: &PgConnection,
+) -> Result<Option<(Job, Option<String>)>, Error>
+where
+    P: Fn(&Job) -> bool,
+{
+    connection.transaction::<_, Error, _>(|| {
+        let locked_job = job::table
+            .find(other_job_id)
+            .for_update()
+            .get_result::<Job>(connection)
+            .optional()
+            .map_err(Error::DBError)?;
+        let locked_job = match locked_job {
+            Some(locked_job) if purgeable(&locked_job) => locked_job,
+            _ => return Ok(None),
+        };
+        let unshared_key = lock_unshared_key(&locked_job, connection)?;
+        diesel::delete(job::table.find(other_job_id))
+            .execute(connection)
+            .map_err(Error::DBError)?;
+        Ok(Some((locked_job, unshared_key)))
+    })
+}
+
+// Delete the job for good, with its file unless another job shares it. Returns the purged job.
+pub(crate) fn purge_job<P>(
+    other_job_id: &Uuid,
+    purgeable: P,
+    storage_config: &StorageConfig,
+    connection: &PgConnection,
+) -> Result<Option<Job>, Error>
+where
+    P: Fn(&Job) -> bool,
+{
+    match delete_purgeable_job(other_job_id, purgeable, connection)? {
+        Some((purged_job, unshared_key)) => {
+            release_job_file(&purged_job, unshared_key, storage_config)?;
+            Ok(Some(purged_job))
+        }
+        None => Ok(None),
+    }
+}
+
+// Apply the retention rules, with `dry_run` nothing is removed and the report shows what would be.
+pub fn apply_retention(
+    policy: &RetentionPolicy,
+    storage_config: &StorageConfig,
+    dry_run: bool,
+    connection: &PgConnection,
+) -> Result<RetentionReport, Error> {
+    let mut report = RetentionReport {
+        dry_run,
+        policy: policy.clone(),
+        ..Default::default()
+    };
+    let mut purged = HashSet::new();
+
+    // Step 1: drop the files of the old completed jobs, the jobs stay as Expired.
+    if let Some(days) = policy.completed_files_days {
+        let completed_before = cutoff(days);
+        let expirable = |found_job: &Job| {
+            found_job.status == JobStatus::Completed.get_name()
+                && found_job.file_path.is_some()
+                && finished_time(found_job) < completed_before
+        };
+        let completed_jobs = job::table
+            .filter(job::status.eq(JobStatus::Completed.get_name()))
+            .filter(coalesce(job::finished_at, job::updated_at).lt(completed_before))
+            .filter(job::file_path.is_not_null())
+            .load::<Job>(connection)
+            .map_err(Error::DBError)?;
+        for found_job in completed_jobs {
+            let expired_job = if dry_run {
+                found_job
+            } else {
+                // the job expires with its event, or not at all. It is locked first, it may have been
+                // restarted or changed its file since it was picked.
+                let expired = connection.transaction::<_, Error, _>(|| {
+                    let locked_job = job::table
+                        .find(found_job.id)
+                        .for_update()
+                        .get_result::<Job>(connection)
+                        .optional()
+                        .map_err(Error::DBError)?;
+                    let locked_job = match locked_job {
+                        Some(locked_job) if expirable(&locked_job) => locked_job,
+                        _ => return Ok(None),
+                    };
+                    let unshared_key = lock_unshared_key(&locked_job, connection)?;
+                    diesel::update(job::table.find(locked_job.id))
+                        .set((
+                            job::status.eq(JobStatus::Expired.get_name()),
+                            job::file_path.eq(Option::<String>::None),
+                        ))
+                        .execute(connection)
+                        .map_err(Error::DBError)?;
+                    record_status_change(&locked_job, JobStatus::Expired.get_name(), SYSTEM_ACTOR, connection)?;
+                    Ok(Some((locked_job, unshared_key)))
+                })?;
+                match expired {
+                    Some((expired_job, unshared_key)) => {
+                        release_job_file(&expired_job, unshared_key, storage_config)?;
+                        expired_job
+                    }
+                    None => continue,
+                }
+            };
+            report.expired_files.push(retained(&expired_job, "completed_files_days"));
+        }
+    }
+
+    // Step 2: delete the old failed jobs.
+    if let Some(days) = policy.failed_jobs_days {
+        let failed_before = cutoff(days);
+        let failed_jobs = job::table
+            .filter(job::status.eq(JobStatus::Failed.get_name()))
+            .filter(coalesce(job::finished_at, job::updated_at).lt(failed_before))
+            .load::<Job>(connection)
+            .map_err(Error::DBError)?;
+        for found_job in failed_jobs {
+            let purgeable = |locked_job: &Job| {
+                locked_job.status == JobStatus::Failed.get_name() && finished_time(locked_job) < failed_before
+            };
+            if !dry_run && purge_job(&found_job.id, purgeable, storage_config, connection)?.is_none() {
+                continue;
+            }
+            purged.insert(found_job.id);
+            report.purged_jobs.push(retained(&found_job, "failed_jobs_days"));
+        }
+    }
+
+    // Step 3: keep the newest finished jobs of every name, whatever its case. The jobs still downloading
+    // and the ones in the trash are not counted.
+    if let Some(keep) = policy.keep_last_per_name {
+        let surplus_ids: Vec<Uuid> = sql_query(
+            "SELECT id FROM (
+                SELECT id, ROW_NUMBER() OVER (PARTITION BY LOWER(name) ORDER BY creation_date DESC, id DESC) AS position
+                FROM job
+                WHERE status NOT IN ($1, $2, $3) AND deleted_at IS NULL
+            ) ranked
+            WHERE position > $4",
+        )
+        .bind::<Text, _>(JobStatus::Active.get_name())
+        .bind::<Text, _>(JobStatus::Queued.get_name())
+        .bind::<Text, _>(JobStatus::Stalled.get_name())
+        .bind::<BigInt, _>(keep.max(0))
+        .load::<RankedJob>(connection)
+        .map_err(Error::DBError)?
+        .into_iter()
+        .map(|ranked_job| ranked_job.id)
+        .filter(|ranked_job_id| !purged.contains(ranked_job_id))
+        .collect();
+        let surplus_jobs = job::table
+            .filter(job::id.eq_any(&surplus_ids))
+            .order_by(job::creation_date)
+            .load::<Job>(connection)
+            .map_err(Error::DBError)?;
+        for found_job in surplus_jobs {
+            let purgeable = |locked_job: &Job| !is_running(locked_job) && locked_job.deleted_at.is_none();
+            if !dry_run && purge_job(&found_job.id, purgeable, storage_config, connection)?.is_none() {
+                continue;
+            }
+            report.purged_jobs.push(retained(&found_job, "keep_last_per_name"));
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db_connection::test_connection;
+    use crate::engine::job::add_test_job;
+
+    use super::*;
+
+    fn finish_test_job(
+        other_job: &Job,
+        other_status: JobStatus,
+        other_finished_at: Option<NaiveDateTime>,
+        other_updated_at: NaiveDateTime,
+        connection: &PgConnection,
+    ) -> Result<(), Error> {
+        diesel::update(job::table.find(other_job.id))
+            .set((
+                job::status.eq(other_status.get_name()),
+                job::file_path.eq(Some(format!("retention-test/{}", other_job.id))),
+                job::finished_at.eq(other_finished_at),
+                job::updated_at.eq(other_updated_at),
+            ))
+            .execute(connection)?;
+        Ok(())
+    }
+
+    fn reported_ids(retained_jobs: &[RetainedJob]) -> Vec<Uuid> {
+        retained_jobs.iter().map(|retained_job| retained_job.id).collect()
+    }
+
+    #[test]
+    fn retention_counts_from_the_finish_of_the_jobs() {
+        let connection = test_connection();
+        let storage_config = StorageConfig::default();
+        let policy = RetentionPolicy {
+            completed_files_days: Some(30),
+            failed_jobs_days: Some(7),
+            keep_last_per_name: None,
+        };
+        connection.test_transaction::<_, Error, _>(|| {
+            let old_job = add_test_job("old completed job", 1000, &connection);
+            let recent_job = add_test_job("recent completed job", 1000, &connection);
+            let untracked_job = add_test_job("untracked completed job", 1000, &connection);
+            let failed_job = add_test_job("old failed job", 1000, &connection);
+            let now = current_timestamp();
+            finish_test_job(&old_job, JobStatus::Completed, Some(now - Duration::days(40)), now, &connection)?;
+            finish_test_job(&untracked_job, JobStatus::Completed, None, now - Duration::days(40), &connection)?;
+            finish_test_job(&failed_job, JobStatus::Failed, Some(now - Duration::days(10)), now, &connection)?;
+            // created long ago, but completed lately.
+            finish_test_job(&recent_job, JobStatus::Completed, Some(now - Duration::days(1)), now, &connection)?;
+            diesel::update(job::table.find(recent_job.id))
+                .set(job::creation_date.eq(now - Duration::days(60)))
+                .execute(&connection)?;
+
+            let dry_report = apply_retention(&policy, &storage_config, true, &connection)?;
+
+            let expired_ids = reported_ids(&dry_report.expired_files);
+            assert!(dry_report.dry_run);
+            assert!(expired_ids.contains(&old_job.id) && expired_ids.contains(&untracked_job.id));
+            assert!(!expired_ids.contains(&recent_job.id));
+            assert!(reported_ids(&dry_report.purged_jobs).contains(&failed_job.id));
+            let unchanged_job = job::table.find(old_job.id).get_result::<Job>(&connection)?;
+            assert_eq!(unchanged_job.status, JobStatus::Completed.get_name());
+            assert!(job::table.find(failed_job.id).get_result::<Job>(&connection).is_ok());
+
+            let report = apply_retention(&policy, &storage_config, false, &connection)?;
+
+            assert_eq!(reported_ids(&report.expired_files), expired_ids);
+            assert_eq!(reported_ids(&report.purged_jobs), reported_ids(&dry_report.purged_jobs));
+            for expired_id in [old_job.id, untracked_job.id] {
+                let expired_job = job::table.find(expired_id).get_result::<Job>(&connection)?;
+                assert_eq!(expired_job.status, JobStatus::Expired.get_name());
+                assert_eq!(expired_job.file_path, None);
+            }
+            let kept_job = job::table.find(recent_job.id).get_result::<Job>(&connection)?;
+            assert_eq!(kept_job.status, JobStatus::Completed.get_name());
+            assert!(kept_job.file_path.is_some());
+            assert!(job::table.find(failed_job.id).get_result::<Job>(&connection).optional()?.is_none());
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn retention_keeps_the_newest_finished_jobs_of_a_name() {
+        let connection = test_connection();
+        let storage_config = StorageConfig::default();
+        let policy = RetentionPolicy {
+            completed_files_days: None,
+            failed_jobs_days: None,
+            keep_last_per_name: Some(1),
+        };
+        connection.test_transaction::<_, Error, _>(|| {
+            let oldest_job = add_test_job("kept name job", 1000, &connection);
+            let now = current_timestamp();
+            finish_test_job(&oldest_job, JobStatus::Completed, Some(now), now, &connection)?;
+            let newest_job = add_test_job("Kept Name Job", 1000, &connection);
+            finish_test_job(&newest_job, JobStatus::Failed, Some(now), now, &connection)?;
+            let running_job = add_test_job("KEPT NAME JOB", 1000, &connection);
+            for (position, other_job) in [&oldest_job, &newest_job, &running_job].iter().enumerate() {
+                diesel::update(job::table.find(other_job.id))
+                    .set(job::creation_date.eq(now - Duration::days(3 - position as i64)))
+                    .execute(&connection)?;
+            }
+
+            let dry_report = apply_retention(&policy, &storage_config, true, &connection)?;
+            assert_eq!(reported_ids(&dry_report.purged_jobs), vec![oldest_job.id]);
+            assert!(job::table.find(oldest_job.id).get_result::<Job>(&connection).is_ok());
+
+            let report = apply_retention(&policy, &storage_config, false, &connection)?;
+
+            assert_eq!(reported_ids(&report.purged_jobs), vec![oldest_job.id]);
+            let left = job::table
+                .filter(job::id.eq_any(vec![oldest_job.id, newest_job.id, running_job.id]))
+                .count()
+                .get_result::<i64>(&connection)?;
+            assert_eq!(left, 2);
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,175 @@
+use chrono::{Duration, NaiveDateTime};
+use diesel::sql_types::{Nullable, Timestamp};
+use diesel::{Connection, ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::engine::event::record_job_event;
+use crate::engine::job::{lock_job, record_status_change};
+use crate::engine::progress::record_sample;
+use crate::errors::Error;
+use crate::model::alert::STALLED_REASON;
+use crate::model::event::{JobEventKind, SYSTEM_ACTOR};
+use crate::model::job::{Job, JobStatus};
+use crate::schema::job;
+use crate::util::utils::current_timestamp;
+
+sql_function!(fn coalesce(x: Nullable<Timestamp>, y: Nullable<Timestamp>, z: Timestamp) -> Timestamp);
+
+// Since when the job has made no progress: its last progress, or the start of its download, or its
+// creation for a job no download reported on yet.
+fn idle_since(found_job: &Job) -> NaiveDateTime {
+    found_job
+        .progressed_at
+        .or(found_job.started_at)
+        .unwrap_or(found_job.creation_date)
+}
+
+// The downloads with no progress for `stall_after_seconds`, the stalled ones a new attempt did not help included.
+pub fn find_stalled_jobs(stall_after_seconds: i64, connection: &PgConnection) -> Result<Vec<Job>, Error> {
+    let cutoff = current_timestamp() - Duration::seconds(stall_after_seconds);
+    let idle_since = coalesce(job::progressed_at, job::started_at, job::creation_date);
+    job::table
+        .filter(job::deleted_at.is_null())
+        .filter(job::is_active.eq(true))
+        .filter(job::status.eq_any(vec![JobStatus::Active.get_name(), JobStatus::Stalled.get_name()]))
+        .filter(idle_since.lt(cutoff))
+        .order_by((idle_since.asc(), job::id.asc()))
+        .load::<Job>(connection)
+        .map_err(Error::DBError)
+}
+
+// The mirror to reconnect to: the one after the current mirror, or the current one again without mirrors.
+pub fn next_mirror(mirrors: &[String], current: Option<&str>) -> Option<String> {
+    if mirrors.is_empty() {
+        return current.map(str::to_string);
+    }
+    let next = match current.and_then(|current| mirrors.iter().position(|mirror| mirror == current)) {
+        Some(index) => (index + 1) % mirrors.len(),
+        None => 0,
+    };
+    Some(mirrors[next].clone())
+}
+
+// Mark the job as stalled and start a new attempt on the next mirror, or fail it once it used
+// `max_attempts`. The events carry `reason: stalled`, the relay raises the alerts from them.
+// False is returned when the job moved forward in the meantime.
+pub fn handle_stalled_job(
+    other_job_id: &Uuid,
+    stall_after_seconds: i64,
+    mirrors: &[String],
+    max_attempts: i32,
+    connection: &PgConnection,
+) -> Result<bool, Error> {
+    connection.transaction(|| {
+        // Step 1: the job must still be stalled once locked.
+        let found_job = lock_job(other_job_id, None, connection)?;
+        let now = current_timestamp();
+        let idle_seconds = (now - idle_since(&found_job)).num_seconds();
+        let running = found_job.status == JobStatus::Active.get_name() || found_job.status == JobStatus::Stalled.get_name();
+        if !running || !found_job.is_active || idle_seconds < stall_after_seconds {
+            return Ok(false);
+        }
+
+        // Step 2: a job out of attempts is failed.
+        if found_job.attempts >= max_attempts {
+            let message = format!(
+                "No progress for {} seconds at {}% after {} attempts.",
+                idle_seconds, found_job.percent_downloaded, found_job.attempts
+            );
+            let failed_job = diesel::update(job::table.find(other_job_id))
+                .set((
+                    job::status.eq(JobStatus::Failed.get_name()),
+                    job::last_error.eq(&message),
+                    job::finished_at.eq(now),
+                ))
+                .get_result::<Job>(connection)
+                .map_err(Error::DBError)?;
+            let details = json!({
+                "reason": STALLED_REASON,
+                "message": message,
+                "idle_seconds": idle_seconds,
+                "attempt": failed_job.attempts,
+                "mirror": failed_job.mirror,
+            });
+            record_job_event(other_job_id, JobEventKind::Error, SYSTEM_ACTOR, details, connection)?;
+            record_status_change(&found_job, &failed_job.status, SYSTEM_ACTOR, connection)?;
+            return Ok(true);
+        }
+
+        // Step 3: the others are reconnected, to the next mirror when there are mirrors.
+        let other_mirror = next_mirror(mirrors, found_job.mirror.as_deref());
+        let stalled_job = diesel::update(job::table.find(other_job_id))
+            .set((
+                job::status.eq(JobStatus::Stalled.get_name()),
+                job::attempts.eq(found_job.attempts + 1),
+                job::mirror.eq(&other_mirror),
+                job::progressed_at.eq(now),
+            ))
+            .get_result::<Job>(connection)
+            .map_err(Error::DBError)?;
+        record_sample(&stalled_job, connection)?;
+        record_status_change(&found_job, &stalled_job.status, SYSTEM_ACTOR, connection)?;
+        let message = match &other_mirror {
+            Some(other_mirror) if found_job.mirror.as_ref() != Some(other_mirror) => format!(
+                "No progress for {} seconds at {}%, switched to the mirror {}.",
+                idle_seconds, found_job.percent_downloaded, other_mirror
+            ),
+            _ => format!("No progress for {} seconds at {}%, reconnecting.", idle_seconds, found_job.percent_downloaded),
+        };
+        let details = json!({
+            "reason": STALLED_REASON,
+            "message": message,
+            "idle_seconds": idle_seconds,
+            "attempt": stalled_job.attempts,
+            "from_mirror": found_job.mirror,
+            "to_mirror": other_mirror,
+        });
+        record_job_event(other_job_id, JobEventKind::Retried, SYSTEM_ACTOR, details, connection)?;
+        Ok(true)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db_connection::test_connection;
+    use crate::engine::job::add_test_job;
+
+    use super::*;
+
+    #[test]
+    fn next_mirror_goes_round_the_mirrors() {
+        let mirrors = vec!["http://a".to_string(), "http://b".to_string()];
+
+        assert_eq!(next_mirror(&mirrors, Some("http://a")).as_deref(), Some("http://b"));
+        assert_eq!(next_mirror(&mirrors, Some("http://b")).as_deref(), Some("http://a"));
+        assert_eq!(next_mirror(&mirrors, Some("http://c")).as_deref(), Some("http://a"));
+        assert_eq!(next_mirror(&mirrors, None).as_deref(), Some("http://a"));
+        assert_eq!(next_mirror(&[], Some("http://c")).as_deref(), Some("http://c"));
+        assert_eq!(next_mirror(&[], None), None);
+    }
+
+    #[test]
+    fn jobs_never_reported_on_stall_from_their_creation() {
+        let connection = test_connection();
+        connection.test_transaction::<_, Error, _>(|| {
+            let added_job = add_test_job("never reported job", 1000, &connection);
+            assert_eq!(added_job.progressed_at, None);
+            let stalled_ids = |connection: &PgConnection| -> Result<Vec<Uuid>, Error> {
+                Ok(find_stalled_jobs(600, connection)?.into_iter().map(|found_job| found_job.id).collect())
+            };
+            assert!(!stalled_ids(&connection)?.contains(&added_job.id));
+
+            diesel::update(job::table.find(added_job.id))
+                .set(job::creation_date.eq(current_timestamp() - Duration::hours(1)))
+                .execute(&connection)?;
+            assert!(stalled_ids(&connection)?.contains(&added_job.id));
+            assert!(handle_stalled_job(&added_job.id, 600, &[], 3, &connection)?);
+
+            let stalled_job = job::table.find(added_job.id).get_result::<Job>(&connection)?;
+            assert_eq!((stalled_job.status.as_str(), stalled_job.attempts), ("Stalled", 1));
+            assert!(stalled_job.progressed_at.is_some());
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,184 @@
+use chrono::Duration;
+use diesel::{Connection, ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::engine::event::record_job_event;
+use crate::engine::job::write_failed;
+use crate::engine::audit::audited_change;
+use crate::engine::retention::{delete_purgeable_job, purge_job, release_job_file};
+use crate::errors::Error;
+use crate::model::audit::AuditedCall;
+use crate::model::event::JobEventKind;
+use crate::model::general::{PaginatedResponseDTO, PaginationDTO};
+use crate::model::job::Job;
+use crate::schema::job;
+use crate::storage::StorageConfig;
+use crate::util::utils::current_timestamp;
+
+// The jobs in the trash, the last deleted first.
+pub fn get_trashed_jobs(
+    pagination_dto: &PaginationDTO,
+    connection: &PgConnection,
+) -> Result<PaginatedResponseDTO<Job>, Error> {
+    let paginated_list = job::table
+        .filter(job::deleted_at.is_not_null())
+        .order_by((job::deleted_at.desc(), job::id.asc()))
+        .limit(pagination_dto.page_size)
+        .offset(pagination_dto.offset)
+        .load::<Job>(connection)
+        .map_err(Error::DBError)?;
+    let count = job::table
+        .filter(job::deleted_at.is_not_null())
+        .count()
+        .get_result::<i64>(connection)
+        .map_err(Error::DBError)?;
+
+    Ok(PaginatedResponseDTO {
+        paginated_list,
+        count: Some(count),
+        next: None,
+        prev: None,
+    })
+}
+
+fn lock_trashed_job(other_job_id: &Uuid, connection: &PgConnection) -> Result<Job, Error> {
+    job::table
+        .find(other_job_id)
+        .filter(job::deleted_at.is_not_null())
+        .for_update()
+        .get_result::<Job>(connection)
+        .map_err(Error::DBError)
+}
+
+// Take the job out of the trash as it was when it was deleted. The name or the reference may have been
+// taken meanwhile, the job then stays in the trash.
+pub fn restore_job(other_job_id: &Uuid, actor: &str, connection: &PgConnection) -> Result<Job, Error> {
+    connection.transaction(|| {
+        lock_trashed_job(other_job_id, connection)?;
+        let restored_job = diesel::update(job::table.find(other_job_id))
+            .set(job::deleted_at.eq(Option::<chrono::NaiveDateTime>::None))
+            .get_result::<Job>(connection)
+            .map_err(write_failed)?;
+        record_job_event(other_job_id, JobEventKind::Restored, actor, json!({}), connection)?;
+        Ok(restored_job)
+    })
+}
+
+// Delete a job of the trash for good, only a job in the trash can be purged. The purge is written to the
+// audit log in its transaction and the file is only released once both are committed.
+pub fn purge_trashed_job<A>(
+    other_job_id: &Uuid,
+    storage_config: &StorageConfig,
+    connection: &PgConnection,
+    audit: A,
+) -> Result<Job, Error>
+where
+    A: FnOnce(&Job) -> AuditedCall,
+{
+    let (purged_job, unshared_key) = audited_change(
+        &[*other_job_id],
+        connection,
+        || {
+            delete_purgeable_job(other_job_id, |locked_job| locked_job.deleted_at.is_some(), connection)?
+                .ok_or(Error::DBError(diesel::result::Error::NotFound))
+        },
+        |(purged_job, _)| audit(purged_job),
+    )?;
+    release_job_file(&purged_job, unshared_key, storage_config)?;
+    Ok(purged_job)
+}
+
+// The jobs a purge of the trash deleted, and the ones it failed to delete with why.
+#[derive(Debug, Default)]
+pub struct TrashPurge {
+    pub purged_ids: Vec<Uuid>,
+    pub failures: Vec<(Uuid, Error)>,
+}
+
+// Purge the jobs deleted more than `retention_days` ago, a job restored meanwhile is kept.
+// A job that cannot be purged does not keep the others from it.
+pub fn purge_trash(
+    retention_days: i64,
+    storage_config: &StorageConfig,
+    connection: &PgConnection,
+) -> Result<TrashPurge, Error> {
+    let cutoff = current_timestamp() - Duration::days(retention_days);
+    let expired_ids = job::table
+        .filter(job::deleted_at.lt(cutoff))
+        .select(job::id)
+        .load::<Uuid>(connection)
+        .map_err(Error::DBError)?;
+
+    let mut trash_purge = TrashPurge::default();
+    for expired_id in expired_ids {
+        let expired = |locked_job: &Job| matches!(locked_job.deleted_at, Some(other_deleted_at) if other_deleted_at < cutoff);
+        match purge_job(&expired_id, expired, storage_config, connection) {
+            Ok(Some(_)) => trash_purge.purged_ids.push(expired_id),
+            Ok(None) => {}
+            Err(e) => trash_purge.failures.push((expired_id, e)),
+        }
+    }
+    Ok(trash_purge)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db_connection::test_connection;
+    use crate::engine::job::{add_test_job, delete_job_by_id};
+    use crate::errors::StateCode::DuplicateName;
+
+    use super::*;
+
+    #[test]
+    fn trashed_jobs_leave_their_name_until_they_are_restored() {
+        let connection = test_connection();
+        connection.test_transaction::<_, Error, _>(|| {
+            let trashed_job = add_test_job("trashed name job", 1000, &connection);
+            delete_job_by_id(&trashed_job.id, None, "test", &connection)?;
+
+            let other_job = add_test_job("Trashed Name Job", 1000, &connection);
+            assert_ne!(other_job.id, trashed_job.id);
+            assert!(matches!(restore_job(&trashed_job.id, "test", &connection), Err(Error::Conflict(DuplicateName))));
+
+            delete_job_by_id(&other_job.id, None, "test", &connection)?;
+            let restored_job = restore_job(&trashed_job.id, "test", &connection)?;
+            assert_eq!(restored_job.deleted_at, None);
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn trash_purge_keeps_the_jobs_deleted_lately_or_restored() {
+        let connection = test_connection();
+        let storage_config = StorageConfig::default();
+        connection.test_transaction::<_, Error, _>(|| {
+            let expired_job = add_test_job("expired trash job", 1000, &connection);
+            let recent_job = add_test_job("recent trash job", 1000, &connection);
+            let restored_job = add_test_job("restored trash job", 1000, &connection);
+            for trashed_job in [&expired_job, &recent_job, &restored_job] {
+                delete_job_by_id(&trashed_job.id, None, "test", &connection)?;
+            }
+            diesel::update(job::table.filter(job::id.eq_any(vec![expired_job.id, restored_job.id])))
+                .set(job::deleted_at.eq(current_timestamp() - Duration::days(40)))
+                .execute(&connection)?;
+            restore_job(&restored_job.id, "test", &connection)?;
+
+            let trash_purge = purge_trash(30, &storage_config, &connection)?;
+
+            let purged_ids = &trash_purge.purged_ids;
+            assert!(purged_ids.contains(&expired_job.id) && trash_purge.failures.is_empty());
+            assert!(!purged_ids.contains(&recent_job.id) && !purged_ids.contains(&restored_job.id));
+            let left = job::table
+                .filter(job::id.eq_any(vec![expired_job.id, recent_job.id, restored_job.id]))
+                .count()
+                .get_result::<i64>(&connection)?;
+            assert_eq!(left, 2);
+            assert!(matches!(
+                purge_trashed_job(&restored_job.id, &storage_config, &connection, |_| unreachable!()),
+                Err(Error::DBError(diesel::result::Error::NotFound))
+            ));
+            Ok(())
+        });
+    }
+}
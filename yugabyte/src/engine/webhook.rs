@@ -0,0 +1,446 @@
+use std::time::Duration as StdDuration;
+
+use chrono::Duration;
+use diesel::pg::Pg;
+use diesel::{
+    Connection, ExpressionMethods, OptionalExtension, PgArrayExpressionMethods, PgConnection, QueryDsl, RunQueryDsl,
+};
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::errors::Error;
+use crate::model::general::{PaginatedResponseDTO, PaginationDTO};
+use crate::model::outbox::OutboxEvent;
+use crate::model::webhook::{
+    DeliveryFilterDTO, NewWebhookDelivery, NewWebhookSubscription, WebhookDelivery, WebhookSubscription,
+    WebhookSubscriptionDTO, ALL_EVENT_TYPES, DELIVERY_DELIVERED, DELIVERY_FAILED, DELIVERY_PENDING,
+};
+use crate::schema::{webhook_delivery, webhook_subscription};
+use crate::util::utils::current_timestamp;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const WEBHOOK_ID: &str = "X-Webhook-Id";
+pub const WEBHOOK_EVENT: &str = "X-Webhook-Event";
+pub const WEBHOOK_TIMESTAMP: &str = "X-Webhook-Timestamp";
+pub const WEBHOOK_SIGNATURE: &str = "X-Webhook-Signature";
+
+const DELIVERY_TIMEOUT_SECONDS: u64 = 10;
+// A claimed delivery is tried again after this lease when its server died while posting it,
+// it outlasts the timeout of the post.
+const DELIVERY_LEASE_SECONDS: i64 = 60;
+// The most deliveries a server posts in one run, the others wait for the next one.
+const DELIVERY_BATCH_SIZE: usize = 100;
+const FIRST_RETRY_SECONDS: i64 = 10;
+const MAX_RETRY_SECONDS: i64 = 3600;
+
+pub fn create_subscription(
+    subscription_dto: &WebhookSubscriptionDTO,
+    connection: &PgConnection,
+) -> Result<WebhookSubscription, Error> {
+    let mut event_types = subscription_dto.event_types.clone();
+    event_types.sort();
+    event_types.dedup();
+    diesel::insert_into(webhook_subscription::table)
+        .values(&NewWebhookSubscription {
+            id: Uuid::new_v4(),
+            url: subscription_dto.url.clone(),
+            event_types,
+            secret: subscription_dto.secret.clone(),
+        })
+        .get_result::<WebhookSubscription>(connection)
+        .map_err(Error::DBError)
+}
+
+// The subscriptions, the newest first.
+pub fn get_subscriptions(
+    pagination_dto: &PaginationDTO,
+    connection: &PgConnection,
+) -> Result<PaginatedResponseDTO<WebhookSubscription>, Error> {
+    let paginated_list = webhook_subscription::table
+        .order_by((webhook_subscription::creation_date.desc(), webhook_subscription::id.asc()))
+        .limit(pagination_dto.page_size)
+        .offset(pagination_dto.offset)
+        .load::<WebhookSubscription>(connection)
+        .map_err(Error::DBError)?;
+    let count = webhook_subscription::table
+        .count()
+        .get_result::<i64>(connection)
+        .map_err(Error::DBError)?;
+
+    Ok(PaginatedResponseDTO {
+        paginated_list,
+        count: Some(count),
+        next: None,
+        prev: None,
+    })
+}
+
+pub fn find_subscription(subscription_id: &Uuid, connection: &PgConnection) -> Result<WebhookSubscription, Error> {
+    webhook_subscription::table
+        .find(subscription_id)
+        .get_result::<WebhookSubscription>(connection)
+        .map_err(Error::DBError)
+}
+
+// Drop the subscription with its delivery log.
+pub fn delete_subscription(subscription_id: &Uuid, connection: &PgConnection) -> Result<(), Error> {
+    match diesel::delete(webhook_subscription::table.find(subscription_id)).execute(connection) {
+        Ok(0) => Err(Error::DBError(diesel::result::Error::NotFound)),
+        Ok(_) => Ok(()),
+        Err(e) => Err(Error::DBError(e)),
+    }
+}
+
+// Queue the outbox event for every subscription asking for it, the relay calls it in the transaction
+// recording the publication so that an event is queued once.
+pub fn enqueue_webhook_deliveries(outbox_event: &OutboxEvent, connection: &PgConnection) -> Result<usize, Error> {
+    let event_type = match outbox_event.event_type() {
+        Some(event_type) => event_type,
+        None => return Ok(0),
+    };
+    let subscriptions = webhook_subscription::table
+        .filter(webhook_subscription::event_types.overlaps_with(vec![event_type.to_string(), ALL_EVENT_TYPES.to_string()]))
+        .load::<WebhookSubscription>(connection)
+        .map_err(Error::DBError)?;
+    if subscriptions.is_empty() {
+        return Ok(0);
+    }
+
+    let now = current_timestamp();
+    let new_deliveries: Vec<NewWebhookDelivery> = subscriptions
+        .iter()
+        .map(|subscription| NewWebhookDelivery {
+            id: Uuid::new_v4(),
+            subscription_id: subscription.id,
+            event_type: event_type.to_string(),
+            job_id: outbox_event.job_id,
+            payload: outbox_event.payload.clone(),
+            next_attempt_at: now,
+            creation_date: now,
+        })
+        .collect();
+    diesel::insert_into(webhook_delivery::table)
+        .values(&new_deliveries)
+        .execute(connection)
+        .map_err(Error::DBError)
+}
+
+fn filtered_deliveries(
+    subscription_id: &Uuid,
+    filter: &DeliveryFilterDTO,
+) -> webhook_delivery::BoxedQuery<'static, Pg> {
+    let mut query = webhook_delivery::table
+        .filter(webhook_delivery::subscription_id.eq(*subscription_id))
+        .into_boxed();
+    if let Some(status) = &filter.status {
+        query = query.filter(webhook_delivery::status.eq(status.clone()));
+    }
+    query
+}
+
+// The delivery log of the subscription, the newest first.
+pub fn get_webhook_deliveries(
+    subscription_id: &Uuid,
+    pagination_dto: &PaginationDTO,
+    filter: &DeliveryFilterDTO,
+    connection: &PgConnection,
+) -> Result<PaginatedResponseDTO<WebhookDelivery>, Error> {
+    find_subscription(subscription_id, connection)?;
+    let paginated_list = filtered_deliveries(subscription_id, filter)
+        .order_by((webhook_delivery::creation_date.desc(), webhook_delivery::id.asc()))
+        .limit(pagination_dto.page_size)
+        .offset(pagination_dto.offset)
+        .load::<WebhookDelivery>(connection)
+        .map_err(Error::DBError)?;
+    let count = filtered_deliveries(subscription_id, filter)
+        .count()
+        .get_result::<i64>(connection)
+        .map_err(Error::DBError)?;
+
+    Ok(PaginatedResponseDTO {
+        paginated_list,
+        count: Some(count),
+        next: None,
+        prev: None,
+    })
+}
+
+// Post the delivery again, with all its attempts, whatever became of it.
+pub fn redeliver(delivery_id: &Uuid, connection: &PgConnection) -> Result<WebhookDelivery, Error> {
+    diesel::update(webhook_delivery::table.find(delivery_id))
+        .set((
+            webhook_delivery::status.eq(DELIVERY_PENDING),
+            webhook_delivery::attempts.eq(0),
+            webhook_delivery::next_attempt_at.eq(current_timestamp()),
+            webhook_delivery::delivered_at.eq(Option::<chrono::NaiveDateTime>::None),
+        ))
+        .get_result::<WebhookDelivery>(connection)
+        .map_err(Error::DBError)
+}
+
+// The signature of a payload: `sha256=` and the hex HMAC-SHA256 of `{timestamp}.{body}` keyed by the secret.
+pub fn sign_payload(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = HmacSha256::new_varkey(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+// The wait before the next attempt, doubled after every failed one.
+pub fn retry_delay(attempts: i32) -> Duration {
+    let exponent = attempts.saturating_sub(1).clamp(0, 20) as u32;
+    Duration::seconds((FIRST_RETRY_SECONDS * 2_i64.pow(exponent)).min(MAX_RETRY_SECONDS))
+}
+
+// Lease the next due delivery so that no other server posts it at the same time. A delivery is claimed
+// right before it is posted, so the lease only has to outlast one post.
+fn claim_due_delivery(connection: &PgConnection) -> Result<Option<(WebhookDelivery, WebhookSubscription)>, Error> {
+    connection.transaction(|| {
+        let now = current_timestamp();
+        let due_delivery = webhook_delivery::table
+            .filter(webhook_delivery::status.eq(DELIVERY_PENDING))
+            .filter(webhook_delivery::next_attempt_at.le(now))
+            .order_by(webhook_delivery::next_attempt_at.asc())
+            .limit(1)
+            .for_update()
+            .skip_locked()
+            .get_result::<WebhookDelivery>(connection)
+            .optional()
+            .map_err(Error::DBError)?;
+        let due_delivery = match due_delivery {
+            Some(due_delivery) => due_delivery,
+            None => return Ok(None),
+        };
+        diesel::update(webhook_delivery::table.find(due_delivery.id))
+            .set(webhook_delivery::next_attempt_at.eq(now + Duration::seconds(DELIVERY_LEASE_SECONDS)))
+            .execute(connection)
+            .map_err(Error::DBError)?;
+
+        let subscription = webhook_subscription::table
+            .find(due_delivery.subscription_id)
+            .get_result::<WebhookSubscription>(connection)
+            .map_err(Error::DBError)?;
+        Ok(Some((due_delivery, subscription)))
+    })
+}
+
+// Post the delivery, the status code of the receiver is returned with the error when it refused it.
+fn post_delivery(delivery: &WebhookDelivery, subscription: &WebhookSubscription) -> Result<u16, (Option<u16>, String)> {
+    let body = delivery.payload.to_string();
+    let timestamp = current_timestamp().timestamp();
+    let response = ureq::post(&subscription.url)
+        .timeout(StdDuration::from_secs(DELIVERY_TIMEOUT_SECONDS))
+        .set("Content-Type", "application/json")
+        .set(WEBHOOK_ID, &delivery.id.to_string())
+        .set(WEBHOOK_EVENT, &delivery.event_type)
+        .set(WEBHOOK_TIMESTAMP, &timestamp.to_string())
+        .set(WEBHOOK_SIGNATURE, &sign_payload(&subscription.secret, timestamp, &body))
+        .send_string(&body);
+    match response {
+        Ok(response) => Ok(response.status()),
+        Err(ureq::Error::Status(code, response)) => {
+            let mut message = response.into_string().unwrap_or_default();
+            message.truncate(1000);
+            Err((Some(code), format!("the receiver answered {}: {}", code, message)))
+        }
+        Err(ureq::Error::Transport(transport)) => Err((None, transport.to_string())),
+    }
+}
+
+// Post the due deliveries, a failed one is tried again later until it used `max_attempts`.
+// The number of delivered and of failed attempts is returned.
+pub fn deliver_due_webhooks(max_attempts: i32, connection: &PgConnection) -> Result<(usize, usize), Error> {
+    let mut outcome = (0, 0);
+    for _ in 0..DELIVERY_BATCH_SIZE {
+        let (delivery, subscription) = match claim_due_delivery(connection)? {
+            Some(claimed) => claimed,
+            None => break,
+        };
+        let attempts = delivery.attempts + 1;
+        let now = current_timestamp();
+        let updated = match post_delivery(&delivery, &subscription) {
+            Ok(status_code) => {
+                outcome.0 += 1;
+                diesel::update(webhook_delivery::table.find(delivery.id))
+                    .set((
+                        webhook_delivery::status.eq(DELIVERY_DELIVERED),
+                        webhook_delivery::attempts.eq(attempts),
+                        webhook_delivery::last_status_code.eq(Some(i32::from(status_code))),
+                        webhook_delivery::last_error.eq(Option::<String>::None),
+                        webhook_delivery::delivered_at.eq(Some(now)),
+                    ))
+                    .execute(connection)
+            }
+            Err((status_code, message)) => {
+                outcome.1 += 1;
+                let status = if attempts >= max_attempts { DELIVERY_FAILED } else { DELIVERY_PENDING };
+                diesel::update(webhook_delivery::table.find(delivery.id))
+                    .set((
+                        webhook_delivery::status.eq(status),
+                        webhook_delivery::attempts.eq(attempts),
+                        webhook_delivery::next_attempt_at.eq(now + retry_delay(attempts)),
+                        webhook_delivery::last_status_code.eq(status_code.map(i32::from)),
+                        webhook_delivery::last_error.eq(Some(message)),
+                    ))
+                    .execute(connection)
+            }
+        };
+        updated.map_err(Error::DBError)?;
+    }
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::thread;
+
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+
+    use crate::db_connection::test_connection;
+
+    use super::*;
+
+    #[derive(Clone, Debug)]
+    struct ReceivedPost {
+        path: String,
+        headers: HashMap<String, String>,
+        body: String,
+    }
+
+    // A receiver that refuses the first post of `/flaky` and every post of `/down`.
+    fn receiver(req: HttpRequest, body: web::Bytes, posts: web::Data<Arc<Mutex<Vec<ReceivedPost>>>>) -> HttpResponse {
+        let mut posts = posts.lock().unwrap();
+        let path = req.path().to_string();
+        let flaky_posts = posts.iter().filter(|post| post.path == "/flaky").count();
+        posts.push(ReceivedPost {
+            path: path.clone(),
+            headers: req
+                .headers()
+                .iter()
+                .map(|(name, value)| (name.as_str().to_lowercase(), value.to_str().unwrap_or_default().to_string()))
+                .collect(),
+            body: String::from_utf8(body.to_vec()).unwrap(),
+        });
+        match path.as_str() {
+            "/flaky" if flaky_posts > 0 => HttpResponse::Ok().finish(),
+            _ => HttpResponse::InternalServerError().body("try again"),
+        }
+    }
+
+    fn start_receiver() -> (String, Arc<Mutex<Vec<ReceivedPost>>>) {
+        let posts = Arc::new(Mutex::new(Vec::new()));
+        let server_posts = posts.clone();
+        let (sender, addresses) = mpsc::channel();
+        thread::spawn(move || {
+            let mut system = actix_web::rt::System::new("webhook-receiver");
+            let server = HttpServer::new(move || {
+                App::new()
+                    .data(server_posts.clone())
+                    .default_service(web::route().to(receiver))
+            })
+            .workers(1)
+            .bind("127.0.0.1:0")
+            .unwrap();
+            sender.send(server.addrs()[0]).unwrap();
+            system.block_on(server.run()).unwrap();
+        });
+        (format!("http://{}", addresses.recv().unwrap()), posts)
+    }
+
+    fn add_test_delivery(url: String, secret: &str, connection: &PgConnection) -> Result<WebhookDelivery, Error> {
+        let subscription = create_subscription(
+            &WebhookSubscriptionDTO {
+                url,
+                event_types: vec![ALL_EVENT_TYPES.to_string()],
+                secret: secret.to_string(),
+            },
+            connection,
+        )?;
+        let now = current_timestamp();
+        diesel::insert_into(webhook_delivery::table)
+            .values(&NewWebhookDelivery {
+                id: Uuid::new_v4(),
+                subscription_id: subscription.id,
+                event_type: "job.completed".to_string(),
+                job_id: None,
+                payload: serde_json::json!({ "name": "delivered job" }),
+                next_attempt_at: now,
+                creation_date: now,
+            })
+            .get_result::<WebhookDelivery>(connection)
+            .map_err(Error::DBError)
+    }
+
+    fn make_due(connection: &PgConnection) -> Result<(), Error> {
+        diesel::update(webhook_delivery::table)
+            .set(webhook_delivery::next_attempt_at.eq(current_timestamp() - Duration::seconds(1)))
+            .execute(connection)
+            .map_err(Error::DBError)?;
+        Ok(())
+    }
+
+    #[test]
+    fn deliveries_are_signed_and_retried_until_they_fail() {
+        let (url, posts) = start_receiver();
+        let connection = test_connection();
+        connection.test_transaction::<_, Error, _>(|| {
+            let flaky_delivery = add_test_delivery(format!("{}/flaky", url), "flaky receiver secret", &connection)?;
+            let down_delivery = add_test_delivery(format!("{}/down", url), "down receiver secret", &connection)?;
+
+            // Step 1: both receivers answer 500, the deliveries wait for their next attempt.
+            assert_eq!(deliver_due_webhooks(2, &connection)?, (0, 2));
+            assert_eq!(deliver_due_webhooks(2, &connection)?, (0, 0));
+            for delivery_id in [flaky_delivery.id, down_delivery.id] {
+                let retried = webhook_delivery::table.find(delivery_id).get_result::<WebhookDelivery>(&connection)?;
+                assert_eq!((retried.status.as_str(), retried.attempts), (DELIVERY_PENDING, 1));
+                assert_eq!(retried.last_status_code, Some(500));
+            }
+
+            // Step 2: the flaky receiver takes the post again, the other one used its attempts.
+            make_due(&connection)?;
+            assert_eq!(deliver_due_webhooks(2, &connection)?, (1, 1));
+            let delivered = webhook_delivery::table.find(flaky_delivery.id).get_result::<WebhookDelivery>(&connection)?;
+            assert_eq!((delivered.status.as_str(), delivered.attempts), (DELIVERY_DELIVERED, 2));
+            assert!(delivered.delivered_at.is_some());
+            let failed = webhook_delivery::table.find(down_delivery.id).get_result::<WebhookDelivery>(&connection)?;
+            assert_eq!((failed.status.as_str(), failed.attempts), (DELIVERY_FAILED, 2));
+            make_due(&connection)?;
+            assert_eq!(deliver_due_webhooks(2, &connection)?, (0, 0));
+
+            let posts = posts.lock().unwrap().clone();
+            assert_eq!(posts.len(), 4);
+            for post in &posts {
+                let (delivery_id, secret) = match post.path.as_str() {
+                    "/flaky" => (flaky_delivery.id, "flaky receiver secret"),
+                    _ => (down_delivery.id, "down receiver secret"),
+                };
+                let timestamp: i64 = post.headers[&WEBHOOK_TIMESTAMP.to_lowercase()].parse().unwrap();
+                assert_eq!(post.headers[&WEBHOOK_ID.to_lowercase()], delivery_id.to_string());
+                assert_eq!(post.headers[&WEBHOOK_EVENT.to_lowercase()], "job.completed");
+                assert_eq!(post.headers[&WEBHOOK_SIGNATURE.to_lowercase()], sign_payload(secret, timestamp, &post.body));
+                assert_eq!(serde_json::from_str::<serde_json::Value>(&post.body).unwrap()["name"], "delivered job");
+            }
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn sign_payload_is_an_hmac_of_the_timestamp_and_body() {
+        let signature = sign_payload("key", 1_641_980_000, "{}");
+
+        assert_eq!(signature, "sha256=0f362ad86bdb07247fb23cb75ca4c3012d75a18ac29da11e290eead588c82a8a");
+        assert_ne!(signature, sign_payload("other key", 1_641_980_000, "{}"));
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_an_hour() {
+        assert_eq!(retry_delay(1), Duration::seconds(10));
+        assert_eq!(retry_delay(2), Duration::seconds(20));
+        assert_eq!(retry_delay(5), Duration::seconds(160));
+        assert_eq!(retry_delay(12), Duration::seconds(3600));
+        assert_eq!(retry_delay(100), Duration::seconds(3600));
+    }
+}
@@ -4,6 +4,7 @@ use std::fmt;
 use actix_web::ResponseError;
 use diesel::result::Error as DieselError;
 use paperclip::actix::web::HttpResponse;
+use paperclip::actix::Apiv2Schema;
 use serde::{Deserialize, Serialize};
 use validator::{ValidationErrors, ValidationErrorsKind};
 
@@ -11,11 +12,15 @@ use validator::{ValidationErrors, ValidationErrorsKind};
 pub enum Errors {
     BadReq(Vec<ErrorCode>),
     BadRequest(ErrorCode),
+    Conflict(ErrorCode),
+    Forbidden(ErrorCode),
     InternalServerError(ErrorCode),
     NotFound(ErrorCode),
+    PreconditionFailed(ErrorCode),
+    PreconditionRequired(ErrorCode),
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Apiv2Schema)]
 #[serde(rename_all = "camelCase")]
 pub struct ErrorCode {
     pub error_code: String,
@@ -58,8 +63,12 @@ impl ResponseError for Errors {
         match self {
             Errors::BadReq(errors) => HttpResponse::BadRequest().json(errors),
             Errors::BadRequest(error) => HttpResponse::BadRequest().json(error),
+            Errors::Conflict(error) => HttpResponse::Conflict().json(error),
+            Errors::Forbidden(error) => HttpResponse::Forbidden().json(error),
             Errors::NotFound(errors) => HttpResponse::NotFound().json(errors),
             Errors::InternalServerError(errors) => HttpResponse::InternalServerError().json(errors),
+            Errors::PreconditionFailed(error) => HttpResponse::PreconditionFailed().json(error),
+            Errors::PreconditionRequired(error) => HttpResponse::PreconditionRequired().json(error),
         }
     }
 }
@@ -71,6 +80,30 @@ pub enum StateCode {
     DBError,
     PaginationError,
     DuplicationError,
+    FileNotReady,
+    FileNotFound,
+    SignedLinksDisabled,
+    InvalidSignature,
+    LinkExpired,
+    InvalidChecksum,
+    InvalidStorage,
+    VersionMismatch,
+    VersionRequired,
+    InvalidCursor,
+    CursorSortConflict,
+    RolledBack,
+    BulkSelectionError,
+    BulkTooLarge,
+    InvalidIdempotencyKey,
+    IdempotencyKeyReused,
+    IdempotencyKeyInProgress,
+    DuplicateName,
+    DuplicateExternalRef,
+    DeletedDuplicationError,
+    InvalidProgressStep,
+    FileTooLarge,
+    JobFinished,
+    AdminOnly,
 }
 
 impl StateCode {
@@ -81,6 +114,30 @@ impl StateCode {
             Self::DBError => "db-error",
             Self::PaginationError => "pagination-error",
             Self::DuplicationError => "duplication-error",
+            Self::FileNotReady => "file-not-ready",
+            Self::FileNotFound => "file-not-found",
+            Self::SignedLinksDisabled => "signed-links-disabled",
+            Self::InvalidSignature => "invalid-signature",
+            Self::LinkExpired => "link-expired",
+            Self::InvalidChecksum => "invalid-checksum",
+            Self::InvalidStorage => "invalid-storage",
+            Self::VersionMismatch => "version-mismatch",
+            Self::VersionRequired => "version-required",
+            Self::InvalidCursor => "invalid-cursor",
+            Self::CursorSortConflict => "cursor-sort-conflict",
+            Self::RolledBack => "rolled-back",
+            Self::BulkSelectionError => "bulk-selection-error",
+            Self::BulkTooLarge => "bulk-too-large",
+            Self::InvalidIdempotencyKey => "invalid-idempotency-key",
+            Self::IdempotencyKeyReused => "idempotency-key-reused",
+            Self::IdempotencyKeyInProgress => "idempotency-key-in-progress",
+            Self::DuplicateName => "duplicate-name",
+            Self::DuplicateExternalRef => "duplicate-external-ref",
+            Self::DeletedDuplicationError => "deleted-duplication-error",
+            Self::InvalidProgressStep => "invalid-progress-step",
+            Self::FileTooLarge => "file-too-large",
+            Self::JobFinished => "job-finished",
+            Self::AdminOnly => "admin-only",
         }
     }
     pub fn get_message(&self) -> &'static str {
@@ -90,6 +147,30 @@ impl StateCode {
             Self::DBError => "There is an error in dealing with Database.",
             Self::PaginationError => "Paginated Data is not valid.",
             Self::DuplicationError => "The object is duplicated.",
+            Self::FileNotReady => "The job has not completed its download yet.",
+            Self::FileNotFound => "The file of the job is missing.",
+            Self::SignedLinksDisabled => "Signed links are disabled, no signing key is configured.",
+            Self::InvalidSignature => "The signature of the link is not valid.",
+            Self::LinkExpired => "The link has expired.",
+            Self::InvalidChecksum => "The checksum must be a SHA-256 hex digest.",
+            Self::InvalidStorage => "The storage backend is not configured or needs a bucket.",
+            Self::VersionMismatch => "The job has changed since it was read, fetch it again and retry.",
+            Self::VersionRequired => "The If-Match header with the ETag of the job is required.",
+            Self::InvalidCursor => "The cursor is not valid, use the next or prev cursor of a page.",
+            Self::CursorSortConflict => "Cursors follow the default order, they cannot be used with sort.",
+            Self::RolledBack => "The item was rolled back because another item of the batch failed.",
+            Self::BulkSelectionError => "Pick the jobs either by `ids` or by `filter`.",
+            Self::BulkTooLarge => "A bulk request can touch at most 1000 jobs.",
+            Self::InvalidIdempotencyKey => "The Idempotency-Key header must have between 1 and 255 characters.",
+            Self::IdempotencyKeyReused => "The Idempotency-Key was already used with a different request.",
+            Self::IdempotencyKeyInProgress => "A request with the same Idempotency-Key is still being processed.",
+            Self::DuplicateName => "A running or queued job already has this name, names are compared without case.",
+            Self::DuplicateExternalRef => "Another job already has this external reference.",
+            Self::DeletedDuplicationError => "A job in the trash holds this name or reference, restore or purge it first.",
+            Self::InvalidProgressStep => "The step of a progress series must be at least one second.",
+            Self::FileTooLarge => "The file is larger than the 2 GiB a job can hold.",
+            Self::JobFinished => "The job has finished, its download takes no more progress.",
+            Self::AdminOnly => "Only the administrators may use this route.",
         }
     }
 }
@@ -112,6 +193,9 @@ pub enum Error {
     HttpRequest(String),
     DuplicationError,
     DeletedDuplicationError,
+    PreconditionFailed,
+    Conflict(StateCode),
+    ValidationError(Vec<ErrorCode>),
 }
 
 impl fmt::Display for Error {
@@ -124,6 +208,12 @@ impl fmt::Display for Error {
             Error::HttpRequest(error) => write!(f, "{}", error),
             Error::DuplicationError => write!(f, "The object is duplicated"),
             Error::DeletedDuplicationError => write!(f, "The deleted object is duplicated."),
+            Error::PreconditionFailed => write!(f, "The object has changed since it was read."),
+            Error::Conflict(state_code) => write!(f, "{}", state_code.get_message()),
+            Error::ValidationError(errors) => {
+                let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+                write!(f, "{}", messages.join(" "))
+            }
         }
     }
 }
@@ -7,4 +7,5 @@ pub mod engine;
 pub mod errors;
 pub mod model;
 pub mod schema;
+pub mod storage;
 pub mod util;
@@ -0,0 +1,132 @@
+use chrono::NaiveDateTime;
+use paperclip::actix::Apiv2Schema;
+use serde::Serialize;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::model::job::Job;
+use crate::model::outbox::OutboxEvent;
+
+// A download with no progress for too long, a new attempt was started to recover it.
+pub const ALERT_JOB_STALLED: &str = "job.stalled";
+// A stalled download that used all its attempts, it is failed.
+pub const ALERT_JOB_STALL_FAILED: &str = "job.stall_failed";
+
+// The reason in the details of the events of the stall watchdog.
+pub const STALLED_REASON: &str = "stalled";
+
+// Something wrong with a job the operators should know about, sent to the configured alert sinks.
+#[derive(Debug, Clone, Serialize, Apiv2Schema)]
+pub struct Alert {
+    // The id of the outbox event raising the alert, the same every time it is sent so that the copies can be dropped.
+    pub event_id: i64,
+    pub kind: String,
+    pub job_id: Uuid,
+    pub name: String,
+    pub status: String,
+    pub downloaded_size: i32,
+    pub percent_downloaded: i32,
+    pub attempt: i32,
+    pub mirror: Option<String>,
+    pub idle_seconds: i64,
+    pub message: String,
+    pub creation_date: NaiveDateTime,
+}
+
+impl Alert {
+    pub fn for_job(
+        event_id: i64,
+        kind: &str,
+        alerted_job: &Job,
+        idle_seconds: i64,
+        message: String,
+        now: NaiveDateTime,
+    ) -> Alert {
+        Alert {
+            event_id,
+            kind: kind.to_string(),
+            job_id: alerted_job.id,
+            name: alerted_job.name.clone(),
+            status: alerted_job.status.clone(),
+            downloaded_size: alerted_job.downloaded_size,
+            percent_downloaded: alerted_job.percent_downloaded,
+            attempt: alerted_job.attempts,
+            mirror: alerted_job.mirror.clone(),
+            idle_seconds,
+            message,
+            creation_date: now,
+        }
+    }
+
+    // The alert raised by an outbox event, only the retries and errors of the stall watchdog raise one.
+    pub fn from_outbox_event(outbox_event: &OutboxEvent) -> Option<Alert> {
+        let details = outbox_event.details();
+        if details.get("reason").and_then(Value::as_str) != Some(STALLED_REASON) {
+            return None;
+        }
+        let kind = match outbox_event.name.as_str() {
+            "JobRetried" => ALERT_JOB_STALLED,
+            "JobError" => ALERT_JOB_STALL_FAILED,
+            _ => return None,
+        };
+        let alerted_job: Job = serde_json::from_value(outbox_event.payload.get("job")?.clone()).ok()?;
+        let idle_seconds = details.get("idle_seconds").and_then(Value::as_i64).unwrap_or_default();
+        let message = details.get("message").and_then(Value::as_str).unwrap_or_default().to_string();
+        Some(Alert::for_job(outbox_event.id, kind, &alerted_job, idle_seconds, message, outbox_event.creation_date))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+    use serde_json::json;
+
+    use super::*;
+
+    fn outbox_event(name: &str, details: Value) -> OutboxEvent {
+        OutboxEvent {
+            id: 1,
+            name: name.to_string(),
+            job_id: None,
+            payload: json!({
+                "event_type": "job.retried",
+                "job": {
+                    "id": "5a4bd4b4-5c4f-4f4c-9d45-7d5d1a2b3c4d",
+                    "name": "nightly backup",
+                    "total_size": 10,
+                    "downloaded_size": 4,
+                    "percent_downloaded": 40,
+                    "status": "Stalled",
+                    "is_active": true,
+                    "creation_date": "2022-01-01T00:00:00",
+                    "expiration_date": null,
+                    "file_path": null,
+                    "expected_checksum": null,
+                    "checksum": null,
+                    "file_name": null,
+                    "storage_backend": "local",
+                    "bucket": null,
+                    "updated_at": "2022-01-01T00:00:00",
+                    "attempts": 2,
+                },
+                "details": details,
+            }),
+            creation_date: NaiveDateTime::from_timestamp(1_641_980_000, 0),
+        }
+    }
+
+    #[test]
+    fn from_outbox_event_raises_the_stall_alerts_only() {
+        let details = json!({ "reason": "stalled", "idle_seconds": 900, "message": "reconnecting" });
+
+        let alert = Alert::from_outbox_event(&outbox_event("JobRetried", details.clone())).unwrap();
+
+        assert_eq!(alert.kind, ALERT_JOB_STALLED);
+        assert_eq!((alert.attempt, alert.idle_seconds, alert.percent_downloaded), (2, 900, 40));
+        assert_eq!(alert.message, "reconnecting");
+        let failed = Alert::from_outbox_event(&outbox_event("JobError", details.clone())).unwrap();
+        assert_eq!(failed.kind, ALERT_JOB_STALL_FAILED);
+        assert!(Alert::from_outbox_event(&outbox_event("JobCreated", details)).is_none());
+        assert!(Alert::from_outbox_event(&outbox_event("JobRetried", json!({ "reason": "requeued" }))).is_none());
+    }
+}
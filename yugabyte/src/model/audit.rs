@@ -0,0 +1,65 @@
+use chrono::NaiveDateTime;
+use diesel::{Insertable, Queryable};
+use paperclip::actix::Apiv2Schema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::schema::audit_log;
+
+pub const AUDIT_SUCCESS: &str = "success";
+pub const AUDIT_FAILURE: &str = "failure";
+
+// A mutating call on the API, with the job it touched as it was before and after the call.
+#[derive(Debug, Serialize, Queryable, Apiv2Schema)]
+pub struct AuditLog {
+    pub id: i64,
+    pub creation_date: NaiveDateTime,
+    pub request_id: String,
+    pub principal: String,
+    pub source_ip: Option<String>,
+    pub method: String,
+    pub path: String,
+    pub action: String,
+    pub job_id: Option<Uuid>,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    pub status_code: i32,
+    pub result: String,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "audit_log"]
+pub struct NewAuditLog {
+    pub request_id: String,
+    pub principal: String,
+    pub source_ip: Option<String>,
+    pub method: String,
+    pub path: String,
+    pub action: String,
+    pub job_id: Option<Uuid>,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    pub status_code: i32,
+    pub result: String,
+}
+
+// The entry of a call and the jobs it changed, written in the transaction of the change.
+#[derive(Debug)]
+pub struct AuditedCall {
+    pub audit_log: NewAuditLog,
+    pub job_ids: Vec<Uuid>,
+}
+
+// Filters of the audit log, every filter left out matches all the entries.
+#[derive(Default, Deserialize, Apiv2Schema, Debug)]
+pub struct AuditFilterDTO {
+    pub principal: Option<String>,
+    pub action: Option<String>,
+    pub job_id: Option<Uuid>,
+    pub request_id: Option<String>,
+    // `success` or `failure`.
+    pub result: Option<String>,
+    pub created_after: Option<NaiveDateTime>,
+    pub created_before: Option<NaiveDateTime>,
+}
@@ -0,0 +1,62 @@
+use paperclip::actix::Apiv2Schema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::errors::ErrorCode;
+use crate::model::filter::JobFilterDTO;
+use crate::model::job::NewJob;
+
+// The most jobs one bulk request may touch.
+pub const MAX_BULK_JOBS: usize = 1000;
+
+// `Transactional` applies every item or none, `BestEffort` keeps the items that succeed.
+#[derive(Default, Serialize, Deserialize, Apiv2Schema, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkMode {
+    #[default]
+    Transactional,
+    BestEffort,
+}
+
+#[derive(Serialize, Deserialize, Apiv2Schema, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkAction {
+    Activate,
+    Deactivate,
+    Delete,
+    Requeue,
+}
+
+#[derive(Serialize, Deserialize, Apiv2Schema, Debug)]
+pub struct BulkCreateDTO {
+    pub jobs: Vec<NewJob>,
+    #[serde(default)]
+    pub mode: BulkMode,
+}
+
+// The jobs of a bulk action, picked by their ids or by a filter of the job list.
+#[derive(Deserialize, Apiv2Schema, Debug)]
+pub struct BulkSelectionDTO {
+    pub ids: Option<Vec<Uuid>>,
+    pub filter: Option<JobFilterDTO>,
+    #[serde(default)]
+    pub mode: BulkMode,
+}
+
+#[derive(Serialize, Apiv2Schema, Debug)]
+pub struct BulkItemResult {
+    pub index: usize,
+    pub id: Option<Uuid>,
+    pub success: bool,
+    pub errors: Vec<ErrorCode>,
+}
+
+#[derive(Serialize, Apiv2Schema, Debug)]
+pub struct BulkResultDTO {
+    pub mode: BulkMode,
+    // False when a transactional batch was rolled back.
+    pub committed: bool,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub results: Vec<BulkItemResult>,
+}
@@ -0,0 +1,115 @@
+use chrono::NaiveDateTime;
+use diesel::{Insertable, Queryable};
+use paperclip::actix::Apiv2Schema;
+use serde::Serialize;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::schema::email_notification;
+
+pub const EMAIL_PENDING: &str = "pending";
+pub const EMAIL_SENT: &str = "sent";
+pub const EMAIL_FAILED: &str = "failed";
+
+// One email to one recipient about one job, sent at once or with the others of the recipient in the daily digest.
+#[derive(Debug, Clone, Serialize, Queryable, Apiv2Schema)]
+pub struct EmailNotification {
+    pub id: Uuid,
+    pub job_id: Option<Uuid>,
+    pub recipient: String,
+    pub event_type: String,
+    pub subject: String,
+    pub body: String,
+    pub digest: bool,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: NaiveDateTime,
+    pub last_error: Option<String>,
+    pub creation_date: NaiveDateTime,
+    pub sent_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "email_notification"]
+pub struct NewEmailNotification {
+    pub id: Uuid,
+    pub job_id: Option<Uuid>,
+    pub recipient: String,
+    pub event_type: String,
+    pub subject: String,
+    pub body: String,
+    pub digest: bool,
+    pub next_attempt_at: NaiveDateTime,
+    pub creation_date: NaiveDateTime,
+}
+
+// The subject and body of an email, `{{field}}` is replaced by the field of the job, `{{name}}` say.
+#[derive(Debug, Clone)]
+pub struct EmailTemplate {
+    pub subject: String,
+    pub body: String,
+}
+
+// The emails of the jobs that completed and of the ones that failed.
+#[derive(Debug, Clone)]
+pub struct EmailTemplates {
+    pub completed: EmailTemplate,
+    pub failed: EmailTemplate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SmtpTls {
+    None,
+    StartTls,
+    Tls,
+}
+
+// The SMTP server the emails are sent through and the address they are sent from.
+#[derive(Debug, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    pub port: u16,
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+}
+
+// Fill the `{{field}}` placeholders of the template with the values, an unknown or null field is left empty.
+pub fn render_template(template: &str, values: &Value) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start..].find("}}") {
+            Some(end) => start + end,
+            None => break,
+        };
+        rendered.push_str(&rest[..start]);
+        match values.get(rest[start + 2..end].trim()) {
+            Some(Value::String(value)) => rendered.push_str(value),
+            Some(Value::Null) | None => {}
+            Some(value) => rendered.push_str(&value.to_string()),
+        }
+        rest = &rest[end + 2..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn render_template_fills_the_fields_of_the_job() {
+        let values = json!({ "name": "nightly backup", "total_size": 10, "last_error": null });
+
+        assert_eq!(
+            render_template("{{name}} ({{ total_size }} bytes){{last_error}}{{unknown}}", &values),
+            "nightly backup (10 bytes)"
+        );
+        assert_eq!(render_template("no placeholder {{name", &values), "no placeholder {{name");
+    }
+}
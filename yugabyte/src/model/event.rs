@@ -0,0 +1,68 @@
+use chrono::NaiveDateTime;
+use diesel::{Insertable, Queryable};
+use paperclip::actix::Apiv2Schema;
+use serde::Serialize;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::schema::job_event;
+
+// The actor of the changes made by the server itself, the background tasks included.
+pub const SYSTEM_ACTOR: &str = "system";
+
+// Something that happened to a job, in the order it happened.
+#[derive(Debug, Serialize, Queryable, Apiv2Schema)]
+pub struct JobEvent {
+    pub id: i64,
+    pub job_id: Uuid,
+    pub kind: String,
+    pub actor: String,
+    pub details: Value,
+    pub creation_date: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "job_event"]
+pub struct NewJobEvent {
+    pub job_id: Uuid,
+    pub kind: String,
+    pub actor: String,
+    pub details: Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JobEventKind {
+    Created,
+    Updated,
+    StatusChanged,
+    Activated,
+    Deactivated,
+    Retried,
+    Deleted,
+    Restored,
+    Error,
+}
+
+impl JobEventKind {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Self::Created => "created",
+            Self::Updated => "updated",
+            Self::StatusChanged => "status_changed",
+            Self::Activated => "activated",
+            Self::Deactivated => "deactivated",
+            Self::Retried => "retried",
+            Self::Deleted => "deleted",
+            Self::Restored => "restored",
+            Self::Error => "error",
+        }
+    }
+
+    pub fn activation(activate: bool) -> JobEventKind {
+        if activate {
+            Self::Activated
+        } else {
+            Self::Deactivated
+        }
+    }
+}
@@ -0,0 +1,20 @@
+use chrono::NaiveDateTime;
+use paperclip::actix::Apiv2Schema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Default, Deserialize, Apiv2Schema, Debug)]
+pub struct FileLinkDTO {
+    pub ttl_seconds: Option<i64>,
+}
+
+#[derive(Default, Deserialize, Apiv2Schema, Debug)]
+pub struct SignedFileDTO {
+    pub expires: i64,
+    pub signature: String,
+}
+
+#[derive(Serialize, Apiv2Schema, Debug)]
+pub struct SignedFileLink {
+    pub url: String,
+    pub expires_at: NaiveDateTime,
+}
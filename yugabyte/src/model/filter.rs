@@ -0,0 +1,219 @@
+use chrono::NaiveDateTime;
+use paperclip::actix::Apiv2Schema;
+use serde::Deserialize;
+
+use crate::errors::ErrorCode;
+use crate::model::job::JobStatus;
+
+// The columns the job list can be sorted by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JobSortField {
+    Name,
+    Status,
+    TotalSize,
+    DownloadedSize,
+    PercentDownloaded,
+    CreationDate,
+    ExpirationDate,
+    UpdatedAt,
+}
+
+impl JobSortField {
+    pub fn from_name(field: &str) -> Option<JobSortField> {
+        match field {
+            "name" => Some(Self::Name),
+            "status" => Some(Self::Status),
+            "total_size" => Some(Self::TotalSize),
+            "downloaded_size" => Some(Self::DownloadedSize),
+            "percent_downloaded" => Some(Self::PercentDownloaded),
+            "creation_date" => Some(Self::CreationDate),
+            "expiration_date" => Some(Self::ExpirationDate),
+            "updated_at" => Some(Self::UpdatedAt),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JobSortKey {
+    pub field: JobSortField,
+    pub descending: bool,
+}
+
+// Filters and sort order of the job list, every filter left out matches all the jobs.
+#[derive(Default, Deserialize, Apiv2Schema, Debug)]
+pub struct JobFilterDTO {
+    // Comma separated statuses, e.g. `Completed,Failed`.
+    pub status: Option<String>,
+    pub is_active: Option<bool>,
+    pub name_contains: Option<String>,
+    pub name_prefix: Option<String>,
+    pub created_after: Option<NaiveDateTime>,
+    pub created_before: Option<NaiveDateTime>,
+    pub expires_after: Option<NaiveDateTime>,
+    pub expires_before: Option<NaiveDateTime>,
+    pub min_total_size: Option<i32>,
+    pub max_total_size: Option<i32>,
+    // Comma separated tags, a job must carry all of them.
+    pub tags: Option<String>,
+    // Comma separated sort keys, e.g. `status,creation_date:desc`, a key is ascending unless `:desc`.
+    pub sort: Option<String>,
+}
+
+fn filter_error(error_code: &str, message: String) -> ErrorCode {
+    ErrorCode {
+        error_code: error_code.to_string(),
+        message,
+    }
+}
+
+fn split_list(list: &Option<String>) -> Vec<&str> {
+    list.as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+impl JobFilterDTO {
+    pub fn statuses(&self) -> Result<Vec<String>, ErrorCode> {
+        split_list(&self.status)
+            .into_iter()
+            .map(|other_status| {
+                JobStatus::from_name(other_status)
+                    .map(|job_status| job_status.get_name().to_string())
+                    .ok_or_else(|| {
+                        filter_error(
+                            "invalid-status-filter",
+                            format!("The status `{}` does not exist.", other_status),
+                        )
+                    })
+            })
+            .collect()
+    }
+
+    pub fn tag_list(&self) -> Vec<String> {
+        split_list(&self.tags).into_iter().map(str::to_string).collect()
+    }
+
+    pub fn sort_keys(&self) -> Result<Vec<JobSortKey>, ErrorCode> {
+        split_list(&self.sort)
+            .into_iter()
+            .map(|key| {
+                let (field, direction) = match key.split_once(':') {
+                    Some((field, direction)) => (field, direction),
+                    None => (key, "asc"),
+                };
+                let descending = match direction {
+                    "asc" => false,
+                    "desc" => true,
+                    _ => {
+                        return Err(filter_error(
+                            "invalid-sort",
+                            format!("The sort direction of `{}` must be `asc` or `desc`.", key),
+                        ))
+                    }
+                };
+                match JobSortField::from_name(field) {
+                    Some(field) => Ok(JobSortKey { field, descending }),
+                    None => Err(filter_error(
+                        "invalid-sort",
+                        format!("The jobs cannot be sorted by `{}`.", field),
+                    )),
+                }
+            })
+            .collect()
+    }
+
+    // Whether any filter narrows the list, the sort order does not count.
+    pub fn has_filters(&self) -> bool {
+        !split_list(&self.status).is_empty()
+            || self.is_active.is_some()
+            || self.name_contains.is_some()
+            || self.name_prefix.is_some()
+            || self.created_after.is_some()
+            || self.created_before.is_some()
+            || self.expires_after.is_some()
+            || self.expires_before.is_some()
+            || self.min_total_size.is_some()
+            || self.max_total_size.is_some()
+            || !split_list(&self.tags).is_empty()
+    }
+
+    // Every problem of the query, reported together.
+    pub fn check(&self) -> Result<(), Vec<ErrorCode>> {
+        let mut errors = Vec::new();
+        if let Err(error) = self.statuses() {
+            errors.push(error);
+        }
+        if let Err(error) = self.sort_keys() {
+            errors.push(error);
+        }
+        if let (Some(min), Some(max)) = (self.min_total_size, self.max_total_size) {
+            if min > max {
+                errors.push(filter_error(
+                    "invalid-size-range",
+                    "`min_total_size` cannot be greater than `max_total_size`.".to_string(),
+                ));
+            }
+        }
+        if let (Some(after), Some(before)) = (self.created_after, self.created_before) {
+            if after > before {
+                errors.push(filter_error(
+                    "invalid-date-range",
+                    "`created_after` cannot be later than `created_before`.".to_string(),
+                ));
+            }
+        }
+        if let (Some(after), Some(before)) = (self.expires_after, self.expires_before) {
+            if after > before {
+                errors.push(filter_error(
+                    "invalid-date-range",
+                    "`expires_after` cannot be later than `expires_before`.".to_string(),
+                ));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sort_keys_are_read_in_order() {
+        let filter = JobFilterDTO {
+            sort: Some("status, creation_date:desc,name:asc".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            filter.sort_keys().unwrap(),
+            vec![
+                JobSortKey { field: JobSortField::Status, descending: false },
+                JobSortKey { field: JobSortField::CreationDate, descending: true },
+                JobSortKey { field: JobSortField::Name, descending: false },
+            ]
+        );
+    }
+
+    #[test]
+    fn check_reports_every_invalid_parameter() {
+        let filter = JobFilterDTO {
+            status: Some("Completed,Paused".to_string()),
+            sort: Some("file_path".to_string()),
+            min_total_size: Some(10),
+            max_total_size: Some(1),
+            ..Default::default()
+        };
+        let codes: Vec<String> = filter.check().unwrap_err().into_iter().map(|e| e.error_code).collect();
+
+        assert_eq!(codes, vec!["invalid-status-filter", "invalid-sort", "invalid-size-range"]);
+    }
+}
@@ -1,14 +1,171 @@
+use chrono::NaiveDateTime;
 use paperclip::actix::Apiv2Schema;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
 
-#[derive(Default, Deserialize, Apiv2Schema, Debug)]
+use crate::errors::ErrorCode;
+
+pub const DEFAULT_PAGE_SIZE: i64 = 20;
+
+fn default_page_size() -> i64 {
+    DEFAULT_PAGE_SIZE
+}
+
+// How the total of a list is counted, an exact count scans every matching row.
+#[derive(Deserialize, Apiv2Schema, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CountMode {
+    Exact,
+    Approximate,
+    None,
+}
+
+#[derive(Deserialize, Apiv2Schema, Debug, Validate)]
 pub struct PaginationDTO {
+    #[serde(default = "default_page_size")]
+    #[validate(range(min = 1, code = "page-size-error", message = "The page size must be at least 1."))]
     pub page_size: i64,
+    #[serde(default)]
+    #[validate(range(min = 0, code = "offset-error", message = "The offset cannot be negative."))]
     pub offset: i64,
+    // The `next` or `prev` cursor of a page, the offset is ignored with it.
+    pub cursor: Option<String>,
+    // Exact unless a cursor is given, then the total is left out.
+    pub count: Option<CountMode>,
+}
+
+impl Default for PaginationDTO {
+    fn default() -> Self {
+        PaginationDTO {
+            page_size: DEFAULT_PAGE_SIZE,
+            offset: 0,
+            cursor: None,
+            count: None,
+        }
+    }
+}
+
+impl PaginationDTO {
+    // Every problem of the pagination, the largest page and offset are set by the server.
+    pub fn check(&self, max_page_size: i64, max_offset: i64) -> Result<(), Vec<ErrorCode>> {
+        let mut errors = Vec::new();
+        if let Err(validation_errors) = self.validate() {
+            ErrorCode::validate_errors(validation_errors, &mut errors);
+        }
+        if self.page_size > max_page_size {
+            errors.push(ErrorCode {
+                error_code: "page-size-error".to_string(),
+                message: format!("The page size cannot be greater than {}.", max_page_size),
+            });
+        }
+        if self.offset > max_offset {
+            errors.push(ErrorCode {
+                error_code: "offset-error".to_string(),
+                message: format!(
+                    "The offset cannot be greater than {}, follow the cursors of the pages instead.",
+                    max_offset
+                ),
+            });
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
 }
 
 #[derive(Default, Serialize, Apiv2Schema, Debug)]
 pub struct PaginatedResponseDTO<T> {
     pub paginated_list: Vec<T>,
-    pub count: i64,
-}
\ No newline at end of file
+    pub count: Option<i64>,
+    pub next: Option<String>,
+    pub prev: Option<String>,
+}
+
+// A position between two rows of a list ordered by (creation_date, id),
+// clients get it as an opaque string and send it back to move from there.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageCursor {
+    pub creation_date: NaiveDateTime,
+    pub id: Uuid,
+    pub backward: bool,
+}
+
+impl PageCursor {
+    pub fn encode(&self) -> String {
+        let direction = if self.backward { "p" } else { "n" };
+        let position = format!("{}:{}:{}", direction, self.creation_date.timestamp_nanos(), self.id);
+        base64::encode_config(position, base64::URL_SAFE_NO_PAD)
+    }
+
+    pub fn decode(cursor: &str) -> Option<PageCursor> {
+        let position = base64::decode_config(cursor, base64::URL_SAFE_NO_PAD).ok()?;
+        let position = String::from_utf8(position).ok()?;
+        let mut parts = position.splitn(3, ':');
+        let backward = match parts.next()? {
+            "p" => true,
+            "n" => false,
+            _ => return None,
+        };
+        let nanos: i64 = parts.next()?.parse().ok()?;
+        let id = Uuid::parse_str(parts.next()?).ok()?;
+        let creation_date = NaiveDateTime::from_timestamp_opt(
+            nanos.div_euclid(1_000_000_000),
+            nanos.rem_euclid(1_000_000_000) as u32,
+        )?;
+        Some(PageCursor {
+            creation_date,
+            id,
+            backward,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pagination_reports_every_problem() {
+        let pagination_dto = PaginationDTO {
+            page_size: 0,
+            offset: -1,
+            ..Default::default()
+        };
+        let mut codes: Vec<String> = pagination_dto
+            .check(100, 1000)
+            .unwrap_err()
+            .into_iter()
+            .map(|e| e.error_code)
+            .collect();
+        codes.sort();
+
+        assert_eq!(codes, vec!["offset-error", "page-size-error"]);
+    }
+
+    #[test]
+    fn pagination_is_limited_by_the_server() {
+        let pagination_dto = PaginationDTO {
+            page_size: 500,
+            offset: 5000,
+            ..Default::default()
+        };
+
+        assert_eq!(pagination_dto.check(100, 1000).unwrap_err().len(), 2);
+        assert!(PaginationDTO::default().check(100, 1000).is_ok());
+    }
+
+    #[test]
+    fn cursor_survives_a_round_trip() {
+        let cursor = PageCursor {
+            creation_date: NaiveDateTime::from_timestamp(1_642_150_000, 123_456_000),
+            id: Uuid::new_v4(),
+            backward: true,
+        };
+
+        assert_eq!(PageCursor::decode(&cursor.encode()), Some(cursor));
+        assert_eq!(PageCursor::decode("not a cursor"), None);
+    }
+}
@@ -0,0 +1,24 @@
+use chrono::NaiveDateTime;
+use diesel::{Insertable, Queryable};
+use serde::{Deserialize, Serialize};
+
+use crate::schema::idempotency_key;
+
+// A request made with an `Idempotency-Key`, the response is kept once the request succeeded.
+#[derive(Debug, Queryable, Insertable)]
+#[table_name = "idempotency_key"]
+pub struct IdempotencyKey {
+    pub key: String,
+    pub endpoint: String,
+    pub request_hash: String,
+    pub response: Option<String>,
+    pub creation_date: NaiveDateTime,
+}
+
+// A response as it is sent, so a retry gets exactly the same one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
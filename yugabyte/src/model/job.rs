@@ -1,40 +1,663 @@
 use chrono::NaiveDateTime;
 use diesel::{Insertable, Queryable};
 use paperclip::actix::Apiv2Schema;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use uuid::Uuid;
-use validator::Validate;
+use validator::{validate_email, Validate};
 
+use crate::errors::ErrorCode;
 use crate::schema::job;
-use crate::util::utils::REGEX_FULL_WORD;
+use crate::util::utils::{REGEX_BUCKET, REGEX_FULL_WORD};
+
+pub const MAX_NOTIFY_EMAILS: usize = 20;
 
 #[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Validate, Apiv2Schema, Clone)]
 #[table_name = "job"]
 pub struct Job {
     pub id: Uuid,
-    #[validate(length(min = 3, max = 49, code = "name-length-error"))]
-    #[validate(regex = "REGEX_FULL_WORD")]
+    #[validate(length(
+        min = 3,
+        max = 49,
+        code = "name-length-error",
+        message = "The name must have between 3 and 49 characters."
+    ))]
+    #[validate(regex(
+        path = "REGEX_FULL_WORD",
+        code = "name-format-error",
+        message = "The name may only have letters, spaces, dots, dashes and underscores."
+    ))]
     pub name: String,
+    #[validate(range(min = 0, code = "total-size-error", message = "The total size cannot be negative."))]
     pub total_size: i32,
+    #[validate(range(
+        min = 0,
+        code = "downloaded-size-error",
+        message = "The downloaded size cannot be negative."
+    ))]
     pub downloaded_size: i32,
+    #[validate(range(
+        min = 0,
+        max = 100,
+        code = "percent-downloaded-error",
+        message = "The percent downloaded must be between 0 and 100."
+    ))]
     pub percent_downloaded: i32,
     pub status: String,
     pub is_active: bool,
     pub creation_date: NaiveDateTime,
     pub expiration_date: Option<NaiveDateTime>,
+    pub file_path: Option<String>,
+    pub expected_checksum: Option<String>,
+    pub checksum: Option<String>,
+    pub file_name: Option<String>,
+    pub storage_backend: String,
+    pub bucket: Option<String>,
+    pub updated_at: NaiveDateTime,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[validate(length(
+        min = 1,
+        max = 255,
+        code = "external-ref-length-error",
+        message = "The external reference must have between 1 and 255 characters."
+    ))]
+    #[serde(default)]
+    pub external_ref: Option<String>,
+    // set while the job is in the trash.
+    #[serde(default)]
+    pub deleted_at: Option<NaiveDateTime>,
+    // the download attempts so far, the mirror the current one reads from and the error that ended the last one.
+    #[serde(default)]
+    pub attempts: i32,
+    #[serde(default)]
+    pub mirror: Option<String>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub started_at: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub finished_at: Option<NaiveDateTime>,
+    // the last time the download moved forward, or its attempt started.
+    #[serde(default)]
+    pub progressed_at: Option<NaiveDateTime>,
+    // who is told by email when the job completes or fails, at once or in the daily digest.
+    #[serde(default)]
+    pub notify_emails: Vec<String>,
+    #[serde(default)]
+    pub email_digest: bool,
 }
 
-#[derive(Default, Debug, Serialize, Deserialize, Apiv2Schema)]
+fn validation_errors<T: Validate>(object: &T) -> Vec<ErrorCode> {
+    let mut errors = Vec::new();
+    if let Err(validation_errors) = object.validate() {
+        ErrorCode::validate_errors(validation_errors, &mut errors);
+    }
+    errors
+}
+
+// The recipients must be valid addresses, and not too many of them.
+fn check_notify_emails(notify_emails: &[String], errors: &mut Vec<ErrorCode>) {
+    if notify_emails.len() > MAX_NOTIFY_EMAILS {
+        errors.push(ErrorCode {
+            error_code: "notify-emails-error".to_string(),
+            message: format!("A job cannot notify more than {} addresses.", MAX_NOTIFY_EMAILS),
+        });
+    }
+    for email in notify_emails {
+        if !validate_email(email.as_str()) {
+            errors.push(ErrorCode {
+                error_code: "notify-emails-error".to_string(),
+                message: format!("The address {} is not valid.", email),
+            });
+        }
+    }
+}
+
+fn checked(errors: Vec<ErrorCode>) -> Result<(), Vec<ErrorCode>> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+impl Job {
+    // Every rule the job breaks, the ones between fields included.
+    pub fn check(&self) -> Result<(), Vec<ErrorCode>> {
+        let mut errors = validation_errors(self);
+        if self.downloaded_size > self.total_size {
+            errors.push(ErrorCode {
+                error_code: "downloaded-size-error".to_string(),
+                message: "The downloaded size cannot be greater than the total size.".to_string(),
+            });
+        }
+        if let Some(other_expiration_date) = self.expiration_date {
+            if other_expiration_date <= self.creation_date {
+                errors.push(ErrorCode {
+                    error_code: "expiration-date-error".to_string(),
+                    message: "The expiration date must be after the creation date.".to_string(),
+                });
+            }
+        }
+        check_notify_emails(&self.notify_emails, &mut errors);
+        checked(errors)
+    }
+
+    // The version of the job as an entity tag, it changes with every update of the row.
+    pub fn etag(&self) -> String {
+        format!("\"{:x}\"", self.updated_at.timestamp_nanos())
+    }
+
+    // Whether an `If-Match` header value matches the current version of the job.
+    // Weak tags never match, `If-Match` uses the strong comparison.
+    pub fn matches_etag(&self, if_match: &str) -> bool {
+        let etag = self.etag();
+        if_match.trim() == "*" || if_match.split(',').any(|tag| tag.trim() == etag)
+    }
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Validate, Apiv2Schema)]
 pub struct NewJob {
+    #[validate(length(
+        min = 3,
+        max = 49,
+        code = "name-length-error",
+        message = "The name must have between 3 and 49 characters."
+    ))]
+    #[validate(regex(
+        path = "REGEX_FULL_WORD",
+        code = "name-format-error",
+        message = "The name may only have letters, spaces, dots, dashes and underscores."
+    ))]
+    // Unique whatever its case among the running, stalled and queued jobs, finished jobs may share it.
+    pub name: String,
+    #[validate(range(min = 0, code = "total-size-error", message = "The total size cannot be negative."))]
+    pub total_size: i32,
+    pub is_active: bool,
+    pub expected_checksum: Option<String>,
+    pub storage_backend: Option<String>,
+    // A directory of the local storage, so it is never a path.
+    #[validate(regex(
+        path = "REGEX_BUCKET",
+        code = "bucket-format-error",
+        message = "The bucket must have 3 to 63 lowercase letters, digits, dots and dashes, starting and ending with a letter or digit."
+    ))]
+    pub bucket: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[validate(length(
+        min = 1,
+        max = 255,
+        code = "external-ref-length-error",
+        message = "The external reference must have between 1 and 255 characters."
+    ))]
+    #[serde(default)]
+    pub external_ref: Option<String>,
+    #[serde(default)]
+    pub notify_emails: Vec<String>,
+    #[serde(default)]
+    pub email_digest: bool,
+}
+
+impl NewJob {
+    pub fn check(&self) -> Result<(), Vec<ErrorCode>> {
+        let mut errors = validation_errors(self);
+        check_notify_emails(&self.notify_emails, &mut errors);
+        checked(errors)
+    }
+}
+
+// The body of the deprecated `/feature/update` route, the job as the first version of the API served it.
+// Only the fields a client owns are read, the others it still sends are ignored.
+#[derive(Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct JobUpdateDTO {
+    pub id: Uuid,
     pub name: String,
     pub total_size: i32,
     pub is_active: bool,
+    #[serde(default)]
+    pub expiration_date: Option<NaiveDateTime>,
+}
+
+// Fields of a job the server owns, a client can never set them.
+pub const SERVER_OWNED_FIELDS: [&str; 19] = [
+    "id",
+    "downloaded_size",
+    "percent_downloaded",
+    "status",
+    "creation_date",
+    "file_path",
+    "expected_checksum",
+    "checksum",
+    "file_name",
+    "storage_backend",
+    "bucket",
+    "updated_at",
+    "deleted_at",
+    "attempts",
+    "mirror",
+    "last_error",
+    "started_at",
+    "finished_at",
+    "progressed_at",
+];
+
+// The changes a client asks for on a job, read from an RFC 7396 merge patch.
+// Only the fields listed here may be changed, `None` leaves a field as it is.
+// The values are checked on the patched job, with the rules of `Job`.
+#[derive(Default, Debug)]
+pub struct JobPatch {
+    pub name: Option<String>,
+    pub total_size: Option<i32>,
+    pub is_active: Option<bool>,
+    pub expiration_date: Option<Option<NaiveDateTime>>,
+    pub tags: Option<Vec<String>>,
+    pub external_ref: Option<Option<String>>,
+    pub notify_emails: Option<Vec<String>>,
+    pub email_digest: Option<bool>,
+}
+
+fn patch_error(error_code: &str, message: String) -> ErrorCode {
+    ErrorCode {
+        error_code: error_code.to_string(),
+        message,
+    }
+}
+
+fn required_field<T: DeserializeOwned>(field: &str, value: &Value) -> Result<T, ErrorCode> {
+    if value.is_null() {
+        return Err(patch_error(
+            "required-field",
+            format!("The field `{}` cannot be removed.", field),
+        ));
+    }
+    serde_json::from_value(value.clone()).map_err(|e| {
+        patch_error("invalid-field", format!("The field `{}` is not valid: {}.", field, e))
+    })
+}
+
+fn nullable_field<T: DeserializeOwned>(field: &str, value: &Value) -> Result<Option<T>, ErrorCode> {
+    if value.is_null() {
+        return Ok(None);
+    }
+    required_field(field, value).map(Some)
+}
+
+impl JobPatch {
+    // Read a merge patch, every field that cannot be applied is reported, not only the first one.
+    pub fn from_merge_patch(patch: &Value) -> Result<JobPatch, Vec<ErrorCode>> {
+        let fields = match patch.as_object() {
+            Some(fields) => fields,
+            None => {
+                return Err(vec![patch_error(
+                    "invalid-patch",
+                    "The merge patch of a job must be a JSON object.".to_string(),
+                )])
+            }
+        };
+
+        let mut job_patch = JobPatch::default();
+        let mut errors = Vec::new();
+        for (field, value) in fields {
+            let result = match field.as_str() {
+                "name" => required_field(field, value).map(|v| job_patch.name = Some(v)),
+                "total_size" => required_field(field, value).map(|v| job_patch.total_size = Some(v)),
+                "is_active" => required_field(field, value).map(|v| job_patch.is_active = Some(v)),
+                "expiration_date" => {
+                    nullable_field(field, value).map(|v| job_patch.expiration_date = Some(v))
+                }
+                // removing the tags leaves the job without any.
+                "tags" => nullable_field(field, value).map(|v| job_patch.tags = Some(v.unwrap_or_default())),
+                "external_ref" => nullable_field(field, value).map(|v| job_patch.external_ref = Some(v)),
+                // removing the addresses stops the emails.
+                "notify_emails" => {
+                    nullable_field(field, value).map(|v| job_patch.notify_emails = Some(v.unwrap_or_default()))
+                }
+                "email_digest" => required_field(field, value).map(|v| job_patch.email_digest = Some(v)),
+                other if SERVER_OWNED_FIELDS.contains(&other) => Err(patch_error(
+                    "read-only-field",
+                    format!("The field `{}` is set by the server and cannot be changed.", field),
+                )),
+                _ => Err(patch_error(
+                    "unknown-field",
+                    format!("The field `{}` does not exist on a job.", field),
+                )),
+            };
+            if let Err(error) = result {
+                errors.push(error);
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(job_patch)
+        } else {
+            Err(errors)
+        }
+    }
+
+    // Read the body of a PUT replacing the job, the fields are read as in a merge patch so the ones set by the
+    // server are rejected the same way. `name`, `total_size` and `is_active` must be sent, the other fields a
+    // client owns are cleared when left out.
+    pub fn from_replacement(replacement: &Value) -> Result<JobPatch, Vec<ErrorCode>> {
+        let missing: Vec<ErrorCode> = ["name", "total_size", "is_active"]
+            .iter()
+            .filter(|field| replacement.as_object().is_some_and(|fields| !fields.contains_key(**field)))
+            .map(|field| patch_error("required-field", format!("The field `{}` is required.", field)))
+            .collect();
+        let job_patch = match JobPatch::from_merge_patch(replacement) {
+            Ok(job_patch) if missing.is_empty() => job_patch,
+            Ok(_) => return Err(missing),
+            Err(mut errors) => {
+                errors.extend(missing);
+                return Err(errors);
+            }
+        };
+
+        Ok(JobPatch {
+            expiration_date: Some(job_patch.expiration_date.flatten()),
+            tags: Some(job_patch.tags.unwrap_or_default()),
+            external_ref: Some(job_patch.external_ref.flatten()),
+            notify_emails: Some(job_patch.notify_emails.unwrap_or_default()),
+            email_digest: Some(job_patch.email_digest.unwrap_or_default()),
+            ..job_patch
+        })
+    }
+
+    // A patch replacing the fields a client of the deprecated update route owns, the fields added since are
+    // left as they are.
+    pub fn replacing(job_update_dto: &JobUpdateDTO) -> JobPatch {
+        JobPatch {
+            name: Some(job_update_dto.name.clone()),
+            total_size: Some(job_update_dto.total_size),
+            is_active: Some(job_update_dto.is_active),
+            expiration_date: Some(job_update_dto.expiration_date),
+            ..JobPatch::default()
+        }
+    }
+
+    // A patch bringing the job of an external reference in line with a creation request, the storage and
+    // the expected checksum of a job are fixed when it is created so they are left as they are.
+    pub fn upserting(new_job: &NewJob) -> JobPatch {
+        JobPatch {
+            name: Some(new_job.name.clone()),
+            total_size: Some(new_job.total_size),
+            is_active: Some(new_job.is_active),
+            expiration_date: None,
+            tags: Some(new_job.tags.clone()),
+            external_ref: None,
+            notify_emails: Some(new_job.notify_emails.clone()),
+            email_digest: Some(new_job.email_digest),
+        }
+    }
+
+    pub fn apply(self, found_job: &mut Job) {
+        if let Some(other_name) = self.name {
+            found_job.name = other_name;
+        }
+        if let Some(other_total_size) = self.total_size {
+            found_job.total_size = other_total_size;
+        }
+        if let Some(other_is_active) = self.is_active {
+            found_job.is_active = other_is_active;
+        }
+        if let Some(other_expiration_date) = self.expiration_date {
+            found_job.expiration_date = other_expiration_date;
+        }
+        if let Some(other_tags) = self.tags {
+            found_job.tags = other_tags;
+        }
+        if let Some(other_external_ref) = self.external_ref {
+            found_job.external_ref = other_external_ref;
+        }
+        if let Some(other_notify_emails) = self.notify_emails {
+            found_job.notify_emails = other_notify_emails;
+        }
+        if let Some(other_email_digest) = self.email_digest {
+            found_job.email_digest = other_email_digest;
+        }
+    }
 }
 
 #[derive(Default, Debug, Serialize, Deserialize, Apiv2Schema)]
 pub struct JobInfo {
     pub name: String,
+    pub status: String,
     pub downloaded_size: i32,
     pub remaining_size: i32,
+    // bytes per second, over the last seconds of the current attempt and over all the attempts.
+    pub current_speed: Option<f64>,
+    pub average_speed: Option<f64>,
+    pub eta_seconds: Option<i64>,
+    pub started_at: Option<NaiveDateTime>,
+    pub finished_at: Option<NaiveDateTime>,
+    pub active_seconds: i64,
+    pub attempts: i32,
+    pub mirror: Option<String>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobStatus {
+    Active,
+    Queued,
+    Completed,
+    Failed,
+    Expired,
+    Missing,
+    Corrupted,
+    Stalled,
 }
 
+impl JobStatus {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Self::Active => "Active",
+            Self::Queued => "Queued",
+            Self::Completed => "Completed",
+            Self::Failed => "Failed",
+            Self::Expired => "Expired",
+            Self::Missing => "Missing",
+            Self::Corrupted => "Corrupted",
+            Self::Stalled => "Stalled",
+        }
+    }
+
+    pub fn from_name(status: &str) -> Option<JobStatus> {
+        match status {
+            "Active" => Some(Self::Active),
+            "Queued" => Some(Self::Queued),
+            "Completed" => Some(Self::Completed),
+            "Failed" => Some(Self::Failed),
+            "Expired" => Some(Self::Expired),
+            "Missing" => Some(Self::Missing),
+            "Corrupted" => Some(Self::Corrupted),
+            "Stalled" => Some(Self::Stalled),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn merge_patch_sets_and_removes_fields() {
+        let job_patch = JobPatch::from_merge_patch(&json!({
+            "name": "nightly backup",
+            "is_active": false,
+            "expiration_date": null,
+            "external_ref": null,
+        }))
+        .unwrap();
+
+        assert_eq!(job_patch.name.as_deref(), Some("nightly backup"));
+        assert_eq!(job_patch.is_active, Some(false));
+        assert_eq!(job_patch.total_size, None);
+        assert_eq!(job_patch.expiration_date, Some(None));
+        assert_eq!(job_patch.external_ref, Some(None));
+    }
+
+    #[test]
+    fn merge_patch_reports_every_rejected_field() {
+        let errors = JobPatch::from_merge_patch(&json!({
+            "id": "5a4bd4b4-5c4f-4f4c-9d45-7d5d1a2b3c4d",
+            "creation_date": "2022-01-01T00:00:00",
+            "name": null,
+            "total_size": "big",
+            "color": "red",
+        }))
+        .unwrap_err();
+        let codes: Vec<&str> = errors.iter().map(|e| e.error_code.as_str()).collect();
+
+        assert_eq!(
+            codes,
+            vec!["unknown-field", "read-only-field", "read-only-field", "required-field", "invalid-field"]
+        );
+    }
+
+    #[test]
+    fn replacement_clears_the_fields_left_out() {
+        let job_patch = JobPatch::from_replacement(&json!({
+            "name": "nightly backup",
+            "total_size": 20,
+            "is_active": true,
+            "tags": ["db"],
+        }))
+        .unwrap();
+
+        assert_eq!(job_patch.name.as_deref(), Some("nightly backup"));
+        assert_eq!(job_patch.tags, Some(vec!["db".to_string()]));
+        assert_eq!(job_patch.expiration_date, Some(None));
+        assert_eq!(job_patch.external_ref, Some(None));
+        assert_eq!(job_patch.notify_emails, Some(Vec::new()));
+        assert_eq!(job_patch.email_digest, Some(false));
+    }
+
+    #[test]
+    fn replacement_rejects_the_server_owned_fields() {
+        let errors = JobPatch::from_replacement(&json!({
+            "id": "5a4bd4b4-5c4f-4f4c-9d45-7d5d1a2b3c4d",
+            "status": "Completed",
+            "name": "nightly backup",
+            "is_active": true,
+        }))
+        .unwrap_err();
+        let codes: Vec<&str> = errors.iter().map(|e| e.error_code.as_str()).collect();
+
+        assert_eq!(codes, vec!["read-only-field", "read-only-field", "required-field"]);
+        assert!(errors[2].message.contains("total_size"));
+    }
+
+    #[test]
+    fn deprecated_update_takes_the_baseline_body() {
+        let job_update_dto: JobUpdateDTO = serde_json::from_value(json!({
+            "id": "5a4bd4b4-5c4f-4f4c-9d45-7d5d1a2b3c4d",
+            "name": "nightly backup",
+            "total_size": 20,
+            "downloaded_size": 5,
+            "percent_downloaded": 25,
+            "status": "Active",
+            "is_active": false,
+            "creation_date": "2022-01-01T00:00:00",
+            "expiration_date": null,
+        }))
+        .unwrap();
+        let job_patch = JobPatch::replacing(&job_update_dto);
+
+        assert_eq!(job_patch.name.as_deref(), Some("nightly backup"));
+        assert_eq!((job_patch.total_size, job_patch.is_active), (Some(20), Some(false)));
+        assert_eq!(job_patch.expiration_date, Some(None));
+        assert_eq!((job_patch.tags, job_patch.external_ref), (None, None));
+    }
+
+    fn sample_job() -> Job {
+        Job {
+            id: Uuid::nil(),
+            name: "report".to_string(),
+            total_size: 10,
+            downloaded_size: 0,
+            percent_downloaded: 0,
+            status: JobStatus::Active.get_name().to_string(),
+            is_active: true,
+            creation_date: NaiveDateTime::from_timestamp(1_641_980_000, 0),
+            expiration_date: None,
+            file_path: None,
+            expected_checksum: None,
+            checksum: None,
+            file_name: None,
+            storage_backend: "local".to_string(),
+            bucket: None,
+            updated_at: NaiveDateTime::from_timestamp(1_641_980_000, 500),
+            tags: Vec::new(),
+            external_ref: None,
+            deleted_at: None,
+            attempts: 0,
+            mirror: None,
+            last_error: None,
+            started_at: None,
+            finished_at: None,
+            progressed_at: None,
+            notify_emails: Vec::new(),
+            email_digest: false,
+        }
+    }
+
+    #[test]
+    fn if_match_uses_the_strong_comparison() {
+        let found_job = sample_job();
+        let etag = found_job.etag();
+
+        assert!(found_job.matches_etag(&etag));
+        assert!(found_job.matches_etag(&format!("\"0\", {}", etag)));
+        assert!(found_job.matches_etag("*"));
+        assert!(!found_job.matches_etag(&format!("W/{}", etag)));
+        assert!(!found_job.matches_etag("\"0\""));
+    }
+
+    #[test]
+    fn job_check_reports_every_broken_rule() {
+        let mut found_job = sample_job();
+        assert!(found_job.check().is_ok());
+
+        found_job.name = "x".to_string();
+        found_job.downloaded_size = 20;
+        found_job.percent_downloaded = 200;
+        found_job.expiration_date = Some(found_job.creation_date);
+        let mut codes: Vec<String> = found_job.check().unwrap_err().into_iter().map(|e| e.error_code).collect();
+        codes.sort();
+
+        assert_eq!(
+            codes,
+            vec!["downloaded-size-error", "expiration-date-error", "name-length-error", "percent-downloaded-error"]
+        );
+    }
+
+    #[test]
+    fn new_job_check_rejects_invalid_values() {
+        let new_job = NewJob {
+            name: "backup #1".to_string(),
+            total_size: -1,
+            ..Default::default()
+        };
+        let mut codes: Vec<String> = new_job.check().unwrap_err().into_iter().map(|e| e.error_code).collect();
+        codes.sort();
+
+        assert_eq!(codes, vec!["name-format-error", "total-size-error"]);
+    }
+
+    #[test]
+    fn notify_emails_must_be_addresses() {
+        let mut found_job = sample_job();
+        found_job.notify_emails = vec!["ops@example.com".to_string(), "not an address".to_string()];
+
+        let errors = found_job.check().unwrap_err();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].error_code, "notify-emails-error");
+    }
+}
@@ -1,2 +1,15 @@
+pub mod alert;
+pub mod audit;
+pub mod bulk;
+pub mod email;
+pub mod event;
+pub mod file;
+pub mod filter;
 pub mod general;
-pub mod job;
\ No newline at end of file
+pub mod idempotency;
+pub mod job;
+pub mod outbox;
+pub mod progress;
+pub mod reconcile;
+pub mod retention;
+pub mod webhook;
@@ -0,0 +1,105 @@
+use chrono::NaiveDateTime;
+use diesel::{AsChangeset, Insertable, Queryable};
+use paperclip::actix::Apiv2Schema;
+use serde::Serialize;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::model::event::JobEventKind;
+use crate::schema::{outbox_event, outbox_publication};
+
+// The sinks the relay publishes the outbox to, each of them gets every event at least once.
+pub const SINK_WEBHOOKS: &str = "webhooks";
+pub const SINK_EVENTS: &str = "events";
+pub const SINK_ALERTS: &str = "alerts";
+// The alert webhook is a sink of its own, a failed post leaves the event to be posted again.
+pub const SINK_ALERT_WEBHOOK: &str = "alert_webhook";
+pub const SINK_EMAILS: &str = "emails";
+// An event is purged once every one of them is done with it.
+pub const OUTBOX_SINKS: [&str; 5] = [SINK_WEBHOOKS, SINK_EVENTS, SINK_ALERTS, SINK_ALERT_WEBHOOK, SINK_EMAILS];
+
+// What became of an event in a sink, a failed event is published again until it is dead.
+pub const PUBLICATION_PUBLISHED: &str = "published";
+pub const PUBLICATION_FAILED: &str = "failed";
+pub const PUBLICATION_DEAD: &str = "dead";
+
+// A domain event written in the transaction of the change, the relay publishes it once it is committed.
+// The payload holds the webhook `event_type`, `occurred_at`, the `job` after the change and the `details`.
+#[derive(Debug, Clone, Serialize, Queryable, Apiv2Schema)]
+pub struct OutboxEvent {
+    pub id: i64,
+    pub name: String,
+    pub job_id: Option<Uuid>,
+    pub payload: Value,
+    pub creation_date: NaiveDateTime,
+}
+
+impl OutboxEvent {
+    pub fn event_type(&self) -> Option<&str> {
+        self.payload.get("event_type").and_then(Value::as_str)
+    }
+
+    pub fn details(&self) -> &Value {
+        self.payload.get("details").unwrap_or(&Value::Null)
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "outbox_event"]
+pub struct NewOutboxEvent {
+    pub name: String,
+    pub job_id: Option<Uuid>,
+    pub payload: Value,
+    pub creation_date: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable, AsChangeset)]
+#[table_name = "outbox_publication"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct NewOutboxPublication {
+    pub event_id: i64,
+    pub sink: String,
+    pub status: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: Option<NaiveDateTime>,
+    pub published_at: Option<NaiveDateTime>,
+}
+
+// The domain event of a job event, `JobCreated` say, a status change is named after the new status, `JobCompleted`.
+pub fn domain_event_name(kind: JobEventKind, details: &Value) -> String {
+    let name = match (kind, details.get("to").and_then(Value::as_str)) {
+        (JobEventKind::StatusChanged, Some(to)) => to.to_string(),
+        (kind, _) => kind.get_name().to_string(),
+    };
+    let camel_case: String = name
+        .split('_')
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut letters = word.chars();
+            match letters.next() {
+                Some(first) => first.to_uppercase().chain(letters.flat_map(char::to_lowercase)).collect(),
+                None => String::new(),
+            }
+        })
+        .collect();
+    format!("Job{}", camel_case)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn domain_event_name_is_camel_case() {
+        assert_eq!(domain_event_name(JobEventKind::Created, &json!({})), "JobCreated");
+        assert_eq!(domain_event_name(JobEventKind::Retried, &json!({ "reason": "stalled" })), "JobRetried");
+        assert_eq!(
+            domain_event_name(JobEventKind::StatusChanged, &json!({ "from": "Active", "to": "Completed" })),
+            "JobCompleted"
+        );
+        assert_eq!(domain_event_name(JobEventKind::StatusChanged, &json!({})), "JobStatusChanged");
+    }
+}
@@ -0,0 +1,72 @@
+use chrono::NaiveDateTime;
+use diesel::{Insertable, Queryable};
+use paperclip::actix::Apiv2Schema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::schema::job_progress;
+
+// The downloaded size of a job at some point of one of its attempts.
+#[derive(Debug, Clone, Serialize, Queryable, Apiv2Schema)]
+pub struct JobProgress {
+    pub id: i64,
+    pub job_id: Uuid,
+    pub attempt: i32,
+    pub downloaded_size: i32,
+    pub mirror: Option<String>,
+    pub sampled_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "job_progress"]
+pub struct NewJobProgress {
+    pub job_id: Uuid,
+    pub attempt: i32,
+    pub downloaded_size: i32,
+    pub mirror: Option<String>,
+    pub sampled_at: NaiveDateTime,
+}
+
+// The speeds of a download in bytes per second, read from its samples.
+#[derive(Debug, Default, PartialEq)]
+pub struct DownloadRates {
+    pub current_speed: Option<f64>,
+    pub average_speed: Option<f64>,
+    pub active_seconds: i64,
+}
+
+// A point of the progress series of a job, `speed` is in bytes per second since the point before it in the same attempt.
+#[derive(Debug, Clone, PartialEq, Serialize, Apiv2Schema)]
+pub struct ProgressPoint {
+    pub sampled_at: NaiveDateTime,
+    pub attempt: i32,
+    pub downloaded_size: i32,
+    pub mirror: Option<String>,
+    pub speed: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Apiv2Schema)]
+pub struct ProgressSeriesDTO {
+    pub job_id: Uuid,
+    // the samples are kept one per step, the last of it, when a step is given or the series is too long.
+    pub step_seconds: Option<i64>,
+    pub points: Vec<ProgressPoint>,
+}
+
+#[derive(Default, Deserialize, Apiv2Schema, Debug)]
+pub struct ProgressSeriesQuery {
+    pub since: Option<NaiveDateTime>,
+    pub until: Option<NaiveDateTime>,
+    pub step_seconds: Option<i64>,
+}
+
+// What the downloader of a job reports: how much of it is downloaded, from which mirror, and whether
+// a new attempt starts with the report or the attempt ended on an error.
+#[derive(Default, Deserialize, Apiv2Schema, Debug)]
+pub struct ProgressReportDTO {
+    pub downloaded_size: i32,
+    pub mirror: Option<String>,
+    #[serde(default)]
+    pub new_attempt: bool,
+    pub error: Option<String>,
+}
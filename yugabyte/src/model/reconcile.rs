@@ -0,0 +1,15 @@
+use paperclip::actix::Apiv2Schema;
+use serde::Serialize;
+use uuid::Uuid;
+
+#[derive(Default, Serialize, Apiv2Schema, Debug)]
+pub struct ReconcileReport {
+    pub checked: i64,
+    pub healthy: i64,
+    pub missing: Vec<Uuid>,
+    pub corrupted: Vec<Uuid>,
+    pub requeued: Vec<Uuid>,
+    // Jobs whose backend could not be reached or that changed while checked, they are left untouched.
+    pub skipped: Vec<Uuid>,
+    pub orphaned_files: Vec<String>,
+}
@@ -0,0 +1,30 @@
+use paperclip::actix::Apiv2Schema;
+use serde::Serialize;
+use uuid::Uuid;
+
+// Each rule is off when it is not set.
+#[derive(Default, Serialize, Apiv2Schema, Debug, Clone)]
+pub struct RetentionPolicy {
+    // Drop the files of jobs completed more than this many days ago, the jobs stay as Expired.
+    pub completed_files_days: Option<i64>,
+    // Delete jobs failed more than this many days ago.
+    pub failed_jobs_days: Option<i64>,
+    // Delete the finished jobs of a name beyond the newest N.
+    pub keep_last_per_name: Option<i64>,
+}
+
+#[derive(Serialize, Apiv2Schema, Debug)]
+pub struct RetainedJob {
+    pub id: Uuid,
+    pub name: String,
+    pub file_path: Option<String>,
+    pub rule: String,
+}
+
+#[derive(Default, Serialize, Apiv2Schema, Debug)]
+pub struct RetentionReport {
+    pub dry_run: bool,
+    pub policy: RetentionPolicy,
+    pub expired_files: Vec<RetainedJob>,
+    pub purged_jobs: Vec<RetainedJob>,
+}
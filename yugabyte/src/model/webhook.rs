@@ -0,0 +1,142 @@
+use chrono::NaiveDateTime;
+use diesel::{Insertable, Queryable};
+use paperclip::actix::Apiv2Schema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::errors::ErrorCode;
+use crate::model::event::JobEventKind;
+use crate::schema::{webhook_delivery, webhook_subscription};
+
+pub const DELIVERY_PENDING: &str = "pending";
+pub const DELIVERY_DELIVERED: &str = "delivered";
+pub const DELIVERY_FAILED: &str = "failed";
+
+// Every event of a job a subscription may ask for, `*` asks for all of them.
+pub const ALL_EVENT_TYPES: &str = "*";
+pub const WEBHOOK_EVENT_TYPES: [&str; 16] = [
+    "job.created",
+    "job.updated",
+    "job.activated",
+    "job.deactivated",
+    "job.retried",
+    "job.deleted",
+    "job.restored",
+    "job.error",
+    "job.active",
+    "job.queued",
+    "job.completed",
+    "job.failed",
+    "job.expired",
+    "job.missing",
+    "job.corrupted",
+    "job.stalled",
+];
+
+// The webhook event of a job event, a status change is named after the new status, `job.completed` say.
+pub fn webhook_event_type(kind: JobEventKind, details: &Value) -> String {
+    match (kind, details.get("to").and_then(Value::as_str)) {
+        (JobEventKind::StatusChanged, Some(to)) => format!("job.{}", to.to_lowercase()),
+        (kind, _) => format!("job.{}", kind.get_name()),
+    }
+}
+
+// Where to post the events of the jobs, the secret signs them and is never shown again.
+#[derive(Debug, Clone, Serialize, Queryable, Apiv2Schema)]
+pub struct WebhookSubscription {
+    pub id: Uuid,
+    pub url: String,
+    pub event_types: Vec<String>,
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub creation_date: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "webhook_subscription"]
+pub struct NewWebhookSubscription {
+    pub id: Uuid,
+    pub url: String,
+    pub event_types: Vec<String>,
+    pub secret: String,
+}
+
+#[derive(Debug, Deserialize, Validate, Apiv2Schema)]
+pub struct WebhookSubscriptionDTO {
+    pub url: String,
+    #[validate(length(min = 1, code = "event-types-error", message = "At least one event type is needed."))]
+    pub event_types: Vec<String>,
+    #[validate(length(
+        min = 16,
+        max = 255,
+        code = "webhook-secret-error",
+        message = "The secret must have between 16 and 255 characters."
+    ))]
+    pub secret: String,
+}
+
+impl WebhookSubscriptionDTO {
+    pub fn check(&self) -> Result<(), Vec<ErrorCode>> {
+        let mut errors = Vec::new();
+        if let Err(validation_errors) = self.validate() {
+            ErrorCode::validate_errors(validation_errors, &mut errors);
+        }
+        let address = self.url.trim_start_matches("http://").trim_start_matches("https://");
+        if address.len() == self.url.len() || address.is_empty() {
+            errors.push(ErrorCode {
+                error_code: "webhook-url-error".to_string(),
+                message: "The URL must be an absolute http or https URL.".to_string(),
+            });
+        }
+        for event_type in &self.event_types {
+            if event_type != ALL_EVENT_TYPES && !WEBHOOK_EVENT_TYPES.contains(&event_type.as_str()) {
+                errors.push(ErrorCode {
+                    error_code: "event-types-error".to_string(),
+                    message: format!("The event type {} is unknown.", event_type),
+                });
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+// One event posted to one subscription, with how its attempts went.
+#[derive(Debug, Clone, Serialize, Queryable, Apiv2Schema)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub subscription_id: Uuid,
+    pub event_type: String,
+    pub job_id: Option<Uuid>,
+    pub payload: Value,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: NaiveDateTime,
+    pub last_status_code: Option<i32>,
+    pub last_error: Option<String>,
+    pub creation_date: NaiveDateTime,
+    pub delivered_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "webhook_delivery"]
+pub struct NewWebhookDelivery {
+    pub id: Uuid,
+    pub subscription_id: Uuid,
+    pub event_type: String,
+    pub job_id: Option<Uuid>,
+    pub payload: Value,
+    pub next_attempt_at: NaiveDateTime,
+    pub creation_date: NaiveDateTime,
+}
+
+#[derive(Default, Deserialize, Apiv2Schema, Debug)]
+pub struct DeliveryFilterDTO {
+    // `pending`, `delivered` or `failed`.
+    pub status: Option<String>,
+}
@@ -1,3 +1,49 @@
+table! {
+    audit_log (id) {
+        id -> Int8,
+        creation_date -> Timestamp,
+        request_id -> Varchar,
+        principal -> Varchar,
+        source_ip -> Nullable<Varchar>,
+        method -> Varchar,
+        path -> Varchar,
+        action -> Varchar,
+        job_id -> Nullable<Uuid>,
+        before -> Nullable<Jsonb>,
+        after -> Nullable<Jsonb>,
+        status_code -> Int4,
+        result -> Varchar,
+    }
+}
+
+table! {
+    email_notification (id) {
+        id -> Uuid,
+        job_id -> Nullable<Uuid>,
+        recipient -> Varchar,
+        event_type -> Varchar,
+        subject -> Text,
+        body -> Text,
+        digest -> Bool,
+        status -> Varchar,
+        attempts -> Int4,
+        next_attempt_at -> Timestamp,
+        last_error -> Nullable<Text>,
+        creation_date -> Timestamp,
+        sent_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    idempotency_key (key, endpoint) {
+        key -> Varchar,
+        endpoint -> Varchar,
+        request_hash -> Varchar,
+        response -> Nullable<Text>,
+        creation_date -> Timestamp,
+    }
+}
+
 table! {
     job (id) {
         id -> Uuid,
@@ -9,5 +55,113 @@ table! {
         is_active -> Bool,
         creation_date -> Timestamp,
         expiration_date -> Nullable<Timestamp>,
+        file_path -> Nullable<Varchar>,
+        expected_checksum -> Nullable<Varchar>,
+        checksum -> Nullable<Varchar>,
+        file_name -> Nullable<Varchar>,
+        storage_backend -> Varchar,
+        bucket -> Nullable<Varchar>,
+        updated_at -> Timestamp,
+        tags -> Array<Text>,
+        external_ref -> Nullable<Varchar>,
+        deleted_at -> Nullable<Timestamp>,
+        attempts -> Int4,
+        mirror -> Nullable<Varchar>,
+        last_error -> Nullable<Text>,
+        started_at -> Nullable<Timestamp>,
+        finished_at -> Nullable<Timestamp>,
+        progressed_at -> Nullable<Timestamp>,
+        notify_emails -> Array<Text>,
+        email_digest -> Bool,
+    }
+}
+
+table! {
+    job_event (id) {
+        id -> Int8,
+        job_id -> Uuid,
+        kind -> Varchar,
+        actor -> Varchar,
+        details -> Jsonb,
+        creation_date -> Timestamp,
+    }
+}
+
+table! {
+    job_progress (id) {
+        id -> Int8,
+        job_id -> Uuid,
+        attempt -> Int4,
+        downloaded_size -> Int4,
+        mirror -> Nullable<Varchar>,
+        sampled_at -> Timestamp,
+    }
+}
+
+table! {
+    outbox_event (id) {
+        id -> Int8,
+        name -> Varchar,
+        job_id -> Nullable<Uuid>,
+        payload -> Jsonb,
+        creation_date -> Timestamp,
+    }
+}
+
+table! {
+    outbox_publication (event_id, sink) {
+        event_id -> Int8,
+        sink -> Varchar,
+        status -> Varchar,
+        attempts -> Int4,
+        last_error -> Nullable<Varchar>,
+        next_attempt_at -> Nullable<Timestamp>,
+        published_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    webhook_delivery (id) {
+        id -> Uuid,
+        subscription_id -> Uuid,
+        event_type -> Varchar,
+        job_id -> Nullable<Uuid>,
+        payload -> Jsonb,
+        status -> Varchar,
+        attempts -> Int4,
+        next_attempt_at -> Timestamp,
+        last_status_code -> Nullable<Int4>,
+        last_error -> Nullable<Text>,
+        creation_date -> Timestamp,
+        delivered_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    webhook_subscription (id) {
+        id -> Uuid,
+        url -> Varchar,
+        event_types -> Array<Text>,
+        secret -> Varchar,
+        creation_date -> Timestamp,
     }
 }
+
+joinable!(email_notification -> job (job_id));
+joinable!(job_event -> job (job_id));
+joinable!(job_progress -> job (job_id));
+joinable!(outbox_publication -> outbox_event (event_id));
+joinable!(webhook_delivery -> webhook_subscription (subscription_id));
+
+allow_tables_to_appear_in_same_query!(
+    audit_log,
+    email_notification,
+    idempotency_key,
+    job,
+    job_event,
+    job_progress,
+    outbox_event,
+    outbox_publication,
+    webhook_delivery,
+    webhook_subscription,
+);
@@ -0,0 +1,100 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::errors::Error;
+use crate::storage::StorageBackend;
+
+// Keeps the objects under a root directory, buckets are sub directories.
+pub struct LocalStorage {
+    pub root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: PathBuf) -> LocalStorage {
+        LocalStorage { root }
+    }
+
+    // The path of the object under the root, a bucket or key that would lead out of it is refused.
+    fn object_path(&self, bucket: Option<&str>, key: &str) -> Result<PathBuf, Error> {
+        let mut object_path = self.root.clone();
+        for component in bucket.into_iter().chain(key.split('/')) {
+            if !is_plain_component(component) {
+                return Err(Error::BadRequest(format!("The object {} is outside of the storage.", key)));
+            }
+            object_path.push(component);
+        }
+        Ok(object_path)
+    }
+}
+
+// A file or directory name that stays where it is joined: not empty, `.` or `..`, and without a separator.
+fn is_plain_component(component: &str) -> bool {
+    !component.is_empty()
+        && component != "."
+        && component != ".."
+        && !component.contains(&['/', '\\', '\0'][..])
+        && !Path::new(component).is_absolute()
+}
+
+fn io_error(e: io::Error) -> Error {
+    Error::InternalServerError(e.to_string())
+}
+
+impl StorageBackend for LocalStorage {
+    fn put_file(&self, bucket: Option<&str>, key: &str, source: &Path) -> Result<(), Error> {
+        let object_path = self.object_path(bucket, key)?;
+        if let Some(parent) = object_path.parent() {
+            fs::create_dir_all(parent).map_err(io_error)?;
+        }
+        if fs::rename(source, &object_path).is_err() {
+            // The source may live on another file system, fall back to copying it.
+            let partial_path = object_path.with_extension("partial");
+            fs::copy(source, &partial_path).map_err(io_error)?;
+            fs::rename(&partial_path, &object_path).map_err(io_error)?;
+            fs::remove_file(source).map_err(io_error)?;
+        }
+        Ok(())
+    }
+
+    fn object_size(&self, bucket: Option<&str>, key: &str) -> Result<Option<u64>, Error> {
+        match fs::metadata(self.object_path(bucket, key)?) {
+            Ok(metadata) if metadata.is_file() => Ok(Some(metadata.len())),
+            Ok(_) => Ok(None),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(io_error(e)),
+        }
+    }
+
+    fn delete_object(&self, bucket: Option<&str>, key: &str) -> Result<(), Error> {
+        match fs::remove_file(self.object_path(bucket, key)?) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(io_error(e)),
+            _ => Ok(()),
+        }
+    }
+
+    fn local_path(&self, bucket: Option<&str>, key: &str) -> Option<PathBuf> {
+        self.object_path(bucket, key).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn object_path_stays_under_the_root() {
+        let storage = LocalStorage::new(PathBuf::from("/srv/downloads"));
+
+        assert_eq!(
+            storage.object_path(Some("backups"), "cas/ab/ab12").unwrap(),
+            PathBuf::from("/srv/downloads/backups/cas/ab/ab12")
+        );
+        assert!(storage.object_path(Some("../../etc"), "passwd").is_err());
+        assert!(storage.object_path(Some("/tmp"), "file").is_err());
+        assert!(storage.object_path(None, "../secret").is_err());
+        assert!(storage.object_path(None, "/etc/passwd").is_err());
+        assert!(storage.object_path(None, "cas//ab").is_err());
+        assert!(storage.object_path(Some("a\\..\\b"), "file").is_err());
+    }
+}
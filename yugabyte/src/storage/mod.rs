@@ -0,0 +1,92 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+use crate::errors::Error;
+use crate::storage::local::LocalStorage;
+use crate::storage::s3::S3Storage;
+
+pub mod local;
+pub mod s3;
+
+pub const LOCAL_BACKEND: &str = "local";
+pub const S3_BACKEND: &str = "s3";
+
+// A place where the files of finished jobs are kept.
+// Objects are addressed by an optional bucket and a key, e.g. `cas/ab/ab12...`.
+pub trait StorageBackend: Send + Sync {
+    // Move the file into the backend under the key, the source file is consumed.
+    fn put_file(&self, bucket: Option<&str>, key: &str, source: &Path) -> Result<(), Error>;
+
+    // Size of the object, None if the backend does not hold it.
+    fn object_size(&self, bucket: Option<&str>, key: &str) -> Result<Option<u64>, Error>;
+
+    fn delete_object(&self, bucket: Option<&str>, key: &str) -> Result<(), Error>;
+
+    // Path of the object if the backend keeps it on this machine.
+    fn local_path(&self, _bucket: Option<&str>, _key: &str) -> Option<PathBuf> {
+        None
+    }
+
+    // Time-limited URL to fetch the object straight from the backend.
+    fn presigned_url(&self, _bucket: Option<&str>, _key: &str, _ttl_seconds: i64) -> Option<String> {
+        None
+    }
+}
+
+// The configured backends, jobs pick one by name or fall back to the default.
+pub struct StorageConfig {
+    pub default_backend: String,
+    pub default_bucket: Option<String>,
+    pub local: LocalStorage,
+    pub s3: Option<S3Storage>,
+}
+
+impl StorageConfig {
+    pub fn backend(&self, name: &str) -> Result<&dyn StorageBackend, Error> {
+        match name {
+            LOCAL_BACKEND => Ok(&self.local),
+            S3_BACKEND => match &self.s3 {
+                Some(s3) => Ok(s3),
+                None => Err(Error::BadRequest("The s3 storage backend is not configured.".to_string())),
+            },
+            _ => Err(Error::BadRequest(format!("{} is not a storage backend.", name))),
+        }
+    }
+
+    // Bucket to use for the job, the default bucket only applies to the default backend.
+    pub fn bucket<'a>(&'a self, name: &str, bucket: Option<&'a str>) -> Option<&'a str> {
+        match bucket {
+            Some(bucket) => Some(bucket),
+            None if name == self.default_backend => self.default_bucket.as_deref(),
+            None => None,
+        }
+    }
+}
+
+impl Default for StorageConfig {
+    // Read the storage configuration from the environment.
+    fn default() -> Self {
+        let download_dir = env::var("DOWNLOAD_DIR").unwrap_or_else(|_| "downloads".to_string());
+        let s3 = env::var("S3_ENDPOINT").ok().map(|endpoint| {
+            let part_size = env::var("S3_PART_SIZE")
+                .ok()
+                .and_then(|part_size| part_size.parse().ok())
+                .unwrap_or(s3::DEFAULT_PART_SIZE)
+                .max(s3::MIN_PART_SIZE);
+            S3Storage::new(
+                endpoint,
+                env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+                env::var("S3_ACCESS_KEY").expect("S3_ACCESS_KEY must be set with S3_ENDPOINT"),
+                env::var("S3_SECRET_KEY").expect("S3_SECRET_KEY must be set with S3_ENDPOINT"),
+                part_size,
+            )
+        });
+
+        StorageConfig {
+            default_backend: env::var("STORAGE_BACKEND").unwrap_or_else(|_| LOCAL_BACKEND.to_string()),
+            default_bucket: env::var("STORAGE_BUCKET").ok(),
+            local: LocalStorage::new(PathBuf::from(download_dir)),
+            s3,
+        }
+    }
+}
@@ -0,0 +1,499 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac, NewMac};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use sha2::{Digest, Sha256};
+
+use crate::errors::Error;
+use crate::storage::StorageBackend;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+pub const DEFAULT_PART_SIZE: u64 = 8 * 1024 * 1024;
+
+// Everything but the unreserved characters is encoded, as Signature Version 4 expects.
+const QUERY_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'_').remove(b'.').remove(b'~');
+const PATH_ENCODE_SET: &AsciiSet = &QUERY_ENCODE_SET.remove(b'/');
+
+// Talks to S3 compatible object storage (AWS, MinIO, ...) with path-style requests.
+pub struct S3Storage {
+    endpoint: String,
+    host: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    part_size: u64,
+    agent: ureq::Agent,
+}
+
+fn hmac_sha256(key: &[u8], message: &str) -> Vec<u8> {
+    let mut mac = HmacSha256::new_varkey(key).expect("HMAC accepts keys of any size");
+    mac.update(message.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn sha256_hex(payload: &[u8]) -> String {
+    hex::encode(Sha256::digest(payload))
+}
+
+// Canonical query string, keys are sorted and everything is encoded.
+fn canonical_query(query: &BTreeMap<String, String>) -> String {
+    query
+        .iter()
+        .map(|(key, value)| {
+            format!(
+                "{}={}",
+                utf8_percent_encode(key, QUERY_ENCODE_SET),
+                utf8_percent_encode(value, QUERY_ENCODE_SET)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+// Sign the request with AWS Signature Version 4, the header names must be lowercase.
+#[allow(clippy::too_many_arguments)]
+fn sign_request(
+    secret_key: &str,
+    region: &str,
+    method: &str,
+    canonical_uri: &str,
+    query: &BTreeMap<String, String>,
+    headers: &BTreeMap<String, String>,
+    payload_hash: &str,
+    amz_date: &str,
+) -> String {
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect();
+    let signed_headers = headers.keys().cloned().collect::<Vec<_>>().join(";");
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method,
+        canonical_uri,
+        canonical_query(query),
+        canonical_headers,
+        signed_headers,
+        payload_hash
+    );
+
+    let date = &amz_date[..8];
+    let scope = format!("{}/{}/s3/aws4_request", date, region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        sha256_hex(canonical_request.as_bytes())
+    );
+
+    let date_key = hmac_sha256(format!("AWS4{}", secret_key).as_bytes(), date);
+    let region_key = hmac_sha256(&date_key, region);
+    let service_key = hmac_sha256(&region_key, "s3");
+    let signing_key = hmac_sha256(&service_key, "aws4_request");
+    hex::encode(hmac_sha256(&signing_key, &string_to_sign))
+}
+
+// Value of the first `<tag>` in the XML document.
+fn xml_value(body: &str, tag: &str) -> Option<String> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let start = body.find(&open)? + open.len();
+    let end = start + body[start..].find(&close)?;
+    Some(body[start..end].to_string())
+}
+
+fn http_error(e: Box<ureq::Error>) -> Error {
+    match *e {
+        ureq::Error::Status(code, response) => Error::HttpRequest(format!(
+            "s3 answered {}: {}",
+            code,
+            response.into_string().unwrap_or_default()
+        )),
+        ureq::Error::Transport(transport) => Error::HttpRequest(transport.to_string()),
+    }
+}
+
+impl S3Storage {
+    pub fn new(
+        endpoint: String,
+        region: String,
+        access_key: String,
+        secret_key: String,
+        part_size: u64,
+    ) -> S3Storage {
+        let endpoint = endpoint.trim_end_matches('/').to_string();
+        let host = endpoint
+            .split("://")
+            .last()
+            .unwrap_or_default()
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+
+        S3Storage {
+            endpoint,
+            host,
+            region,
+            access_key,
+            secret_key,
+            part_size,
+            agent: ureq::AgentBuilder::new().build(),
+        }
+    }
+
+    fn canonical_uri(bucket: &str, key: &str) -> String {
+        format!("/{}/{}", bucket, utf8_percent_encode(key, PATH_ENCODE_SET))
+    }
+
+    fn send(
+        &self,
+        method: &str,
+        bucket: &str,
+        key: &str,
+        query: &[(&str, &str)],
+        body: &[u8],
+    ) -> Result<ureq::Response, Box<ureq::Error>> {
+        let canonical_uri = Self::canonical_uri(bucket, key);
+        let query: BTreeMap<String, String> = query
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let payload_hash = sha256_hex(body);
+
+        let mut headers = BTreeMap::new();
+        headers.insert("host".to_string(), self.host.clone());
+        headers.insert("x-amz-content-sha256".to_string(), payload_hash.clone());
+        headers.insert("x-amz-date".to_string(), amz_date.clone());
+        let signature = sign_request(
+            &self.secret_key,
+            &self.region,
+            method,
+            &canonical_uri,
+            &query,
+            &headers,
+            &payload_hash,
+            &amz_date,
+        );
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}/{}/s3/aws4_request, SignedHeaders={}, Signature={}",
+            self.access_key,
+            &amz_date[..8],
+            self.region,
+            headers.keys().cloned().collect::<Vec<_>>().join(";"),
+            signature
+        );
+
+        let mut url = format!("{}{}", self.endpoint, canonical_uri);
+        if !query.is_empty() {
+            url = format!("{}?{}", url, canonical_query(&query));
+        }
+        let mut request = self.agent.request(method, &url);
+        for (name, value) in &headers {
+            request = request.set(name, value);
+        }
+        request.set("authorization", &authorization).send_bytes(body).map_err(Box::new)
+    }
+
+    fn upload_parts(&self, bucket: &str, key: &str, upload_id: &str, source: &Path) -> Result<(), Error> {
+        let mut file = File::open(source).map_err(|e| Error::InternalServerError(e.to_string()))?;
+        let mut parts = Vec::new();
+
+        // Step 1: upload the file part by part, an empty file still needs one part.
+        loop {
+            let mut part = Vec::new();
+            (&mut file)
+                .take(self.part_size)
+                .read_to_end(&mut part)
+                .map_err(|e| Error::InternalServerError(e.to_string()))?;
+            if part.is_empty() && !parts.is_empty() {
+                break;
+            }
+            let part_number = (parts.len() + 1).to_string();
+            let response = self
+                .send(
+                    "PUT",
+                    bucket,
+                    key,
+                    &[("partNumber", &part_number), ("uploadId", upload_id)],
+                    &part,
+                )
+                .map_err(http_error)?;
+            let etag = response
+                .header("etag")
+                .ok_or_else(|| Error::HttpRequest("s3 did not return the etag of the part".to_string()))?
+                .to_string();
+            parts.push(format!(
+                "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                part_number, etag
+            ));
+            if (part.len() as u64) < self.part_size {
+                break;
+            }
+        }
+
+        // Step 2: stitch the parts together, s3 may report a failure with a 200 status.
+        let body = format!(
+            "<CompleteMultipartUpload>{}</CompleteMultipartUpload>",
+            parts.join("")
+        );
+        let response = self
+            .send("POST", bucket, key, &[("uploadId", upload_id)], body.as_bytes())
+            .map_err(http_error)?
+            .into_string()
+            .map_err(|e| Error::HttpRequest(e.to_string()))?;
+        match xml_value(&response, "Message") {
+            Some(message) if response.contains("<Error>") => Err(Error::HttpRequest(message)),
+            _ => Ok(()),
+        }
+    }
+
+    fn bucket(bucket: Option<&str>) -> Result<&str, Error> {
+        bucket.ok_or_else(|| Error::BadRequest("The s3 storage backend needs a bucket.".to_string()))
+    }
+
+    fn presigned_url_at(&self, bucket: &str, key: &str, ttl_seconds: i64, now: DateTime<Utc>) -> String {
+        let canonical_uri = Self::canonical_uri(bucket, key);
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let credential = format!(
+            "{}/{}/{}/s3/aws4_request",
+            self.access_key,
+            &amz_date[..8],
+            self.region
+        );
+
+        let mut query = BTreeMap::new();
+        query.insert("X-Amz-Algorithm".to_string(), "AWS4-HMAC-SHA256".to_string());
+        query.insert("X-Amz-Credential".to_string(), credential);
+        query.insert("X-Amz-Date".to_string(), amz_date.clone());
+        query.insert("X-Amz-Expires".to_string(), ttl_seconds.to_string());
+        query.insert("X-Amz-SignedHeaders".to_string(), "host".to_string());
+        let mut headers = BTreeMap::new();
+        headers.insert("host".to_string(), self.host.clone());
+        let signature = sign_request(
+            &self.secret_key,
+            &self.region,
+            "GET",
+            &canonical_uri,
+            &query,
+            &headers,
+            "UNSIGNED-PAYLOAD",
+            &amz_date,
+        );
+
+        format!(
+            "{}{}?{}&X-Amz-Signature={}",
+            self.endpoint,
+            canonical_uri,
+            canonical_query(&query),
+            signature
+        )
+    }
+}
+
+impl StorageBackend for S3Storage {
+    fn put_file(&self, bucket: Option<&str>, key: &str, source: &Path) -> Result<(), Error> {
+        let bucket = Self::bucket(bucket)?;
+
+        // Step 1: start the multipart upload.
+        let response = self
+            .send("POST", bucket, key, &[("uploads", "")], &[])
+            .map_err(http_error)?
+            .into_string()
+            .map_err(|e| Error::HttpRequest(e.to_string()))?;
+        let upload_id = xml_value(&response, "UploadId")
+            .ok_or_else(|| Error::HttpRequest("s3 did not return an upload id".to_string()))?;
+
+        // Step 2: upload the parts, abort the upload so s3 does not keep the parts on failure.
+        if let Err(e) = self.upload_parts(bucket, key, &upload_id, source) {
+            let _ = self.send("DELETE", bucket, key, &[("uploadId", &upload_id)], &[]);
+            return Err(e);
+        }
+
+        // Step 3: the file now lives in the bucket.
+        std::fs::remove_file(source).map_err(|e| Error::InternalServerError(e.to_string()))
+    }
+
+    fn object_size(&self, bucket: Option<&str>, key: &str) -> Result<Option<u64>, Error> {
+        let bucket = Self::bucket(bucket)?;
+        match self.send("HEAD", bucket, key, &[], &[]) {
+            Ok(response) => Ok(response.header("content-length").and_then(|length| length.parse().ok())),
+            Err(e) if matches!(*e, ureq::Error::Status(404, _)) => Ok(None),
+            Err(e) => Err(http_error(e)),
+        }
+    }
+
+    fn delete_object(&self, bucket: Option<&str>, key: &str) -> Result<(), Error> {
+        let bucket = Self::bucket(bucket)?;
+        match self.send("DELETE", bucket, key, &[], &[]) {
+            Ok(_) => Ok(()),
+            Err(e) if matches!(*e, ureq::Error::Status(404, _)) => Ok(()),
+            Err(e) => Err(http_error(e)),
+        }
+    }
+
+    fn presigned_url(&self, bucket: Option<&str>, key: &str, ttl_seconds: i64) -> Option<String> {
+        let bucket = bucket?;
+        Some(self.presigned_url_at(bucket, key, ttl_seconds, Utc::now()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{mpsc, Mutex};
+    use std::thread;
+
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use chrono::TimeZone;
+    use uuid::Uuid;
+
+    use super::*;
+
+    const EMPTY_PAYLOAD_HASH: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    // Example request of the AWS Signature Version 4 documentation.
+    #[test]
+    fn sign_request_matches_aws_example() {
+        let mut headers = BTreeMap::new();
+        headers.insert("host".to_string(), "examplebucket.s3.amazonaws.com".to_string());
+        headers.insert("range".to_string(), "bytes=0-9".to_string());
+        headers.insert("x-amz-content-sha256".to_string(), EMPTY_PAYLOAD_HASH.to_string());
+        headers.insert("x-amz-date".to_string(), "20130524T000000Z".to_string());
+
+        let signature = sign_request(
+            "wJalrXUtnFEMI/K7MDENG/bPxRfiCYEXAMPLEKEY",
+            "us-east-1",
+            "GET",
+            "/test.txt",
+            &BTreeMap::new(),
+            &headers,
+            EMPTY_PAYLOAD_HASH,
+            "20130524T000000Z",
+        );
+        assert_eq!(signature, "f0e8bdb87c964420e857bd35b5d6ed310bd44f0170aba48dd91039c6036bdb41");
+    }
+
+    #[derive(Default)]
+    struct StandIn {
+        objects: HashMap<String, Vec<u8>>,
+        uploads: HashMap<String, HashMap<String, Vec<u8>>>,
+    }
+
+    // Just enough of the S3 API to run the multipart upload.
+    fn stand_in(req: HttpRequest, body: web::Bytes, state: web::Data<Mutex<StandIn>>) -> HttpResponse {
+        let mut state = state.lock().unwrap();
+        let path = req.path().to_string();
+        let query: HashMap<String, String> = web::Query::from_query(req.query_string())
+            .map(|query: web::Query<HashMap<String, String>>| query.into_inner())
+            .unwrap_or_default();
+        if req.headers().get("authorization").is_none() {
+            return HttpResponse::Forbidden().finish();
+        }
+
+        match (req.method().as_str(), query.get("uploadId")) {
+            ("POST", None) if query.contains_key("uploads") => {
+                let upload_id = Uuid::new_v4().to_string();
+                state.uploads.insert(upload_id.clone(), HashMap::new());
+                HttpResponse::Ok().body(format!(
+                    "<InitiateMultipartUploadResult><UploadId>{}</UploadId></InitiateMultipartUploadResult>",
+                    upload_id
+                ))
+            }
+            ("PUT", Some(upload_id)) => match state.uploads.get_mut(upload_id) {
+                Some(parts) => {
+                    let part_number = query["partNumber"].clone();
+                    parts.insert(part_number.clone(), body.to_vec());
+                    HttpResponse::Ok().header("ETag", format!("\"etag-{}\"", part_number)).finish()
+                }
+                None => HttpResponse::NotFound().finish(),
+            },
+            ("POST", Some(upload_id)) => match state.uploads.remove(upload_id) {
+                Some(parts) => {
+                    let body = String::from_utf8(body.to_vec()).unwrap();
+                    let mut object = Vec::new();
+                    for part_number in 1..=parts.len() {
+                        assert!(body.contains(&format!("<ETag>\"etag-{}\"</ETag>", part_number)));
+                        object.extend_from_slice(&parts[&part_number.to_string()]);
+                    }
+                    state.objects.insert(path, object);
+                    HttpResponse::Ok().body("<CompleteMultipartUploadResult></CompleteMultipartUploadResult>")
+                }
+                None => HttpResponse::NotFound().finish(),
+            },
+            ("HEAD", None) => match state.objects.get(&path) {
+                // actix drops the body of HEAD responses but keeps its length.
+                Some(object) => HttpResponse::Ok().body(object.clone()),
+                None => HttpResponse::NotFound().finish(),
+            },
+            ("DELETE", None) => match state.objects.remove(&path) {
+                Some(_) => HttpResponse::NoContent().finish(),
+                None => HttpResponse::NotFound().finish(),
+            },
+            _ => HttpResponse::BadRequest().finish(),
+        }
+    }
+
+    fn start_stand_in() -> String {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut system = actix_web::rt::System::new("s3-stand-in");
+            let state = web::Data::new(Mutex::new(StandIn::default()));
+            let server = HttpServer::new(move || {
+                App::new()
+                    .app_data(state.clone())
+                    .default_service(web::route().to(stand_in))
+            })
+            .workers(1)
+            .bind("127.0.0.1:0")
+            .unwrap();
+            sender.send(server.addrs()[0]).unwrap();
+            system.block_on(server.run()).unwrap();
+        });
+        format!("http://{}", receiver.recv().unwrap())
+    }
+
+    #[test]
+    fn multipart_upload_to_stand_in() {
+        let storage = S3Storage::new(
+            start_stand_in(),
+            "us-east-1".to_string(),
+            "access".to_string(),
+            "secret".to_string(),
+            8,
+        );
+        let source = std::env::temp_dir().join(format!("s3-{}", Uuid::new_v4()));
+        std::fs::write(&source, b"twenty three bytes long").unwrap();
+
+        storage.put_file(Some("jobs"), "cas/ab/abc", &source).unwrap();
+        assert!(!source.exists());
+        assert_eq!(storage.object_size(Some("jobs"), "cas/ab/abc").unwrap(), Some(23));
+        assert_eq!(storage.object_size(Some("jobs"), "cas/ab/other").unwrap(), None);
+
+        storage.delete_object(Some("jobs"), "cas/ab/abc").unwrap();
+        assert_eq!(storage.object_size(Some("jobs"), "cas/ab/abc").unwrap(), None);
+        assert!(storage.put_file(None, "cas/ab/abc", &source).is_err());
+    }
+
+    #[test]
+    fn presigned_url_is_scoped_to_the_object() {
+        let storage = S3Storage::new(
+            "http://127.0.0.1:9000/".to_string(),
+            "us-east-1".to_string(),
+            "access".to_string(),
+            "secret".to_string(),
+            DEFAULT_PART_SIZE,
+        );
+        let url = storage.presigned_url_at("jobs", "cas/ab/a b", 60, Utc.ymd(2022, 1, 10).and_hms(9, 0, 0));
+        assert!(url.starts_with("http://127.0.0.1:9000/jobs/cas/ab/a%20b?X-Amz-Algorithm=AWS4-HMAC-SHA256"));
+        assert!(url.contains("X-Amz-Credential=access%2F20220110%2Fus-east-1%2Fs3%2Faws4_request"));
+        assert!(url.contains("X-Amz-Expires=60&X-Amz-SignedHeaders=host&X-Amz-Signature="));
+    }
+}
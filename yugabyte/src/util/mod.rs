@@ -1,3 +1,4 @@
+pub mod signature;
 pub mod utils;
 
 
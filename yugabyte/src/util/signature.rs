@@ -0,0 +1,47 @@
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// The signed message binds the link to one job and one expiry instant.
+fn file_link_mac(key: &str, job_id: &Uuid, expires: i64) -> HmacSha256 {
+    let mut mac = HmacSha256::new_varkey(key.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(format!("{}:{}", job_id, expires).as_bytes());
+    mac
+}
+
+// Sign the download link of the job, returns the hex encoded signature.
+pub fn sign_file_link(key: &str, job_id: &Uuid, expires: i64) -> String {
+    hex::encode(file_link_mac(key, job_id, expires).finalize().into_bytes())
+}
+
+// Check the signature of the download link in constant time.
+pub fn verify_file_link(key: &str, job_id: &Uuid, expires: i64, signature: &str) -> bool {
+    match hex::decode(signature) {
+        Ok(signature) => file_link_mac(key, job_id, expires).verify(&signature).is_ok(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signed_link_is_verified() {
+        let job_id = Uuid::new_v4();
+        let signature = sign_file_link("secret", &job_id, 1_700_000_000);
+        assert!(verify_file_link("secret", &job_id, 1_700_000_000, &signature));
+    }
+
+    #[test]
+    fn tampered_link_is_rejected() {
+        let job_id = Uuid::new_v4();
+        let signature = sign_file_link("secret", &job_id, 1_700_000_000);
+        assert!(!verify_file_link("secret", &job_id, 1_700_000_001, &signature));
+        assert!(!verify_file_link("secret", &Uuid::new_v4(), 1_700_000_000, &signature));
+        assert!(!verify_file_link("other", &job_id, 1_700_000_000, &signature));
+        assert!(!verify_file_link("secret", &job_id, 1_700_000_000, "not-hex"));
+    }
+}
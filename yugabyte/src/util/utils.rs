@@ -21,4 +21,5 @@ pub fn expiration_date(
 lazy_static! {
     pub static ref REGEX_FULL_WORD: Regex = Regex::new(r"^[a-zA-Z ._-]*$").unwrap();   // examples: "abdelaziz", "abdelaziz said", "abdelaziz-said", "abdelaziz_said", "abdelaziz.said"
     pub static ref REGEX_WORD: Regex = Regex::new(r"^[a-zA-Z]+$").unwrap();   // examples: "abdelaziz"
+    pub static ref REGEX_BUCKET: Regex = Regex::new(r"^[a-z0-9][a-z0-9.-]{1,61}[a-z0-9]$").unwrap();   // examples: "backups", "eu-west.archive"
 }